@@ -0,0 +1,115 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::models::{Album, SmmsPicture};
+use crate::services::albums;
+use crate::services::d1::D1Client;
+use crate::services::mirror::{self, LocalMirror};
+
+/// 列出全部相册，按用户设置的顺序排列
+#[tauri::command]
+pub async fn list_albums(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+) -> Result<Vec<Album>, AppError> {
+    mirror::ensure_synced(&d1, &mirror).await?;
+    albums::list(&mirror)
+}
+
+/// 按相册内的顺序获取相册中的图片
+#[tauri::command]
+pub async fn list_album_pictures(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<SmmsPicture>, AppError> {
+    mirror::ensure_synced(&d1, &mirror).await?;
+    albums::pictures(&mirror, id, limit, offset)
+}
+
+/// 新建相册
+#[tauri::command]
+pub async fn create_album(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    name: String,
+) -> Result<Album, AppError> {
+    albums::create(&d1, &mirror, &name).await
+}
+
+/// 重命名相册
+#[tauri::command]
+pub async fn rename_album(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+    name: String,
+) -> Result<Album, AppError> {
+    albums::rename(&d1, &mirror, id, &name).await
+}
+
+/// 删除相册（不删除其中的图片）
+#[tauri::command]
+pub async fn delete_album(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+) -> Result<(), AppError> {
+    albums::delete(&d1, &mirror, id).await
+}
+
+/// 调整相册顺序，列出的相册按给定顺序排在最前面
+#[tauri::command]
+pub async fn reorder_albums(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    ids: Vec<i64>,
+) -> Result<Vec<Album>, AppError> {
+    albums::reorder(&d1, &mirror, &ids).await
+}
+
+/// 把图片加到相册末尾
+#[tauri::command]
+pub async fn add_pictures_to_album(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+    picture_ids: Vec<i64>,
+) -> Result<Album, AppError> {
+    albums::add_pictures(&d1, &mirror, id, &picture_ids).await
+}
+
+/// 从相册中移除图片
+#[tauri::command]
+pub async fn remove_pictures_from_album(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+    picture_ids: Vec<i64>,
+) -> Result<Album, AppError> {
+    albums::remove_pictures(&d1, &mirror, id, &picture_ids).await
+}
+
+/// 调整相册中图片的顺序，列出的图片按给定顺序排在最前面
+#[tauri::command]
+pub async fn reorder_album_pictures(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+    picture_ids: Vec<i64>,
+) -> Result<Album, AppError> {
+    albums::reorder_pictures(&d1, &mirror, id, &picture_ids).await
+}
+
+/// 设置相册封面，`picture_id` 为空时使用相册中的第一张图片
+#[tauri::command]
+pub async fn set_album_cover(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+    picture_id: Option<i64>,
+) -> Result<Album, AppError> {
+    albums::set_cover(&d1, &mirror, id, picture_id).await
+}
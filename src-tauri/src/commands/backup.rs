@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use tauri::State;
+
+use crate::error::AppError;
+use crate::models::{BackupFormat, BackupReport, RestoreReport};
+use crate::services::backup;
+use crate::services::d1::D1Client;
+use crate::services::mirror::{self, LocalMirror};
+
+/// 把数据库备份到本地文件，未指定格式时按扩展名判断（`.sql` 为 SQL 文本）
+#[tauri::command]
+pub async fn export_database_backup(
+    d1: State<'_, D1Client>,
+    save_path: String,
+    format: Option<BackupFormat>,
+) -> Result<BackupReport, AppError> {
+    let path = Path::new(&save_path);
+    let format = format.unwrap_or_else(|| BackupFormat::from_path(path));
+    backup::dump(&d1, path, format).await
+}
+
+/// 把备份文件恢复到当前（空）数据库，完成后重新下载本地镜像
+#[tauri::command]
+pub async fn restore_database_backup(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    path: String,
+) -> Result<RestoreReport, AppError> {
+    let report = backup::restore(&d1, Path::new(&path)).await?;
+    if let Err(e) = mirror::pull(&d1, &mirror, true).await {
+        eprintln!("本地镜像同步失败: {}", e);
+    }
+    Ok(report)
+}
@@ -1,183 +1,142 @@
-use std::fs;
+use tauri::State;
 
-use crate::models::{D1Config, D1Response};
-use crate::services::config::{
-    clear_d1_config_cache, get_config_path, update_d1_config_cache, D1_CONFIG_CACHE,
+use crate::error::AppError;
+use crate::models::{
+    D1BatchResult, D1Config, D1Param, D1QueryOutput, D1Statement, SchemaVersion, StorageKind,
 };
+use crate::services::config::ConfigStore;
+use crate::services::d1::D1Client;
+use crate::services::migrations;
+use crate::services::usage::D1Usage;
 
 /// 保存 D1 配置
+///
+/// 当前使用 D1 后端时立即生效并迁移结构，使用本地后端时只保存，切换到 D1 后生效。
 #[tauri::command]
-pub async fn save_d1_config(config: D1Config) -> Result<String, String> {
-    let config_path = get_config_path()?;
-    let json =
-        serde_json::to_string_pretty(&config).map_err(|e| format!("序列化配置失败: {}", e))?;
+pub async fn save_d1_config(
+    store: State<'_, ConfigStore>,
+    d1: State<'_, D1Client>,
+    config: D1Config,
+) -> Result<String, AppError> {
+    store.write_d1_config(&config)?;
+
+    if store.read_settings()?.storage != StorageKind::D1 {
+        return Ok("配置保存成功，切换到 D1 存储后生效".to_string());
+    }
 
-    fs::write(&config_path, json).map_err(|e| format!("写入配置文件失败: {}", e))?;
+    d1.reload(config)?;
 
-    update_d1_config_cache(config);
+    migrations::migrate(&d1)
+        .await
+        .map_err(|e| e.context("配置已保存，但数据库结构迁移失败"))?;
 
     Ok("配置保存成功".to_string())
 }
 
-/// 读取 D1 配置
+/// 读取已保存的 D1 配置
 #[tauri::command]
-pub async fn load_d1_config() -> Result<D1Config, String> {
-    if let Ok(cache) = D1_CONFIG_CACHE.read() {
-        if let Some(config) = cache.as_ref() {
-            return Ok(config.clone());
-        }
-    }
-
-    let config_path = get_config_path()?;
-    if !config_path.exists() {
-        return Err("配置文件不存在".to_string());
-    }
-
-    let json = fs::read_to_string(&config_path).map_err(|e| format!("读取配置文件失败: {}", e))?;
-    let config: D1Config =
-        serde_json::from_str(&json).map_err(|e| format!("解析配置文件失败: {}", e))?;
-
-    update_d1_config_cache(config.clone());
-
-    Ok(config)
+pub async fn load_d1_config(store: State<'_, ConfigStore>) -> Result<D1Config, AppError> {
+    store.read_d1_config()
 }
 
 /// 删除 D1 配置
 #[tauri::command]
-pub async fn delete_d1_config() -> Result<String, String> {
-    let config_path = get_config_path()?;
-    if config_path.exists() {
-        fs::remove_file(&config_path).map_err(|e| format!("删除配置文件失败: {}", e))?;
+pub async fn delete_d1_config(
+    store: State<'_, ConfigStore>,
+    d1: State<'_, D1Client>,
+) -> Result<String, AppError> {
+    store.remove_d1_config()?;
+
+    if d1.kind() == Some(StorageKind::D1) {
+        d1.clear();
     }
 
-    clear_d1_config_cache();
-
     Ok("配置已完全删除".to_string())
 }
 
-/// 测试 D1 连接
+/// 获取当前的元数据存储后端
 #[tauri::command]
-pub async fn test_d1_connection(config: D1Config) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://api.cloudflare.com/client/v4/accounts/{}/d1/database/{}/query",
-        config.account_id, config.database_id
-    );
-
-    let body = serde_json::json!({ "sql": "SELECT 1 as test" });
-
-    let response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| format!("连接失败: {}", e))?;
-
-    let result: D1Response = response
-        .json()
-        .await
-        .map_err(|e| format!("解析响应失败: {}", e))?;
-
-    if result.success {
-        Ok("连接成功".to_string())
-    } else {
-        let error_msg = result
-            .errors
-            .iter()
-            .map(|e| format!("[{}] {}", e.code, e.message))
-            .collect::<Vec<_>>()
-            .join(", ");
-        Err(format!("连接失败: {}", error_msg))
-    }
+pub async fn get_storage_backend(store: State<'_, ConfigStore>) -> Result<StorageKind, AppError> {
+    Ok(store.read_settings()?.storage)
 }
 
-/// 执行 D1 SQL 查询
+/// 切换元数据存储后端并迁移其表结构
+///
+/// 两个后端的数据互不同步；切换到尚未配置的 D1 时保存设置并返回 `ConfigMissing`。
 #[tauri::command]
-pub async fn execute_d1_query(sql: String) -> Result<Vec<serde_json::Value>, String> {
-    let config = load_d1_config().await?;
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://api.cloudflare.com/client/v4/accounts/{}/d1/database/{}/query",
-        config.account_id, config.database_id
-    );
-
-    let body = serde_json::json!({ "sql": sql });
-
-    let response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
+pub async fn set_storage_backend(
+    store: State<'_, ConfigStore>,
+    d1: State<'_, D1Client>,
+    kind: StorageKind,
+) -> Result<String, AppError> {
+    let mut settings = store.read_settings()?;
+    settings.storage = kind;
+    store.write_settings(&settings)?;
+
+    crate::services::store::activate(&d1, &store, kind)?;
+    if d1.kind().is_none() {
+        return Err(AppError::ConfigMissing(
+            "已切换到 D1 存储，请先保存 D1 配置".to_string(),
+        ));
+    }
+    migrations::migrate(&d1)
         .await
-        .map_err(|e| format!("请求失败: {}", e))?;
+        .map_err(|e| e.context("已切换存储，但数据库结构迁移失败"))?;
 
-    let result: D1Response = response
-        .json()
-        .await
-        .map_err(|e| format!("解析响应失败: {}", e))?;
-
-    if result.success {
-        if let Some(query_results) = result.result {
-            if let Some(first_result) = query_results.first() {
-                return Ok(first_result.results.clone());
-            }
-        }
-        Ok(vec![])
-    } else {
-        let error_msg = result
-            .errors
-            .iter()
-            .map(|e| format!("[{}] {}", e.code, e.message))
-            .collect::<Vec<_>>()
-            .join(", ");
-        Err(format!("查询失败: {}", error_msg))
-    }
+    Ok(match kind {
+        StorageKind::D1 => "已切换到 Cloudflare D1 存储".to_string(),
+        StorageKind::Local => "已切换到本地存储".to_string(),
+    })
 }
 
-/// 批量执行 D1 SQL 语句（性能优化）
-pub async fn execute_d1_batch(sqls: Vec<String>) -> Result<(), String> {
-    if sqls.is_empty() {
-        return Ok(());
-    }
+/// 测试 D1 连接
+#[tauri::command]
+pub async fn test_d1_connection(
+    d1: State<'_, D1Client>,
+    config: D1Config,
+) -> Result<String, AppError> {
+    d1.test_connection(config).await?;
+    Ok("连接成功".to_string())
+}
 
-    let config = load_d1_config().await?;
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://api.cloudflare.com/client/v4/accounts/{}/d1/database/{}/query",
-        config.account_id, config.database_id
-    );
+/// 获取数据库结构版本
+#[tauri::command]
+pub async fn get_schema_version(d1: State<'_, D1Client>) -> Result<SchemaVersion, AppError> {
+    migrations::schema_version(&d1).await
+}
 
-    // 使用分号连接的批量SQL（D1会自动作为事务执行）
-    let batch_sql = sqls.join("; ");
+/// 执行 D1 SQL 查询，返回结果行及读写行数、耗时
+#[tauri::command]
+pub async fn execute_d1_query(
+    d1: State<'_, D1Client>,
+    sql: String,
+    params: Option<Vec<D1Param>>,
+) -> Result<D1QueryOutput, AppError> {
+    let statement = D1Statement {
+        sql,
+        params: params.unwrap_or_default(),
+    };
+    d1.query_with_meta(statement).await
+}
 
-    let body = serde_json::json!({ "sql": batch_sql });
+/// 批量执行 D1 SQL 语句，返回每条语句的结果
+#[tauri::command]
+pub async fn execute_d1_batch(
+    d1: State<'_, D1Client>,
+    statements: Vec<D1Statement>,
+) -> Result<D1BatchResult, AppError> {
+    d1.batch(statements).await
+}
 
-    let response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_token))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| format!("批量请求失败: {}", e))?;
+/// 获取本次会话的 D1 用量（读写行数、耗时，按语句分类）
+#[tauri::command]
+pub async fn get_d1_usage(d1: State<'_, D1Client>) -> Result<D1Usage, AppError> {
+    Ok(d1.usage().snapshot())
+}
 
-    let result: D1Response = response
-        .json()
-        .await
-        .map_err(|e| format!("解析响应失败: {}", e))?;
-
-    if result.success {
-        Ok(())
-    } else {
-        let error_msg = result
-            .errors
-            .iter()
-            .map(|e| format!("[{}] {}", e.code, e.message))
-            .collect::<Vec<_>>()
-            .join(", ");
-        Err(format!("批量执行失败: {}", error_msg))
-    }
+/// 清零 D1 用量计数
+#[tauri::command]
+pub async fn reset_d1_usage(d1: State<'_, D1Client>) -> Result<(), AppError> {
+    d1.usage().reset();
+    Ok(())
 }
@@ -1,129 +1,97 @@
-use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::Write;
-use tauri::AppHandle;
-use zip::write::FileOptions;
-use zip::ZipWriter;
+use tauri::{AppHandle, Runtime, State};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DownloadFileInfo {
-    pub url: String,
-    pub filename: String,
-}
+use crate::error::AppError;
+use crate::models::DownloadFileInfo;
+use crate::services::albums;
+use crate::services::d1::D1Client;
+use crate::services::download;
+use crate::services::jobs::JobRegistry;
+use crate::services::mirror::{self, LocalMirror};
+use crate::services::progress::Progress;
 
 /// 下载单个文件
+///
+/// 下载过程中以 `job-progress` 事件发送进度，`job_id` 用于区分不同的任务，
+/// 也可以用来取消下载。
 #[tauri::command]
-pub async fn download_single_file(
-    _app: AppHandle,
+pub async fn download_single_file<R: Runtime>(
+    app: AppHandle<R>,
+    jobs: State<'_, JobRegistry>,
     url: String,
     save_path: String,
-) -> Result<String, String> {
-    // 下载文件
-    let response = reqwest::get(&url)
-        .await
-        .map_err(|e| format!("下载失败: {}", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!("下载失败: HTTP {}", response.status()));
-    }
-
-    let bytes = response
-        .bytes()
-        .await
-        .map_err(|e| format!("读取文件内容失败: {}", e))?;
-
-    // 保存文件
-    let mut file = File::create(&save_path).map_err(|e| format!("创建文件失败: {}", e))?;
-
-    file.write_all(&bytes)
-        .map_err(|e| format!("写入文件失败: {}", e))?;
+    job_id: String,
+) -> Result<String, AppError> {
+    let job = jobs.start(job_id)?;
+    let progress = Progress::to_app(&app, &job, 1);
+    download::download_file(&url, &save_path, &progress).await?;
 
     Ok(format!("文件已保存到: {}", save_path))
 }
 
 /// 批量下载文件并打包成 zip
+///
+/// 指定 `album_id` 时按相册内的顺序追加整个相册的图片，文件名前加上序号；
+/// ZIP 中重名的文件会自动加上编号。每个文件的进度以 `job-progress` 事件发送；
+/// 取消后保留已打包的文件。
 #[tauri::command]
-pub async fn download_files_as_zip(
-    _app: AppHandle,
+#[allow(clippy::too_many_arguments)]
+pub async fn download_files_as_zip<R: Runtime>(
+    app: AppHandle<R>,
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    jobs: State<'_, JobRegistry>,
     files: Vec<DownloadFileInfo>,
     save_path: String,
-) -> Result<String, String> {
-    if files.is_empty() {
-        return Err("没有要下载的文件".to_string());
-    }
-
-    // 创建 zip 文件
-    let zip_file = File::create(&save_path).map_err(|e| format!("创建 ZIP 文件失败: {}", e))?;
-
-    let mut zip = ZipWriter::new(zip_file);
-    let options = FileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated)
-        .unix_permissions(0o755);
-
-    let mut success_count = 0;
-    let mut failed_files = Vec::new();
-
-    // 下载并添加每个文件到 zip
-    for file_info in &files {
-        match download_and_add_to_zip(&mut zip, file_info, &options).await {
-            Ok(_) => {
-                success_count += 1;
-            }
-            Err(e) => {
-                failed_files.push(format!("{}: {}", file_info.filename, e));
-            }
-        }
+    album_id: Option<i64>,
+    job_id: String,
+) -> Result<String, AppError> {
+    let job = jobs.start(job_id)?;
+    let mut files = files;
+    if let Some(album_id) = album_id {
+        mirror::ensure_synced(&d1, &mirror).await?;
+        let pictures = albums::pictures(&mirror, album_id, None, None)?;
+        let width = pictures.len().to_string().len();
+        files.extend(
+            pictures
+                .into_iter()
+                .enumerate()
+                .map(|(index, picture)| DownloadFileInfo {
+                    url: picture.url,
+                    filename: format!("{:0width$}_{}", index + 1, picture.filename),
+                }),
+        );
     }
 
-    // 完成 zip 文件
-    zip.finish()
-        .map_err(|e| format!("完成 ZIP 文件失败: {}", e))?;
+    let progress = Progress::to_app(&app, &job, files.len());
+    let summary = download::download_zip(files, &save_path, &progress).await?;
 
-    if success_count == 0 {
-        return Err(format!("所有文件下载失败:\n{}", failed_files.join("\n")));
+    if summary.succeeded == 0 {
+        return Err(AppError::Network {
+            message: format!("所有文件下载失败:\n{}", summary.failed.join("\n")),
+            status: None,
+            retryable: false,
+        });
     }
 
-    if !failed_files.is_empty() {
+    if summary.cancelled > 0 {
+        Ok(format!(
+            "任务已取消，已打包 {} 个文件到: {}（{} 个失败，{} 个未打包）",
+            summary.succeeded,
+            save_path,
+            summary.failed.len(),
+            summary.cancelled
+        ))
+    } else if !summary.failed.is_empty() {
         Ok(format!(
             "成功打包 {} 个文件，{} 个失败\n失败文件:\n{}",
-            success_count,
-            failed_files.len(),
-            failed_files.join("\n")
+            summary.succeeded,
+            summary.failed.len(),
+            summary.failed.join("\n")
         ))
     } else {
         Ok(format!(
             "成功打包 {} 个文件到: {}",
-            success_count, save_path
+            summary.succeeded, save_path
         ))
     }
 }
-
-/// 下载文件并添加到 zip
-async fn download_and_add_to_zip(
-    zip: &mut ZipWriter<File>,
-    file_info: &DownloadFileInfo,
-    options: &FileOptions,
-) -> Result<(), String> {
-    // 下载文件
-    let response = reqwest::get(&file_info.url)
-        .await
-        .map_err(|e| format!("下载失败: {}", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!("HTTP {}", response.status()));
-    }
-
-    let bytes = response
-        .bytes()
-        .await
-        .map_err(|e| format!("读取内容失败: {}", e))?;
-
-    // 添加到 zip
-    zip.start_file(&file_info.filename, *options)
-        .map_err(|e| format!("添加到 ZIP 失败: {}", e))?;
-
-    zip.write_all(&bytes)
-        .map_err(|e| format!("写入 ZIP 失败: {}", e))?;
-
-    Ok(())
-}
@@ -0,0 +1,13 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::services::jobs::JobRegistry;
+
+/// 取消正在运行的任务（上传、导入、批量删除、打包下载）
+///
+/// 任务在下一个安全点停止并返回已完成的部分，已上传的文件和已写入的记录保持不变。
+#[tauri::command]
+pub async fn cancel_job(jobs: State<'_, JobRegistry>, job_id: String) -> Result<String, AppError> {
+    jobs.cancel(&job_id)?;
+    Ok(format!("已请求取消任务 {}", job_id))
+}
@@ -0,0 +1,21 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::services::d1::D1Client;
+use crate::services::mirror::{self, LocalMirror, MirrorStatus};
+
+/// 获取本地镜像的同步状态（最近同步时间、图片数量）
+#[tauri::command]
+pub async fn get_mirror_status(mirror: State<'_, LocalMirror>) -> Result<MirrorStatus, AppError> {
+    mirror.status()
+}
+
+/// 立即从 D1 拉取变更到本地镜像，`full` 为 true 时重新下载全部数据
+#[tauri::command]
+pub async fn sync_local_mirror(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    full: Option<bool>,
+) -> Result<MirrorStatus, AppError> {
+    mirror::pull(&d1, &mirror, full.unwrap_or(false)).await
+}
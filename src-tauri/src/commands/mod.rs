@@ -1,7 +1,21 @@
+pub mod albums;
+pub mod backup;
 pub mod d1;
 pub mod download;
+pub mod jobs;
+pub mod mirror;
+pub mod offline;
+pub mod saved_search;
 pub mod smms;
+pub mod tags;
 
+pub use albums::*;
+pub use backup::*;
 pub use d1::*;
 pub use download::*;
+pub use jobs::*;
+pub use mirror::*;
+pub use offline::*;
+pub use saved_search::*;
 pub use smms::*;
+pub use tags::*;
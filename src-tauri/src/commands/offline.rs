@@ -0,0 +1,34 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::services::d1::D1Client;
+use crate::services::mirror::LocalMirror;
+use crate::services::offline::{self, PendingOperation, ReplayReport};
+use crate::services::smms::SmmsClient;
+
+/// 列出离线队列中尚未写入 D1 的操作（包括冲突和失败的）
+#[tauri::command]
+pub async fn list_pending_operations(
+    mirror: State<'_, LocalMirror>,
+) -> Result<Vec<PendingOperation>, AppError> {
+    offline::pending_operations(&mirror)
+}
+
+/// 放弃离线队列中的操作，`seqs` 为空时放弃全部，返回放弃的数量
+#[tauri::command]
+pub async fn discard_pending_operations(
+    mirror: State<'_, LocalMirror>,
+    seqs: Option<Vec<i64>>,
+) -> Result<usize, AppError> {
+    offline::discard(&mirror, seqs)
+}
+
+/// 立即按顺序重放离线队列
+#[tauri::command]
+pub async fn replay_pending_operations(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    smms: State<'_, SmmsClient>,
+) -> Result<ReplayReport, AppError> {
+    offline::replay(&d1, &smms, &mirror).await
+}
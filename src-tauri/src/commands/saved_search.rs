@@ -0,0 +1,66 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::models::{PictureQueryParams, PictureQueryResult, SavedSearch, SavedSearchUpdate};
+use crate::services::d1::D1Client;
+use crate::services::mirror::{self, LocalMirror};
+use crate::services::{picture_query, saved_search};
+
+/// 列出保存的搜索，`smart_albums_only` 为 true 时只返回智能相册
+#[tauri::command]
+pub async fn list_saved_searches(
+    d1: State<'_, D1Client>,
+    smart_albums_only: Option<bool>,
+) -> Result<Vec<SavedSearch>, AppError> {
+    saved_search::list(&d1, smart_albums_only.unwrap_or(false)).await
+}
+
+/// 保存当前的筛选和排序条件（分页参数不保存）
+#[tauri::command]
+pub async fn create_saved_search(
+    d1: State<'_, D1Client>,
+    name: String,
+    params: PictureQueryParams,
+    smart_album: Option<bool>,
+) -> Result<SavedSearch, AppError> {
+    saved_search::create(&d1, &name, &params, smart_album.unwrap_or(false)).await
+}
+
+/// 修改保存的搜索的名称、条件或是否作为智能相册显示
+#[tauri::command]
+pub async fn update_saved_search(
+    d1: State<'_, D1Client>,
+    id: i64,
+    changes: SavedSearchUpdate,
+) -> Result<SavedSearch, AppError> {
+    saved_search::update(&d1, id, &changes).await
+}
+
+/// 删除保存的搜索
+#[tauri::command]
+pub async fn delete_saved_search(d1: State<'_, D1Client>, id: i64) -> Result<(), AppError> {
+    saved_search::delete(&d1, id).await
+}
+
+/// 按保存的条件查询图片，结果与 [`query_pictures`](crate::commands::query_pictures) 相同
+///
+/// 每次都按当前数据重新筛选，作为智能相册时内容随图片的变化实时更新。
+#[tauri::command]
+pub async fn run_saved_search(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    cursor: Option<String>,
+) -> Result<PictureQueryResult, AppError> {
+    let saved = saved_search::get(&d1, id).await?;
+    mirror::ensure_synced(&d1, &mirror).await?;
+    let params = PictureQueryParams {
+        limit,
+        offset,
+        cursor,
+        ..saved.params
+    };
+    picture_query::query(&mirror, &params)
+}
@@ -0,0 +1,92 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::models::{PictureTags, Tag, TagUsage};
+use crate::services::d1::D1Client;
+use crate::services::mirror::{self, LocalMirror};
+use crate::services::tags;
+
+/// 列出全部标签及使用次数
+#[tauri::command]
+pub async fn list_tags(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+) -> Result<Vec<TagUsage>, AppError> {
+    mirror::ensure_synced(&d1, &mirror).await?;
+    tags::list(&mirror)
+}
+
+/// 获取图片的标签
+#[tauri::command]
+pub async fn get_picture_tags(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    ids: Vec<i64>,
+) -> Result<Vec<PictureTags>, AppError> {
+    mirror::ensure_synced(&d1, &mirror).await?;
+    tags::picture_tags(&mirror, &ids)
+}
+
+/// 新建标签，已存在同名标签（不区分大小写）时返回该标签
+#[tauri::command]
+pub async fn create_tag(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    name: String,
+) -> Result<Tag, AppError> {
+    tags::create(&d1, &mirror, &name).await
+}
+
+/// 重命名标签
+#[tauri::command]
+pub async fn rename_tag(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+    name: String,
+) -> Result<Tag, AppError> {
+    tags::rename(&d1, &mirror, id, &name).await
+}
+
+/// 把一个或多个标签合并到目标标签
+#[tauri::command]
+pub async fn merge_tags(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    source_ids: Vec<i64>,
+    target_id: i64,
+) -> Result<(), AppError> {
+    tags::merge(&d1, &mirror, &source_ids, target_id).await
+}
+
+/// 删除标签
+#[tauri::command]
+pub async fn delete_tag(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    id: i64,
+) -> Result<(), AppError> {
+    tags::delete(&d1, &mirror, id).await
+}
+
+/// 批量给图片添加标签（按名称，不存在的标签自动创建），返回涉及的标签
+#[tauri::command]
+pub async fn add_tags_to_pictures(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    ids: Vec<i64>,
+    tags: Vec<String>,
+) -> Result<Vec<Tag>, AppError> {
+    tags::tag_pictures(&d1, &mirror, &ids, &tags).await
+}
+
+/// 批量移除图片上的标签
+#[tauri::command]
+pub async fn remove_tags_from_pictures(
+    d1: State<'_, D1Client>,
+    mirror: State<'_, LocalMirror>,
+    ids: Vec<i64>,
+    tag_ids: Vec<i64>,
+) -> Result<(), AppError> {
+    tags::untag_pictures(&d1, &mirror, &ids, &tag_ids).await
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+
+/// 命令返回给前端的错误
+///
+/// 序列化为 `{code, category, retryable, message, details}`，前端根据 `category`
+/// 决定提示重新登录、重试还是打开设置。
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    /// 尚未配置 D1
+    ConfigMissing(String),
+    /// 配置无效（格式错误、API Token 无权限等）
+    ConfigInvalid(String),
+    /// D1 拒绝执行语句，`statement` 为批量中被拒绝语句的下标
+    D1 {
+        code: Option<i32>,
+        message: String,
+        statement: Option<usize>,
+    },
+    /// 数据库结构版本与应用不匹配
+    Schema(String),
+    /// 查询结果无法解码
+    Decode(String),
+    /// 本地镜像数据库读写失败
+    LocalDb(String),
+    /// SM.MS 接口返回失败
+    Smms {
+        code: Option<String>,
+        message: String,
+    },
+    /// SM.MS 凭证缺失或失效，需要重新登录
+    AuthRequired(String),
+    /// 网络错误或服务端暂时不可用
+    Network {
+        message: String,
+        status: Option<u16>,
+        retryable: bool,
+    },
+    /// 服务端返回了无法解析的响应
+    BadResponse(String),
+    /// 本地文件读写失败
+    Io(String),
+    /// 凭证加解密失败
+    Crypto(String),
+    /// 参数无效
+    InvalidInput(String),
+    /// 记录不存在
+    NotFound(String),
+    /// 任务被用户取消
+    Cancelled(String),
+}
+
+impl AppError {
+    /// 根据 SM.MS 返回的 `code` 区分登录失效与其他失败
+    pub fn smms(code: &str, message: impl Into<String>) -> Self {
+        let message = message.into();
+        match code {
+            "unauthorized" => AppError::AuthRequired(message),
+            "" => AppError::Smms {
+                code: None,
+                message,
+            },
+            _ => AppError::Smms {
+                code: Some(code.to_string()),
+                message,
+            },
+        }
+    }
+
+    /// 机器可读的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::ConfigMissing(_) => "config_missing",
+            AppError::ConfigInvalid(_) => "config_invalid",
+            AppError::D1 { .. } => "d1_error",
+            AppError::Schema(_) => "schema_mismatch",
+            AppError::Decode(_) => "decode_error",
+            AppError::LocalDb(_) => "local_db_error",
+            AppError::Smms { .. } => "smms_error",
+            AppError::AuthRequired(_) => "auth_required",
+            AppError::Network { .. } => "network_error",
+            AppError::BadResponse(_) => "bad_response",
+            AppError::Io(_) => "io_error",
+            AppError::Crypto(_) => "crypto_error",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::NotFound(_) => "not_found",
+            AppError::Cancelled(_) => "cancelled",
+        }
+    }
+
+    /// 错误类别，对应前端的处理方式
+    pub fn category(&self) -> &'static str {
+        match self {
+            AppError::ConfigMissing(_) | AppError::ConfigInvalid(_) => "config",
+            AppError::D1 { .. }
+            | AppError::Schema(_)
+            | AppError::Decode(_)
+            | AppError::LocalDb(_) => "database",
+            AppError::Smms { .. } => "smms",
+            AppError::AuthRequired(_) => "auth",
+            AppError::Network { .. } | AppError::BadResponse(_) => "network",
+            AppError::Io(_) => "io",
+            AppError::Crypto(_) => "crypto",
+            AppError::InvalidInput(_) | AppError::NotFound(_) => "validation",
+            AppError::Cancelled(_) => "cancelled",
+        }
+    }
+
+    /// 稍后重试是否可能成功
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            AppError::Network {
+                retryable: true,
+                ..
+            }
+        )
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::ConfigMissing(message)
+            | AppError::ConfigInvalid(message)
+            | AppError::Schema(message)
+            | AppError::Decode(message)
+            | AppError::LocalDb(message)
+            | AppError::AuthRequired(message)
+            | AppError::BadResponse(message)
+            | AppError::Io(message)
+            | AppError::Crypto(message)
+            | AppError::InvalidInput(message)
+            | AppError::NotFound(message)
+            | AppError::Cancelled(message)
+            | AppError::D1 { message, .. }
+            | AppError::Smms { message, .. }
+            | AppError::Network { message, .. } => message,
+        }
+    }
+
+    /// 附加信息（D1 错误码、HTTP 状态码等）
+    pub fn details(&self) -> Value {
+        match self {
+            AppError::D1 {
+                code, statement, ..
+            } => json!({ "d1_code": code, "statement": statement }),
+            AppError::Smms { code, .. } => json!({ "smms_code": code }),
+            AppError::Network { status, .. } => json!({ "status": status }),
+            _ => Value::Null,
+        }
+    }
+
+    /// 改写错误信息，保留类别和附加信息
+    pub fn map_message(mut self, f: impl FnOnce(&str) -> String) -> Self {
+        let message = match &mut self {
+            AppError::ConfigMissing(message)
+            | AppError::ConfigInvalid(message)
+            | AppError::Schema(message)
+            | AppError::Decode(message)
+            | AppError::LocalDb(message)
+            | AppError::AuthRequired(message)
+            | AppError::BadResponse(message)
+            | AppError::Io(message)
+            | AppError::Crypto(message)
+            | AppError::InvalidInput(message)
+            | AppError::NotFound(message)
+            | AppError::Cancelled(message)
+            | AppError::D1 { message, .. }
+            | AppError::Smms { message, .. }
+            | AppError::Network { message, .. } => message,
+        };
+        *message = f(message);
+        self
+    }
+
+    /// 在错误信息前加上上下文，例如 `查询失败: ...`
+    pub fn context(self, context: &str) -> Self {
+        self.map_message(|message| format!("{}: {}", context, message))
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 5)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("category", self.category())?;
+        state.serialize_field("retryable", &self.retryable())?;
+        state.serialize_field("message", self.message())?;
+        state.serialize_field("details", &self.details())?;
+        state.end()
+    }
+}
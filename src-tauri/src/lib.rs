@@ -1,15 +1,31 @@
-mod commands;
-mod models;
-mod services;
+pub mod commands;
+pub mod error;
+pub mod models;
+pub mod services;
 
 use commands::{
-    batch_delete_pictures, batch_update_picture_remark, delete_d1_config, delete_picture,
-    download_files_as_zip, download_single_file, execute_d1_query, get_all_file_types,
-    get_pictures_count, get_smms_token, get_smms_upload_history, import_all_smms_pictures,
-    init_smms_pictures_table, load_d1_config, load_smms_user, query_smms_pictures, save_d1_config,
-    save_smms_user, sync_smms_pictures, test_d1_connection, toggle_picture_favorite,
-    update_picture_remark, upload_images,
+    add_pictures_to_album, add_tags_to_pictures, batch_delete_pictures,
+    batch_update_picture_remark, cancel_job, create_album, create_saved_search, create_tag,
+    delete_album, delete_d1_config, delete_picture, delete_saved_search, delete_tag,
+    discard_pending_operations, download_files_as_zip, download_single_file, execute_d1_batch,
+    execute_d1_query, export_database_backup, get_all_file_types, get_d1_usage, get_mirror_status,
+    get_picture_tags, get_pictures_count, get_schema_version, get_smms_token,
+    get_smms_upload_history, get_storage_backend, import_all_smms_pictures,
+    init_smms_pictures_table, list_album_pictures, list_albums, list_pending_operations,
+    list_saved_searches, list_tags, load_d1_config, load_smms_user, merge_tags, query_pictures,
+    query_pictures_page, query_smms_pictures, remove_pictures_from_album,
+    remove_tags_from_pictures, rename_album, rename_tag, reorder_album_pictures, reorder_albums,
+    replay_pending_operations, reset_d1_usage, restore_database_backup, run_saved_search,
+    save_d1_config, save_smms_user, search_pictures, set_album_cover, set_storage_backend,
+    sync_local_mirror, sync_smms_pictures, test_d1_connection, toggle_picture_favorite,
+    update_picture_remark, update_saved_search, upload_images,
 };
+use services::config::ConfigStore;
+use services::d1::D1Client;
+use services::jobs::JobRegistry;
+use services::mirror::LocalMirror;
+use services::smms::SmmsClient;
+use tauri::Manager;
 
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -18,9 +34,58 @@ fn greet(name: &str) -> String {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let config_store = ConfigStore::from_env().expect("无法获取配置目录");
+    let endpoints = config_store.api_endpoints();
+    let mirror = config_store
+        .path_of("mirror.sqlite")
+        .and_then(|path| LocalMirror::open(&path))
+        .or_else(|e| {
+            eprintln!("{}，改用内存镜像", e);
+            LocalMirror::in_memory()
+        })
+        .expect("无法创建本地镜像");
+
+    // 按设置启用存储后端，配置无效时保持未配置状态，由用户在设置页修改
+    let d1 = D1Client::new(endpoints.cloudflare, None);
+    let storage = config_store.read_settings().unwrap_or_default().storage;
+    if let Err(e) = services::store::activate(&d1, &config_store, storage) {
+        eprintln!("启用存储后端失败: {}", e);
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(d1)
+        .manage(SmmsClient::new(endpoints.smms))
+        .manage(mirror)
+        .manage(config_store)
+        .manage(JobRegistry::new())
+        .setup(|app| {
+            // 启动时在后台执行尚未应用的结构迁移，之后定期重放离线队列并把 D1 的变更拉取到本地镜像
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let d1 = handle.state::<D1Client>();
+                if d1.kind().is_some() {
+                    if let Err(e) = services::migrations::migrate(&d1).await {
+                        eprintln!("数据库结构迁移失败: {}", e);
+                    }
+                }
+
+                let mirror = handle.state::<LocalMirror>();
+                let smms = handle.state::<SmmsClient>();
+                let mut interval = tokio::time::interval(services::mirror::PULL_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if d1.kind().is_some() {
+                        if let Err(e) = services::offline::replay(&d1, &smms, &mirror).await {
+                            eprintln!("离线队列重放失败: {}", e);
+                        }
+                        services::mirror::refresh(&d1, &mirror).await;
+                    }
+                }
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             get_smms_token,
@@ -30,11 +95,50 @@ pub fn run() {
             save_d1_config,
             load_d1_config,
             delete_d1_config,
+            get_storage_backend,
+            set_storage_backend,
             test_d1_connection,
             execute_d1_query,
+            execute_d1_batch,
+            get_schema_version,
+            get_d1_usage,
+            reset_d1_usage,
+            get_mirror_status,
+            sync_local_mirror,
+            export_database_backup,
+            restore_database_backup,
+            list_pending_operations,
+            discard_pending_operations,
+            replay_pending_operations,
             init_smms_pictures_table,
             sync_smms_pictures,
             query_smms_pictures,
+            query_pictures_page,
+            query_pictures,
+            search_pictures,
+            list_saved_searches,
+            create_saved_search,
+            update_saved_search,
+            delete_saved_search,
+            run_saved_search,
+            list_tags,
+            get_picture_tags,
+            create_tag,
+            rename_tag,
+            merge_tags,
+            delete_tag,
+            add_tags_to_pictures,
+            remove_tags_from_pictures,
+            list_albums,
+            list_album_pictures,
+            create_album,
+            rename_album,
+            delete_album,
+            reorder_albums,
+            add_pictures_to_album,
+            remove_pictures_from_album,
+            reorder_album_pictures,
+            set_album_cover,
             toggle_picture_favorite,
             import_all_smms_pictures,
             get_pictures_count,
@@ -44,6 +148,7 @@ pub fn run() {
             batch_delete_pictures,
             download_single_file,
             download_files_as_zip,
+            cancel_job,
             update_picture_remark,
             batch_update_picture_remark
         ])
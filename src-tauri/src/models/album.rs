@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+/// 相册（名称不区分大小写），一张图片可以属于多个相册
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Album {
+    pub id: i64,
+    pub name: String,
+    /// 相册之间的顺序，越小越靠前
+    pub position: i64,
+    /// 手动设置的封面图片，未设置时为空
+    pub cover_picture_id: Option<i64>,
+    /// 实际显示的封面：手动设置的封面，否则为相册中的第一张图片
+    pub cover_url: Option<String>,
+    /// 相册中未删除的图片数量
+    pub picture_count: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
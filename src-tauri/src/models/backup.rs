@@ -0,0 +1,49 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// 备份文件格式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupFormat {
+    /// 独立的 SQLite 数据库文件，可直接用 sqlite3 打开
+    #[default]
+    Sqlite,
+    /// SQL 文本（`CREATE TABLE` + `INSERT`）
+    Sql,
+}
+
+impl BackupFormat {
+    /// 按扩展名推断格式：`.sql` 为 SQL 文本，其余为 SQLite 文件
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("sql") => BackupFormat::Sql,
+            _ => BackupFormat::Sqlite,
+        }
+    }
+}
+
+/// 一张表的行数
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TableCount {
+    pub table: String,
+    pub rows: i64,
+}
+
+/// 备份结果
+#[derive(Serialize, Clone, Debug)]
+pub struct BackupReport {
+    pub path: String,
+    pub format: BackupFormat,
+    /// 备份时数据库的结构版本
+    pub schema_version: u32,
+    pub tables: Vec<TableCount>,
+}
+
+/// 恢复结果，`tables` 为恢复后数据库中核对过的行数
+#[derive(Serialize, Clone, Debug)]
+pub struct RestoreReport {
+    /// 备份文件的结构版本（恢复后数据库已迁移到最新版本）
+    pub schema_version: u32,
+    pub tables: Vec<TableCount>,
+}
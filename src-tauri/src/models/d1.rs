@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::error::AppError;
+
 /// Cloudflare D1 完整配置结构（用于前端交互）
 #[derive(Serialize, Deserialize, Clone)]
 pub struct D1Config {
@@ -8,6 +10,87 @@ pub struct D1Config {
     pub api_token: String,
 }
 
+/// D1 SQL 绑定参数（对应 SQLite 的 NULL / INTEGER / REAL / TEXT）
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum D1Param {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl From<i64> for D1Param {
+    fn from(value: i64) -> Self {
+        D1Param::Integer(value)
+    }
+}
+
+impl From<i32> for D1Param {
+    fn from(value: i32) -> Self {
+        D1Param::Integer(value.into())
+    }
+}
+
+impl From<bool> for D1Param {
+    fn from(value: bool) -> Self {
+        D1Param::Integer(value.into())
+    }
+}
+
+impl From<f64> for D1Param {
+    fn from(value: f64) -> Self {
+        D1Param::Real(value)
+    }
+}
+
+impl From<String> for D1Param {
+    fn from(value: String) -> Self {
+        D1Param::Text(value)
+    }
+}
+
+impl From<&str> for D1Param {
+    fn from(value: &str) -> Self {
+        D1Param::Text(value.to_string())
+    }
+}
+
+impl From<&String> for D1Param {
+    fn from(value: &String) -> Self {
+        D1Param::Text(value.clone())
+    }
+}
+
+impl<T: Into<D1Param>> From<Option<T>> for D1Param {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(D1Param::Null, Into::into)
+    }
+}
+
+/// 带绑定参数的 D1 SQL 语句，SQL 中使用 `?` 占位
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct D1Statement {
+    pub sql: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<D1Param>,
+}
+
+impl D1Statement {
+    pub fn new(sql: impl Into<String>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// 追加一个绑定参数
+    pub fn bind(mut self, value: impl Into<D1Param>) -> Self {
+        self.params.push(value.into());
+        self
+    }
+}
+
 /// D1 API 响应结构
 #[derive(Deserialize)]
 pub struct D1Response {
@@ -28,5 +111,99 @@ pub struct D1Error {
 pub struct D1QueryResult {
     pub results: Vec<serde_json::Value>,
     pub success: bool,
-    pub meta: Option<serde_json::Value>,
+    #[serde(default)]
+    pub meta: Option<D1QueryMeta>,
+}
+
+/// D1 返回的语句执行开销
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct D1QueryMeta {
+    #[serde(default, deserialize_with = "crate::services::rows::null_as_default")]
+    pub rows_read: u64,
+    #[serde(default, deserialize_with = "crate::services::rows::null_as_default")]
+    pub rows_written: u64,
+    /// 执行耗时（毫秒）
+    #[serde(default, deserialize_with = "crate::services::rows::null_as_default")]
+    pub duration: f64,
+    /// 执行语句的 D1 实例
+    #[serde(default)]
+    pub served_by: Option<String>,
+}
+
+impl D1QueryMeta {
+    /// 累加另一条语句的开销
+    pub fn add(&mut self, other: &D1QueryMeta) {
+        self.rows_read += other.rows_read;
+        self.rows_written += other.rows_written;
+        self.duration += other.duration;
+        if other.served_by.is_some() {
+            self.served_by.clone_from(&other.served_by);
+        }
+    }
+}
+
+/// 单条查询的结果行及执行开销
+#[derive(Serialize, Debug, Clone)]
+pub struct D1QueryOutput {
+    pub results: Vec<serde_json::Value>,
+    pub meta: D1QueryMeta,
+}
+
+/// 数据库结构版本
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SchemaVersion {
+    /// 数据库已应用的最新迁移版本
+    pub current: u32,
+    /// 当前应用支持的最新版本
+    pub latest: u32,
+}
+
+/// 批量执行中单条语句的结果
+#[derive(Serialize, Debug, Clone)]
+pub struct D1StatementResult {
+    /// 语句在批量中的下标
+    pub index: usize,
+    pub success: bool,
+    /// 执行开销，失败的语句全为 0
+    pub meta: D1QueryMeta,
+    pub error: Option<String>,
+    /// 语句返回的行（`SELECT` 或 `RETURNING`）
+    pub results: Vec<serde_json::Value>,
+}
+
+/// 批量执行结果
+///
+/// 语句按顺序执行，遇到第一条被拒绝的语句即停止：之前的语句已提交，之后的语句未执行。
+#[derive(Serialize, Debug, Default)]
+pub struct D1BatchResult {
+    pub results: Vec<D1StatementResult>,
+    /// 第一条失败语句的下标
+    pub failed_index: Option<usize>,
+    pub error: Option<AppError>,
+    /// 已执行语句的开销合计
+    pub meta: D1QueryMeta,
+}
+
+impl D1BatchResult {
+    pub fn is_success(&self) -> bool {
+        self.failed_index.is_none()
+    }
+
+    /// 存在失败语句时转为错误，`describe` 根据下标描述被拒绝的语句或记录
+    pub fn check(&self, describe: impl FnOnce(usize) -> String) -> Result<(), AppError> {
+        let Some(index) = self.failed_index else {
+            return Ok(());
+        };
+        let error = self
+            .error
+            .clone()
+            .unwrap_or_else(|| AppError::InvalidInput("未知错误".to_string()));
+        match error {
+            // 语句本身被拒绝时指明对应的记录；网络错误无法确定具体语句
+            AppError::D1 { .. } | AppError::InvalidInput(_) => {
+                Err(error.map_message(|message| format!("{}被拒绝: {}", describe(index), message)))
+            }
+            other => Err(other),
+        }
+    }
 }
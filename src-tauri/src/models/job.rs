@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+/// 文件处理阶段
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStage {
+    /// 读取本地文件
+    Reading,
+    /// 上传到图床
+    Uploading,
+    /// 写入数据库
+    Saving,
+    /// 从网络下载
+    Downloading,
+    /// 写入 ZIP
+    Zipping,
+    /// 已完成
+    Done,
+    /// 失败
+    Failed,
+    /// 任务被取消，未处理
+    Cancelled,
+}
+
+/// 单个文件的进度事件，通过 `job-progress` 事件发送给前端
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct JobProgress {
+    /// 调用命令时传入的任务 id
+    pub job_id: String,
+    /// 文件在本次任务中的下标（从 0 开始）
+    pub file_index: usize,
+    pub file_count: usize,
+    pub filename: String,
+    pub stage: JobStage,
+    /// 当前阶段已处理的字节数
+    pub bytes: u64,
+    /// 总字节数，未知时为空
+    pub total: Option<u64>,
+    /// 失败原因，只在 `failed` 阶段提供
+    pub message: Option<String>,
+}
@@ -1,5 +1,17 @@
+pub mod album;
+pub mod backup;
 pub mod d1;
+pub mod job;
+pub mod saved_search;
+pub mod settings;
 pub mod smms;
+pub mod tag;
 
+pub use album::*;
+pub use backup::*;
 pub use d1::*;
+pub use job::*;
+pub use saved_search::*;
+pub use settings::*;
 pub use smms::*;
+pub use tag::*;
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::PictureQueryParams;
+
+/// 保存的搜索，可以作为智能相册显示，内容随图片变化实时更新
+#[derive(Serialize, Debug, Clone)]
+pub struct SavedSearch {
+    pub id: i64,
+    pub name: String,
+    /// 筛选和排序条件，不包含分页参数
+    pub params: PictureQueryParams,
+    /// 是否作为智能相册显示
+    pub smart_album: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// 修改保存的搜索，未提供的字段保持不变
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedSearchUpdate {
+    pub name: Option<String>,
+    pub params: Option<PictureQueryParams>,
+    pub smart_album: Option<bool>,
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// 元数据存储后端
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageKind {
+    /// Cloudflare D1，多台设备共享
+    #[default]
+    D1,
+    /// 本机 SQLite 文件，无需 Cloudflare 账号
+    Local,
+}
+
+/// 应用设置（`settings.json`），字段均可省略
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AppSettings {
+    #[serde(default)]
+    pub storage: StorageKind,
+}
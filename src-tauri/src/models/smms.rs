@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 #[derive(Deserialize)]
 pub struct SmmsTokenResponse {
     pub success: bool,
+    #[serde(default)]
+    pub code: String,
     pub message: String,
     pub data: Option<SmmsTokenData>,
 }
@@ -46,7 +48,7 @@ pub struct SmmsUploadItem {
 pub struct SmmsUploadHistoryResponse {
     pub success: bool,
     #[serde(default)]
-    pub _code: String,
+    pub code: String,
     #[serde(default)]
     pub message: String,
     pub data: Option<Vec<SmmsUploadItem>>,
@@ -71,26 +73,130 @@ pub struct SmmsPicture {
     pub delete_url: String,
     pub page_url: String,
     pub is_favorite: i32,
+    #[serde(default, deserialize_with = "crate::services::rows::null_as_default")]
     pub is_deleted: i32,
     pub deleted_at: Option<String>,
     pub remark: Option<String>,
+    /// 上传时本地文件内容的 SHA-256，从 SM.MS 导入的记录为空
+    #[serde(default)]
+    pub content_sha256: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+/// 全文搜索命中的图片
+///
+/// 高亮字段为 HTML：原文已转义，匹配的词包在 `<mark>` 中。
+#[derive(Serialize, Debug, Clone)]
+pub struct PictureSearchHit {
+    #[serde(flatten)]
+    pub picture: SmmsPicture,
+    /// bm25 相关度，越小越相关
+    pub rank: f64,
+    pub filename_highlight: String,
+    pub store_name_highlight: String,
+    /// 备注中命中位置附近的片段，备注未命中时为空
+    pub remark_snippet: Option<String>,
+}
+
+/// 全文搜索结果
+#[derive(Serialize, Debug, Clone)]
+pub struct PictureSearchPage {
+    pub items: Vec<PictureSearchHit>,
+    /// 命中的图片总数
+    pub total: i64,
+}
+
+/// 图片方向
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    /// 横图（宽 > 高）
+    Landscape,
+    /// 竖图（宽 < 高）
+    Portrait,
+    /// 方图（宽 = 高）
+    Square,
+}
+
 /// 图片查询参数
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PictureQueryParams {
     pub file_type: Option<String>,
+    /// 多个文件类型（任一匹配），可与 `file_type` 同时使用
+    pub file_types: Option<Vec<String>>,
     pub is_favorite: Option<bool>,
     pub include_deleted: Option<bool>,
     pub order_by: Option<String>,
     pub filename: Option<String>,
     pub store_name: Option<String>,
     pub remark: Option<String>,
+    /// 时间范围（`YYYY-MM-DD` 或 `YYYY-MM-DD HH:MM:SS`，UTC），上限只写日期时包含当天
+    pub created_from: Option<String>,
+    pub created_to: Option<String>,
+    pub updated_from: Option<String>,
+    pub updated_to: Option<String>,
+    /// 文件大小范围（字节，含边界）
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    /// 尺寸范围（像素，含边界）
+    pub min_width: Option<i64>,
+    pub max_width: Option<i64>,
+    pub min_height: Option<i64>,
+    pub max_height: Option<i64>,
+    /// 宽高比（宽 / 高）范围
+    pub min_aspect_ratio: Option<f64>,
+    pub max_aspect_ratio: Option<f64>,
+    pub orientation: Option<Orientation>,
+    /// 标签筛选（标签 id）：全部包含、包含任一、都不包含，三者可同时使用
+    pub tags_all: Option<Vec<i64>>,
+    pub tags_any: Option<Vec<i64>>,
+    pub tags_none: Option<Vec<i64>>,
+    /// 只返回该相册中的图片
+    pub album_id: Option<i64>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    /// 上一页返回的 `next_cursor`，不能与 `offset` 同时使用
+    pub cursor: Option<String>,
+}
+
+/// 按游标分页的一页图片
+#[derive(Serialize, Debug, Clone)]
+pub struct PicturePage {
+    pub items: Vec<SmmsPicture>,
+    /// 下一页的游标，已到最后一页时为空
+    pub next_cursor: Option<String>,
+}
+
+/// 某个筛选值及其匹配的图片数量
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: i64,
+}
+
+/// 分面计数，每一组都忽略自身的筛选条件，表示切换到该值后能看到的数量
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PictureFacets {
+    /// 各文件类型的数量（忽略文件类型筛选）
+    pub file_types: Vec<FacetCount>,
+    /// 收藏与未收藏的数量（忽略收藏筛选）
+    pub favorites: i64,
+    pub not_favorites: i64,
+    /// 未删除与已删除的数量（忽略删除状态筛选）
+    pub active: i64,
+    pub deleted: i64,
+}
+
+/// 一次查询的结果：当前页、匹配总数和分面计数
+#[derive(Serialize, Debug, Clone)]
+pub struct PictureQueryResult {
+    pub items: Vec<SmmsPicture>,
+    /// 下一页的游标，已到最后一页时为空
+    pub next_cursor: Option<String>,
+    pub total: i64,
+    pub facets: PictureFacets,
 }
 
 /// SM.MS 上传响应数据
@@ -136,6 +242,19 @@ pub struct UploadResult {
     pub message: String,
     pub url: Option<String>,
     pub remark: Option<String>,
+    /// 上传请求的尝试次数（读取文件失败时为 0）
+    pub attempts: u32,
+    /// 任务被取消，文件未上传
+    pub cancelled: bool,
+    /// 已有相同内容的图片，未重复上传，`url` 为已有记录的地址
+    pub duplicate: bool,
+}
+
+/// 要下载的文件
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadFileInfo {
+    pub url: String,
+    pub filename: String,
 }
 
 /// SM.MS 删除响应
@@ -143,7 +262,7 @@ pub struct UploadResult {
 pub struct SmmsDeleteResponse {
     pub success: bool,
     #[serde(default)]
-    pub _code: String,
+    pub code: String,
     #[serde(default)]
     pub message: String,
     #[serde(rename = "RequestId")]
@@ -157,12 +276,22 @@ pub struct SyncStats {
     pub added: usize,
     pub skipped: usize,
     pub deleted: usize,
+    /// 获取上传历史时的重试次数
+    pub retries: u32,
+    /// 任务被取消，只导入了部分页面，也没有标记已删除的图片
+    pub cancelled: bool,
 }
 
 /// 批量删除结果
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Default)]
 pub struct BatchDeleteResult {
     pub success_count: usize,
     pub failed_count: usize,
     pub failed_items: Vec<String>,
+    /// 网络不可用、已加入离线队列的数量
+    pub queued_count: usize,
+    /// 调用删除接口时的重试次数
+    pub retries: u32,
+    /// 任务被取消、未处理的数量
+    pub cancelled_count: usize,
 }
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// 图片标签（名称不区分大小写）
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub created_at: Option<String>,
+}
+
+/// 标签及其使用次数
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TagUsage {
+    pub id: i64,
+    pub name: String,
+    pub created_at: Option<String>,
+    /// 使用该标签的未删除图片数量
+    pub count: i64,
+}
+
+/// 一张图片及其标签
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PictureTags {
+    pub picture_id: i64,
+    pub tags: Vec<Tag>,
+}
@@ -0,0 +1,436 @@
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+use crate::error::AppError;
+use crate::models::{Album, D1Param, D1Statement, SmmsPicture};
+use crate::services::d1::{D1Client, MAX_BOUND_PARAMS};
+use crate::services::migrations;
+use crate::services::mirror::{LocalMirror, ALBUMS};
+use crate::services::offline::MAX_IDS_PER_STATEMENT;
+use crate::services::picture_query::DEFAULT_PAGE_SIZE;
+
+/// 相册名称的最大长度（字符）
+const MAX_NAME_CHARS: usize = 64;
+
+/// 按顺序写入位置时每条语句处理的数量：每项两个参数，另有几个固定参数
+const POSITIONS_PER_STATEMENT: usize = (MAX_BOUND_PARAMS - 4) / 2;
+
+/// 相册及其封面、图片数量；封面和数量只计算未删除的图片
+const ALBUM_SELECT: &str = "SELECT a.id, a.name, a.position, a.cover_picture_id, \
+     a.created_at, a.updated_at, \
+     (SELECT COUNT(*) FROM album_pictures ap JOIN smms_pictures p ON p.id = ap.picture_id \
+      WHERE ap.album_id = a.id AND p.is_deleted = 0) AS picture_count, \
+     COALESCE( \
+      (SELECT p.url FROM smms_pictures p WHERE p.id = a.cover_picture_id AND p.is_deleted = 0), \
+      (SELECT p.url FROM album_pictures ap JOIN smms_pictures p ON p.id = ap.picture_id \
+       WHERE ap.album_id = a.id AND p.is_deleted = 0 \
+       ORDER BY ap.position, ap.picture_id LIMIT 1)) AS cover_url \
+     FROM albums a";
+
+/// 全部相册，按用户设置的顺序排列
+pub fn list(mirror: &LocalMirror) -> Result<Vec<Album>, AppError> {
+    mirror.query_as(&D1Statement::new(format!(
+        "{} ORDER BY a.position, a.id",
+        ALBUM_SELECT
+    )))
+}
+
+/// 按 id 读取相册
+pub fn get(mirror: &LocalMirror, id: i64) -> Result<Album, AppError> {
+    mirror
+        .query_as::<Album>(&D1Statement::new(format!("{} WHERE a.id = ?", ALBUM_SELECT)).bind(id))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found(id))
+}
+
+/// 相册中未删除的图片，按相册内的顺序排列；`limit` 为空时返回全部
+pub fn pictures(
+    mirror: &LocalMirror,
+    id: i64,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<SmmsPicture>, AppError> {
+    get(mirror, id)?;
+    let mut sql = "SELECT p.* FROM album_pictures ap JOIN smms_pictures p ON p.id = ap.picture_id \
+                   WHERE ap.album_id = ? AND p.is_deleted = 0 \
+                   ORDER BY ap.position, ap.picture_id"
+        .to_string();
+    let mut statement_params = vec![D1Param::from(id)];
+    if limit.is_some() || offset.is_some() {
+        sql.push_str(" LIMIT ? OFFSET ?");
+        statement_params.push(D1Param::from(limit.unwrap_or(DEFAULT_PAGE_SIZE)));
+        statement_params.push(D1Param::from(offset.unwrap_or(0)));
+    }
+    mirror.query_as(&D1Statement {
+        sql,
+        params: statement_params,
+    })
+}
+
+/// 新建相册，排在最后
+pub async fn create(d1: &D1Client, mirror: &LocalMirror, name: &str) -> Result<Album, AppError> {
+    let name = validate_name(name)?;
+    migrations::ensure_ready(d1).await?;
+    check_conflict(d1, &name, None).await?;
+
+    let insert = D1Statement::new(
+        "INSERT INTO albums (name, position) \
+         VALUES (?, (SELECT COALESCE(MAX(position), -1) + 1 FROM albums)) \
+         RETURNING id, name, cover_picture_id, position, created_at, updated_at",
+    )
+    .bind(&name);
+    let (revision, results) = ALBUMS.execute(d1, vec![insert]).await?;
+    let row = results
+        .first()
+        .and_then(|rows| rows.first())
+        .ok_or_else(|| AppError::BadResponse("创建相册后没有返回记录".to_string()))?;
+    let id = row["id"]
+        .as_i64()
+        .ok_or_else(|| AppError::BadResponse("创建相册后没有返回 id".to_string()))?;
+
+    // 新相册的 id 由 D1 分配，镜像按 id 写入
+    let local = D1Statement::new(
+        "INSERT OR REPLACE INTO albums (id, name, cover_picture_id, position, created_at, updated_at) \
+         VALUES (?, ?, NULL, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(&name)
+    .bind(row["position"].as_i64().unwrap_or_default())
+    .bind(text(&row["created_at"]))
+    .bind(text(&row["updated_at"]));
+    ALBUMS.write_through(d1, mirror, revision, &[local]).await?;
+    get(mirror, id)
+}
+
+/// 重命名相册，新名称不能与其他相册重复
+pub async fn rename(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    id: i64,
+    name: &str,
+) -> Result<Album, AppError> {
+    let name = validate_name(name)?;
+    migrations::ensure_ready(d1).await?;
+    require_album(d1, id).await?;
+    check_conflict(d1, &name, Some(id)).await?;
+
+    let update =
+        D1Statement::new("UPDATE albums SET name = ?, updated_at = datetime('now') WHERE id = ?")
+            .bind(&name)
+            .bind(id);
+    ALBUMS.apply(d1, mirror, vec![update]).await?;
+    get(mirror, id)
+}
+
+/// 删除相册，相册中的图片本身不受影响
+pub async fn delete(d1: &D1Client, mirror: &LocalMirror, id: i64) -> Result<(), AppError> {
+    migrations::ensure_ready(d1).await?;
+    require_album(d1, id).await?;
+    let statements = vec![
+        D1Statement::new("DELETE FROM album_pictures WHERE album_id = ?").bind(id),
+        D1Statement::new("DELETE FROM albums WHERE id = ?").bind(id),
+    ];
+    ALBUMS.apply(d1, mirror, statements).await
+}
+
+/// 按给定顺序把图片加到相册末尾，已在相册中的图片保持原位置，不存在的图片忽略
+pub async fn add_pictures(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    id: i64,
+    picture_ids: &[i64],
+) -> Result<Album, AppError> {
+    let picture_ids = validate_picture_ids(picture_ids)?;
+    migrations::ensure_ready(d1).await?;
+    require_album(d1, id).await?;
+
+    let mut statements: Vec<D1Statement> = picture_ids
+        .chunks(POSITIONS_PER_STATEMENT)
+        .map(|chunk| {
+            let (values, mut params) = positions(chunk, 0);
+            params.extend([id, id, id].map(D1Param::from));
+            D1Statement {
+                sql: format!(
+                    "WITH input (picture_id, ordinal) AS (VALUES {}) \
+                     INSERT INTO album_pictures (album_id, picture_id, position) \
+                     SELECT ?, i.picture_id, \
+                      (SELECT COALESCE(MAX(position), -1) FROM album_pictures WHERE album_id = ?) \
+                      + ROW_NUMBER() OVER (ORDER BY i.ordinal) \
+                     FROM input i JOIN smms_pictures p ON p.id = i.picture_id \
+                     WHERE i.picture_id NOT IN \
+                      (SELECT picture_id FROM album_pictures WHERE album_id = ?) \
+                     RETURNING album_id, picture_id, position",
+                    values
+                ),
+                params,
+            }
+        })
+        .collect();
+    let inserts = statements.len();
+    statements.push(touch(id));
+    let (revision, results) = ALBUMS.execute(d1, statements).await?;
+
+    // 镜像可能还没有这些图片，按 D1 实际插入的行写入，不在镜像中重新连接图片表
+    let mut local = ALBUMS.replace_rows("album_pictures", &results[..inserts].concat());
+    local.push(touch(id));
+    ALBUMS.write_through(d1, mirror, revision, &local).await?;
+    get(mirror, id)
+}
+
+/// 从相册中移除图片；移除的是封面时改用默认封面
+pub async fn remove_pictures(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    id: i64,
+    picture_ids: &[i64],
+) -> Result<Album, AppError> {
+    let picture_ids = validate_picture_ids(picture_ids)?;
+    migrations::ensure_ready(d1).await?;
+    require_album(d1, id).await?;
+
+    let mut statements: Vec<D1Statement> = picture_ids
+        .chunks(MAX_IDS_PER_STATEMENT)
+        .map(|chunk| {
+            let mut params = vec![D1Param::from(id)];
+            params.extend(chunk.iter().map(|id| D1Param::from(*id)));
+            D1Statement {
+                sql: format!(
+                    "DELETE FROM album_pictures WHERE album_id = ? AND picture_id IN ({})",
+                    placeholders(chunk.len())
+                ),
+                params,
+            }
+        })
+        .collect();
+    statements.push(
+        D1Statement::new(
+            "UPDATE albums SET cover_picture_id = NULL WHERE id = ? AND cover_picture_id NOT IN \
+             (SELECT picture_id FROM album_pictures WHERE album_id = ?)",
+        )
+        .bind(id)
+        .bind(id),
+    );
+    statements.push(touch(id));
+    ALBUMS.apply(d1, mirror, statements).await?;
+    get(mirror, id)
+}
+
+/// 调整相册中图片的顺序
+///
+/// `picture_ids` 按新顺序排在最前面，未列出的图片保持原有的相对顺序排在后面。
+pub async fn reorder_pictures(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    id: i64,
+    picture_ids: &[i64],
+) -> Result<Album, AppError> {
+    let picture_ids = validate_picture_ids(picture_ids)?;
+    migrations::ensure_ready(d1).await?;
+    require_album(d1, id).await?;
+
+    let current = ids_in_order(
+        d1,
+        D1Statement::new(
+            "SELECT picture_id AS id FROM album_pictures WHERE album_id = ? \
+             ORDER BY position, picture_id",
+        )
+        .bind(id),
+    )
+    .await?;
+    let order = new_order(&current, &picture_ids, |picture_id| {
+        AppError::InvalidInput(format!("图片 {} 不在相册 {} 中", picture_id, id))
+    })?;
+
+    let mut statements: Vec<D1Statement> = order
+        .chunks(POSITIONS_PER_STATEMENT)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let (values, mut params) = positions(chunk, index * POSITIONS_PER_STATEMENT);
+            params.push(D1Param::from(id));
+            D1Statement {
+                sql: format!(
+                    "WITH input (picture_id, position) AS (VALUES {}) \
+                     UPDATE album_pictures SET position = \
+                      (SELECT position FROM input WHERE input.picture_id = album_pictures.picture_id) \
+                     WHERE album_id = ? AND picture_id IN (SELECT picture_id FROM input)",
+                    values
+                ),
+                params,
+            }
+        })
+        .collect();
+    statements.push(touch(id));
+    ALBUMS.apply(d1, mirror, statements).await?;
+    get(mirror, id)
+}
+
+/// 调整相册之间的顺序，规则与 [`reorder_pictures`] 相同
+pub async fn reorder(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    ids: &[i64],
+) -> Result<Vec<Album>, AppError> {
+    let ids = validate_picture_ids(ids)?;
+    migrations::ensure_ready(d1).await?;
+
+    let current = ids_in_order(
+        d1,
+        D1Statement::new("SELECT id FROM albums ORDER BY position, id"),
+    )
+    .await?;
+    let order = new_order(&current, &ids, not_found)?;
+
+    let statements = order
+        .chunks(POSITIONS_PER_STATEMENT)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let (values, params) = positions(chunk, index * POSITIONS_PER_STATEMENT);
+            D1Statement {
+                sql: format!(
+                    "WITH input (id, position) AS (VALUES {}) \
+                     UPDATE albums SET position = \
+                      (SELECT position FROM input WHERE input.id = albums.id) \
+                     WHERE id IN (SELECT id FROM input)",
+                    values
+                ),
+                params,
+            }
+        })
+        .collect();
+    ALBUMS.apply(d1, mirror, statements).await?;
+    list(mirror)
+}
+
+/// 设置相册封面，图片必须在相册中；`picture_id` 为空时恢复默认封面（第一张图片）
+pub async fn set_cover(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    id: i64,
+    picture_id: Option<i64>,
+) -> Result<Album, AppError> {
+    migrations::ensure_ready(d1).await?;
+    require_album(d1, id).await?;
+    if let Some(picture_id) = picture_id {
+        let member =
+            D1Statement::new("SELECT 1 FROM album_pictures WHERE album_id = ? AND picture_id = ?")
+                .bind(id)
+                .bind(picture_id);
+        if d1.query(member).await?.is_empty() {
+            return Err(AppError::InvalidInput(format!(
+                "图片 {} 不在相册 {} 中",
+                picture_id, id
+            )));
+        }
+    }
+
+    let update = D1Statement::new(
+        "UPDATE albums SET cover_picture_id = ?, updated_at = datetime('now') WHERE id = ?",
+    )
+    .bind(picture_id)
+    .bind(id);
+    ALBUMS.apply(d1, mirror, vec![update]).await?;
+    get(mirror, id)
+}
+
+async fn require_album(d1: &D1Client, id: i64) -> Result<(), AppError> {
+    if d1
+        .query(D1Statement::new("SELECT id FROM albums WHERE id = ?").bind(id))
+        .await?
+        .is_empty()
+    {
+        return Err(not_found(id));
+    }
+    Ok(())
+}
+
+async fn check_conflict(d1: &D1Client, name: &str, id: Option<i64>) -> Result<(), AppError> {
+    let statement = D1Statement::new("SELECT id FROM albums WHERE name = ? AND id != ?")
+        .bind(name)
+        .bind(id.unwrap_or(0));
+    if !d1.query(statement).await?.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "已存在名为「{}」的相册",
+            name
+        )));
+    }
+    Ok(())
+}
+
+async fn ids_in_order(d1: &D1Client, statement: D1Statement) -> Result<Vec<i64>, AppError> {
+    Ok(d1
+        .query(statement)
+        .await?
+        .iter()
+        .filter_map(|row| row["id"].as_i64())
+        .collect())
+}
+
+/// `first` 按给定顺序排在前面，`current` 中其余的保持原有相对顺序；`first` 中的每一项都必须在 `current` 中
+fn new_order(
+    current: &[i64],
+    first: &[i64],
+    missing: impl Fn(i64) -> AppError,
+) -> Result<Vec<i64>, AppError> {
+    let existing: BTreeSet<i64> = current.iter().copied().collect();
+    if let Some(id) = first.iter().find(|id| !existing.contains(id)) {
+        return Err(missing(*id));
+    }
+    let moved: BTreeSet<i64> = first.iter().copied().collect();
+    let mut order = first.to_vec();
+    order.extend(current.iter().filter(|id| !moved.contains(id)));
+    Ok(order)
+}
+
+/// 更新相册的修改时间
+fn touch(id: i64) -> D1Statement {
+    D1Statement::new("UPDATE albums SET updated_at = datetime('now') WHERE id = ?").bind(id)
+}
+
+/// `(id, start + 序号)` 的 VALUES 列表及其参数
+fn positions(ids: &[i64], start: usize) -> (String, Vec<D1Param>) {
+    let values = vec!["(?, ?)"; ids.len()].join(", ");
+    let params = ids
+        .iter()
+        .enumerate()
+        .flat_map(|(index, id)| [D1Param::from(*id), D1Param::from((start + index) as i64)])
+        .collect();
+    (values, params)
+}
+
+fn text(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string)
+}
+
+fn not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("相册 {} 不存在", id))
+}
+
+fn validate_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("相册名称不能为空".to_string()));
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(AppError::InvalidInput(format!(
+            "相册名称不能超过 {} 个字符",
+            MAX_NAME_CHARS
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/// 去掉重复的 id，保留第一次出现的位置
+fn validate_picture_ids(ids: &[i64]) -> Result<Vec<i64>, AppError> {
+    let mut seen = BTreeSet::new();
+    let ids: Vec<i64> = ids.iter().copied().filter(|id| seen.insert(*id)).collect();
+    if ids.is_empty() {
+        return Err(AppError::InvalidInput("请至少选择一项".to_string()));
+    }
+    Ok(ids)
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
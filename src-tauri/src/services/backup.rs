@@ -0,0 +1,597 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::AppError;
+use crate::models::{BackupFormat, BackupReport, D1Param, D1Statement, RestoreReport, TableCount};
+use crate::services::crypto::{decrypt_password, encrypt_password};
+use crate::services::d1::{D1Client, MAX_BOUND_PARAMS};
+use crate::services::migrations;
+use crate::services::mirror::json_to_sql;
+
+/// 备份文件中记录来源信息的表，恢复时不写入数据库
+const META_TABLE: &str = "smflare_backup";
+
+/// 由迁移维护的表，恢复时由目标数据库自己的迁移生成
+const MIGRATIONS_TABLE: &str = "schema_migrations";
+
+/// 每次请求读取的行数
+const DUMP_PAGE_SIZE: i64 = 500;
+
+/// 分页时读取的 rowid 列名
+const ROWID_COLUMN: &str = "_backup_rowid";
+
+/// SQLite 数据库文件头
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// 按数据库密钥加密的列，恢复到其他数据库时需要重新加密
+const ENCRYPTED_COLUMNS: &[(&str, &str)] = &[
+    ("smms_user", "encrypted_password"),
+    ("smms_user", "encrypted_api_token"),
+];
+
+/// 重新加密凭证时的（来源, 目标）密钥材料
+type Rekey = ((String, String), (String, String));
+
+/// 排除 SQLite 和 D1 的内部表
+const USER_TABLES: &str =
+    "name NOT LIKE 'sqlite\\_%' ESCAPE '\\' AND name NOT LIKE '\\_cf\\_%' ESCAPE '\\'";
+
+/// 一张表的结构
+struct TableSchema {
+    name: String,
+    /// 建表语句
+    sql: String,
+    columns: Vec<String>,
+    /// 备份中的行数
+    rows: i64,
+}
+
+#[derive(Deserialize)]
+struct SchemaEntry {
+    name: String,
+    sql: String,
+}
+
+#[derive(Deserialize)]
+struct Count {
+    count: i64,
+}
+
+/// 把当前数据库的全部表写入独立的 SQLite 文件或 SQL 文本
+///
+/// 逐表按 rowid 分页读取，先在内存中组装完整的备份，全部读取成功后才写入 `path`。
+pub async fn dump(
+    d1: &D1Client,
+    path: &Path,
+    format: BackupFormat,
+) -> Result<BackupReport, AppError> {
+    migrations::ensure_ready(d1).await?;
+    let schema_version = migrations::schema_version(d1).await?.current;
+    let key_material = d1.store()?.exportable_key_material();
+
+    let backup = Connection::open_in_memory().map_err(backup_error)?;
+    backup
+        .execute_batch(&format!(
+            "CREATE TABLE {META_TABLE} (key TEXT PRIMARY KEY, value TEXT);
+             INSERT INTO {META_TABLE} (key, value) VALUES ('created_at', datetime('now'));"
+        ))
+        .map_err(backup_error)?;
+    // 凭证按来源数据库的密钥加密，恢复到其他数据库时据此重新加密；
+    // 本地后端的密钥是机密，不写入备份
+    let mut meta = vec![
+        ("schema_version", schema_version.to_string()),
+        ("source", d1.source()?),
+    ];
+    if let Some((account_id, database_id)) = key_material {
+        meta.extend([("account_id", account_id), ("database_id", database_id)]);
+    }
+    for (key, value) in meta {
+        backup
+            .execute(
+                &format!("INSERT INTO {META_TABLE} (key, value) VALUES (?, ?)"),
+                [key, value.as_str()],
+            )
+            .map_err(backup_error)?;
+    }
+
+    let mut tables = Vec::new();
+    for table in remote_tables(d1).await? {
+        backup.execute(&table.sql, []).map_err(backup_error)?;
+
+        let columns = quoted_columns(&table.columns);
+        let select = format!(
+            "SELECT rowid AS {ROWID_COLUMN}, {columns} FROM {} WHERE rowid > ? ORDER BY rowid LIMIT ?",
+            quote_ident(&table.name)
+        );
+        let insert = format!(
+            "INSERT INTO {} ({columns}) VALUES ({})",
+            quote_ident(&table.name),
+            vec!["?"; table.columns.len()].join(", ")
+        );
+
+        let mut rows = 0;
+        let mut after = i64::MIN;
+        loop {
+            let page = d1
+                .query(D1Statement::new(&select).bind(after).bind(DUMP_PAGE_SIZE))
+                .await
+                .map_err(|e| e.context(&format!("读取 {} 表失败", table.name)))?;
+            for row in &page {
+                let values = table
+                    .columns
+                    .iter()
+                    .map(|column| json_to_sql(row.get(column).unwrap_or(&Value::Null)));
+                backup
+                    .execute(&insert, params_from_iter(values))
+                    .map_err(backup_error)?;
+            }
+            rows += page.len() as i64;
+            match page.last().and_then(|row| row[ROWID_COLUMN].as_i64()) {
+                Some(rowid) if page.len() as i64 == DUMP_PAGE_SIZE => after = rowid,
+                _ => break,
+            }
+        }
+        tables.push(TableCount {
+            table: table.name,
+            rows,
+        });
+    }
+
+    for sql in remote_indexes(d1).await? {
+        backup.execute(&sql, []).map_err(backup_error)?;
+    }
+
+    match format {
+        BackupFormat::Sqlite => {
+            // `VACUUM INTO` 不会覆盖已有文件
+            if path.exists() {
+                fs::remove_file(path)
+                    .map_err(|e| AppError::Io(format!("覆盖备份文件失败: {}", e)))?;
+            }
+            backup
+                .execute("VACUUM INTO ?", [path.to_string_lossy()])
+                .map_err(backup_error)?;
+        }
+        BackupFormat::Sql => fs::write(path, render_sql(&backup)?)
+            .map_err(|e| AppError::Io(format!("写入备份文件失败: {}", e)))?,
+    }
+
+    Ok(BackupReport {
+        path: path.to_string_lossy().into_owned(),
+        format,
+        schema_version,
+        tables,
+    })
+}
+
+/// 把备份（SQLite 文件或 SQL 文本）恢复到当前数据库
+///
+/// 只能恢复到空数据库：备份中的表在目标数据库中不能有数据。目标数据库先迁移到最新结构，
+/// 写入全部行后逐表核对行数；凭证按目标数据库的密钥重新加密。写入中途失败时清空已写入的表，
+/// 目标数据库仍是空的，可以直接重试。
+pub async fn restore(d1: &D1Client, path: &Path) -> Result<RestoreReport, AppError> {
+    let backup = open_backup(path)?;
+    let meta = read_meta(&backup)?;
+    let schema_version: u32 = meta
+        .get("schema_version")
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| AppError::InvalidInput("备份文件缺少结构版本".to_string()))?;
+    if schema_version > migrations::latest_version() {
+        return Err(AppError::Schema(format!(
+            "备份的结构版本 {} 高于当前应用支持的版本 {}，请升级应用",
+            schema_version,
+            migrations::latest_version()
+        )));
+    }
+    let tables = backup_tables(&backup)?;
+    let indexes = schema_entries(&backup, "index")?;
+
+    migrations::ensure_ready(d1).await?;
+    // 写入前检查全部表，避免恢复到一半才发现目标数据库不为空
+    let mut missing = Vec::new();
+    for table in &tables {
+        if !remote_exists(d1, "table", &table.name).await? {
+            missing.push(table.name.clone());
+        } else if remote_count(d1, &table.name).await? > 0 {
+            return Err(AppError::InvalidInput(format!(
+                "目标数据库的 {} 表已有数据，只能恢复到空数据库",
+                table.name
+            )));
+        }
+    }
+
+    let rekey = match (meta.get("account_id"), meta.get("database_id")) {
+        (Some(account_id), Some(database_id)) => {
+            let target = d1.store()?.key_material();
+            let source = (account_id.clone(), database_id.clone());
+            (source != target).then_some((source, target))
+        }
+        _ => None,
+    };
+
+    let mut written = Vec::new();
+    for table in &tables {
+        if missing.contains(&table.name) {
+            d1.query(D1Statement::new(&table.sql)).await?;
+        }
+        written.push(table.name.as_str());
+        if let Err(error) = restore_rows(d1, &backup, table, rekey.as_ref()).await {
+            return Err(roll_back(d1, &written, error).await);
+        }
+    }
+    for entry in indexes {
+        if !remote_exists(d1, "index", &entry.name).await? {
+            d1.query(D1Statement::new(entry.sql)).await?;
+        }
+    }
+
+    let mut counts = Vec::new();
+    for table in tables {
+        let rows = remote_count(d1, &table.name).await?;
+        if rows != table.rows {
+            return Err(AppError::D1 {
+                code: None,
+                message: format!(
+                    "恢复后 {} 表有 {} 行，备份中有 {} 行",
+                    table.name, rows, table.rows
+                ),
+                statement: None,
+            });
+        }
+        counts.push(TableCount {
+            table: table.name,
+            rows,
+        });
+    }
+
+    Ok(RestoreReport {
+        schema_version,
+        tables: counts,
+    })
+}
+
+/// 写入一张表的全部行；各批之间没有事务，失败时已写入的批次会保留
+async fn restore_rows(
+    d1: &D1Client,
+    backup: &Connection,
+    table: &TableSchema,
+    rekey: Option<&Rekey>,
+) -> Result<(), AppError> {
+    let statements = insert_statements(backup, table, rekey)?;
+    d1.batch(statements)
+        .await?
+        .check(|index| format!("恢复 {} 表的第 {} 批数据", table.name, index + 1))?;
+    Ok(())
+}
+
+/// 恢复失败时清空本次写入过的表，使目标数据库回到恢复前的空状态、可以重试
+///
+/// 恢复前已确认这些表为空，表中的行都是本次写入的。
+async fn roll_back(d1: &D1Client, tables: &[&str], error: AppError) -> AppError {
+    let statements = tables
+        .iter()
+        .map(|table| D1Statement::new(format!("DELETE FROM {}", quote_ident(table))))
+        .collect();
+    let cleared = d1
+        .batch(statements)
+        .await
+        .and_then(|result| result.check(|index| format!("清空 {} 表", tables[index])));
+    match cleared {
+        Ok(()) => error,
+        Err(cleanup) => AppError::D1 {
+            code: None,
+            message: format!(
+                "{}；清空已恢复的数据也失败，请手动清空后重试: {}",
+                error, cleanup
+            ),
+            statement: None,
+        },
+    }
+}
+
+/// 当前数据库中的表
+async fn remote_tables(d1: &D1Client) -> Result<Vec<TableSchema>, AppError> {
+    let entries: Vec<SchemaEntry> = d1
+        .query_as(D1Statement::new(format!(
+            "SELECT name, sql FROM sqlite_master WHERE type = 'table' AND {USER_TABLES} ORDER BY name"
+        )))
+        .await?;
+
+    let mut tables = Vec::new();
+    for entry in entries {
+        #[derive(Deserialize)]
+        struct Column {
+            name: String,
+        }
+        let columns: Vec<Column> = d1
+            .query_as(
+                D1Statement::new("SELECT name FROM pragma_table_info(?) ORDER BY cid")
+                    .bind(&entry.name),
+            )
+            .await?;
+        tables.push(TableSchema {
+            name: entry.name,
+            sql: entry.sql,
+            columns: columns.into_iter().map(|c| c.name).collect(),
+            rows: 0,
+        });
+    }
+    Ok(tables)
+}
+
+/// 当前数据库中用户表上的索引（不含主键、唯一约束自动创建的索引）
+async fn remote_indexes(d1: &D1Client) -> Result<Vec<String>, AppError> {
+    #[derive(Deserialize)]
+    struct Index {
+        sql: String,
+    }
+
+    let indexes: Vec<Index> = d1
+        .query_as(D1Statement::new(format!(
+            "SELECT sql FROM sqlite_master WHERE type = 'index' AND sql IS NOT NULL AND {} ORDER BY name",
+            USER_TABLES.replace("name", "tbl_name")
+        )))
+        .await?;
+    Ok(indexes.into_iter().map(|index| index.sql).collect())
+}
+
+async fn remote_exists(d1: &D1Client, kind: &str, name: &str) -> Result<bool, AppError> {
+    let rows: Vec<Count> = d1
+        .query_as(
+            D1Statement::new(
+                "SELECT COUNT(*) AS count FROM sqlite_master WHERE type = ? AND name = ?",
+            )
+            .bind(kind)
+            .bind(name),
+        )
+        .await?;
+    Ok(rows.first().is_some_and(|row| row.count > 0))
+}
+
+async fn remote_count(d1: &D1Client, table: &str) -> Result<i64, AppError> {
+    let rows: Vec<Count> = d1
+        .query_as(D1Statement::new(format!(
+            "SELECT COUNT(*) AS count FROM {}",
+            quote_ident(table)
+        )))
+        .await?;
+    Ok(rows.first().map_or(0, |row| row.count))
+}
+
+/// 打开备份文件，SQL 文本先载入内存数据库
+fn open_backup(path: &Path) -> Result<Connection, AppError> {
+    let io_error = |e: std::io::Error| AppError::Io(format!("读取备份文件失败: {}", e));
+    let mut header = Vec::new();
+    File::open(path)
+        .map_err(io_error)?
+        .take(SQLITE_HEADER.len() as u64)
+        .read_to_end(&mut header)
+        .map_err(io_error)?;
+
+    if header == SQLITE_HEADER {
+        return Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(backup_error);
+    }
+
+    let sql = fs::read(path).map_err(io_error)?;
+    let sql = String::from_utf8(sql).map_err(|_| {
+        AppError::InvalidInput("备份文件既不是 SQLite 数据库也不是 SQL 文本".to_string())
+    })?;
+    let conn = Connection::open_in_memory().map_err(backup_error)?;
+    conn.execute_batch(&sql)
+        .map_err(|e| AppError::InvalidInput(format!("备份文件中的 SQL 无法执行: {}", e)))?;
+    Ok(conn)
+}
+
+fn read_meta(backup: &Connection) -> Result<HashMap<String, String>, AppError> {
+    let exists = schema_entries(backup, "table")?
+        .iter()
+        .any(|entry| entry.name == META_TABLE);
+    if !exists {
+        return Err(AppError::InvalidInput(
+            "不是 SMFlare 导出的备份文件".to_string(),
+        ));
+    }
+
+    let mut stmt = backup
+        .prepare(&format!("SELECT key, value FROM {META_TABLE}"))
+        .map_err(backup_error)?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(backup_error)?;
+    rows.collect::<Result<_, _>>().map_err(backup_error)
+}
+
+/// 备份中需要恢复的表
+fn backup_tables(backup: &Connection) -> Result<Vec<TableSchema>, AppError> {
+    let mut tables = Vec::new();
+    for entry in schema_entries(backup, "table")? {
+        if entry.name == META_TABLE || entry.name == MIGRATIONS_TABLE {
+            continue;
+        }
+        let columns = {
+            let mut stmt = backup
+                .prepare("SELECT name FROM pragma_table_info(?) ORDER BY cid")
+                .map_err(backup_error)?;
+            let names = stmt
+                .query_map([&entry.name], |row| row.get(0))
+                .map_err(backup_error)?;
+            names
+                .collect::<Result<Vec<String>, _>>()
+                .map_err(backup_error)?
+        };
+        let rows = backup
+            .query_row(
+                &format!("SELECT COUNT(*) FROM {}", quote_ident(&entry.name)),
+                [],
+                |row| row.get(0),
+            )
+            .map_err(backup_error)?;
+        tables.push(TableSchema {
+            name: entry.name,
+            sql: entry.sql,
+            columns,
+            rows,
+        });
+    }
+    Ok(tables)
+}
+
+fn schema_entries(conn: &Connection, kind: &str) -> Result<Vec<SchemaEntry>, AppError> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT name, sql FROM sqlite_master WHERE type = ? AND sql IS NOT NULL AND {} ORDER BY rowid",
+            USER_TABLES.replace("name", "tbl_name")
+        ))
+        .map_err(backup_error)?;
+    let entries = stmt
+        .query_map([kind], |row| {
+            Ok(SchemaEntry {
+                name: row.get(0)?,
+                sql: row.get(1)?,
+            })
+        })
+        .map_err(backup_error)?;
+    entries.collect::<Result<_, _>>().map_err(backup_error)
+}
+
+/// 把备份中一张表的行转换为多行 `INSERT`，每条语句的参数不超过 D1 的上限
+fn insert_statements(
+    backup: &Connection,
+    table: &TableSchema,
+    rekey: Option<&Rekey>,
+) -> Result<Vec<D1Statement>, AppError> {
+    let columns = quoted_columns(&table.columns);
+    let encrypted: Vec<bool> = table
+        .columns
+        .iter()
+        .map(|column| ENCRYPTED_COLUMNS.contains(&(table.name.as_str(), column.as_str())))
+        .collect();
+
+    let mut stmt = backup
+        .prepare(&format!(
+            "SELECT {columns} FROM {} ORDER BY rowid",
+            quote_ident(&table.name)
+        ))
+        .map_err(backup_error)?;
+    let mut rows = stmt.query([]).map_err(backup_error)?;
+    let mut values: Vec<Vec<D1Param>> = Vec::new();
+    while let Some(row) = rows.next().map_err(backup_error)? {
+        let mut params = Vec::with_capacity(table.columns.len());
+        for (index, is_encrypted) in encrypted.iter().enumerate() {
+            let mut param = to_param(row.get_ref(index).map_err(backup_error)?)?;
+            if let (true, Some((source, target)), D1Param::Text(secret)) =
+                (*is_encrypted, rekey, &param)
+            {
+                if !secret.is_empty() {
+                    let plain = decrypt_password(secret, &source.0, &source.1)?;
+                    param = D1Param::Text(encrypt_password(&plain, &target.0, &target.1)?);
+                }
+            }
+            params.push(param);
+        }
+        values.push(params);
+    }
+
+    let placeholders = format!("({})", vec!["?"; table.columns.len()].join(", "));
+    let rows_per_statement = (MAX_BOUND_PARAMS / table.columns.len().max(1)).max(1);
+    Ok(values
+        .chunks(rows_per_statement)
+        .map(|chunk| D1Statement {
+            sql: format!(
+                "INSERT INTO {} ({columns}) VALUES {}",
+                quote_ident(&table.name),
+                vec![placeholders.as_str(); chunk.len()].join(", ")
+            ),
+            params: chunk.concat(),
+        })
+        .collect())
+}
+
+/// 生成 SQL 文本备份，可用 `sqlite3 new.db < backup.sql` 还原
+fn render_sql(backup: &Connection) -> Result<String, AppError> {
+    let mut out =
+        String::from("-- SMFlare 数据库备份\nPRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\n");
+    for entry in schema_entries(backup, "table")? {
+        let _ = writeln!(out, "{};", entry.sql);
+        let mut stmt = backup
+            .prepare(&format!(
+                "SELECT * FROM {} ORDER BY rowid",
+                quote_ident(&entry.name)
+            ))
+            .map_err(backup_error)?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let columns = quoted_columns(&columns);
+        let mut rows = stmt.query([]).map_err(backup_error)?;
+        while let Some(row) = rows.next().map_err(backup_error)? {
+            let values = (0..row.as_ref().column_count())
+                .map(|index| row.get_ref(index).map(sql_literal))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(backup_error)?;
+            let _ = writeln!(
+                out,
+                "INSERT INTO {} ({columns}) VALUES ({});",
+                quote_ident(&entry.name),
+                values.join(", ")
+            );
+        }
+    }
+    for entry in schema_entries(backup, "index")? {
+        let _ = writeln!(out, "{};", entry.sql);
+    }
+    out.push_str("COMMIT;\n");
+    Ok(out)
+}
+
+fn sql_literal(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => format!("{:?}", f),
+        ValueRef::Text(t) => format!("'{}'", String::from_utf8_lossy(t).replace('\'', "''")),
+        ValueRef::Blob(b) => format!(
+            "X'{}'",
+            b.iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        ),
+    }
+}
+
+fn to_param(value: ValueRef<'_>) -> Result<D1Param, AppError> {
+    Ok(match SqlValue::from(value) {
+        SqlValue::Null => D1Param::Null,
+        SqlValue::Integer(i) => D1Param::Integer(i),
+        SqlValue::Real(f) => D1Param::Real(f),
+        SqlValue::Text(s) => D1Param::Text(s),
+        SqlValue::Blob(_) => {
+            return Err(AppError::InvalidInput(
+                "备份中包含二进制数据，无法写入 D1".to_string(),
+            ))
+        }
+    })
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quoted_columns(columns: &[String]) -> String {
+    columns
+        .iter()
+        .map(|column| quote_ident(column))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn backup_error(error: rusqlite::Error) -> AppError {
+    AppError::LocalDb(format!("备份文件读写失败: {}", error))
+}
@@ -1,31 +1,186 @@
 use std::fs;
-use std::path::PathBuf;
-use std::sync::RwLock;
+use std::path::{Path, PathBuf};
 
-use crate::models::D1Config;
+use serde::Deserialize;
 
-/// 缓存 D1 配置，减少重复的文件和密钥链读取
-pub static D1_CONFIG_CACHE: RwLock<Option<D1Config>> = RwLock::new(None);
+use crate::error::AppError;
+use crate::models::{AppSettings, D1Config};
 
-/// 获取配置文件路径
-pub fn get_config_path() -> Result<PathBuf, String> {
-    let mut path = dirs::config_dir().ok_or("无法获取配置目录")?;
-    path.push("tauri-app");
-    fs::create_dir_all(&path).map_err(|e| format!("创建配置目录失败: {}", e))?;
-    path.push("d1_config.json");
-    Ok(path)
+/// 本地后端凭证加密密钥的文件名
+pub const LOCAL_KEY_FILE: &str = "local.key";
+
+/// SM.MS API 默认地址
+pub const DEFAULT_SMMS_API_BASE: &str = "https://sm.ms/api/v2";
+/// Cloudflare API 默认地址
+pub const DEFAULT_CLOUDFLARE_API_BASE: &str = "https://api.cloudflare.com/client/v4";
+
+/// 覆盖配置目录的环境变量
+pub const CONFIG_DIR_ENV: &str = "SMFLARE_CONFIG_DIR";
+/// 覆盖 SM.MS API 地址的环境变量
+pub const SMMS_API_BASE_ENV: &str = "SMFLARE_SMMS_API_BASE";
+/// 覆盖 Cloudflare API 地址的环境变量
+pub const CLOUDFLARE_API_BASE_ENV: &str = "SMFLARE_CLOUDFLARE_API_BASE";
+
+/// 外部 API 基础地址（可替换为本地 mock 服务）
+#[derive(Clone, Debug)]
+pub struct ApiEndpoints {
+    pub smms: String,
+    pub cloudflare: String,
 }
 
-/// 更新 D1 配置缓存
-pub fn update_d1_config_cache(config: D1Config) {
-    if let Ok(mut cache) = D1_CONFIG_CACHE.write() {
-        *cache = Some(config);
+impl Default for ApiEndpoints {
+    fn default() -> Self {
+        Self {
+            smms: DEFAULT_SMMS_API_BASE.to_string(),
+            cloudflare: DEFAULT_CLOUDFLARE_API_BASE.to_string(),
+        }
     }
 }
 
-/// 清除 D1 配置缓存
-pub fn clear_d1_config_cache() {
-    if let Ok(mut cache) = D1_CONFIG_CACHE.write() {
-        *cache = None;
+impl ApiEndpoints {
+    /// 使用同一个基础地址（如 mock 服务）构建 SM.MS 和 Cloudflare 地址
+    pub fn with_base(base: &str) -> Self {
+        let base = base.trim_end_matches('/');
+        Self {
+            smms: format!("{}/api/v2", base),
+            cloudflare: format!("{}/client/v4", base),
+        }
+    }
+}
+
+/// `endpoints.json` 文件内容，字段均可省略
+#[derive(Deserialize, Default)]
+struct EndpointsFile {
+    smms_api_base: Option<String>,
+    cloudflare_api_base: Option<String>,
+}
+
+/// 本地配置目录，作为 Tauri 状态管理
+pub struct ConfigStore {
+    dir: PathBuf,
+}
+
+impl ConfigStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 默认配置目录，可通过 `SMFLARE_CONFIG_DIR` 覆盖
+    pub fn from_env() -> Result<Self, AppError> {
+        if let Some(dir) = std::env::var_os(CONFIG_DIR_ENV) {
+            return Ok(Self::new(dir));
+        }
+        let mut path =
+            dirs::config_dir().ok_or_else(|| AppError::Io("无法获取配置目录".to_string()))?;
+        path.push("tauri-app");
+        Ok(Self::new(path))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 获取配置目录下的文件路径（目录不存在时自动创建）
+    pub fn path_of(&self, file_name: &str) -> Result<PathBuf, AppError> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| AppError::Io(format!("创建配置目录失败: {}", e)))?;
+        Ok(self.dir.join(file_name))
+    }
+
+    /// 读取 API 地址：环境变量优先，其次 `endpoints.json`，最后使用默认值
+    pub fn api_endpoints(&self) -> ApiEndpoints {
+        let file: EndpointsFile = fs::read_to_string(self.dir.join("endpoints.json"))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        let defaults = ApiEndpoints::default();
+        ApiEndpoints {
+            smms: std::env::var(SMMS_API_BASE_ENV)
+                .ok()
+                .or(file.smms_api_base)
+                .unwrap_or(defaults.smms),
+            cloudflare: std::env::var(CLOUDFLARE_API_BASE_ENV)
+                .ok()
+                .or(file.cloudflare_api_base)
+                .unwrap_or(defaults.cloudflare),
+        }
+    }
+
+    /// 从配置文件读取 D1 配置
+    pub fn read_d1_config(&self) -> Result<D1Config, AppError> {
+        let config_path = self.path_of("d1_config.json")?;
+        if !config_path.exists() {
+            return Err(AppError::ConfigMissing("配置文件不存在".to_string()));
+        }
+
+        let json = fs::read_to_string(&config_path)
+            .map_err(|e| AppError::Io(format!("读取配置文件失败: {}", e)))?;
+        serde_json::from_str(&json)
+            .map_err(|e| AppError::ConfigInvalid(format!("解析配置文件失败: {}", e)))
+    }
+
+    /// 将 D1 配置写入配置文件
+    pub fn write_d1_config(&self, config: &D1Config) -> Result<(), AppError> {
+        let config_path = self.path_of("d1_config.json")?;
+        let json = serde_json::to_string_pretty(config)
+            .map_err(|e| AppError::InvalidInput(format!("序列化配置失败: {}", e)))?;
+
+        fs::write(&config_path, json).map_err(|e| AppError::Io(format!("写入配置文件失败: {}", e)))
+    }
+
+    /// 删除配置文件
+    pub fn remove_d1_config(&self) -> Result<(), AppError> {
+        let config_path = self.path_of("d1_config.json")?;
+        if config_path.exists() {
+            fs::remove_file(&config_path)
+                .map_err(|e| AppError::Io(format!("删除配置文件失败: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    /// 读取本地后端的凭证加密密钥，不存在时随机生成并保存；返回密钥及是否刚生成
+    ///
+    /// 密钥只保存在本机配置目录，本地数据库文件和备份中不包含它。
+    pub fn local_key(&self) -> Result<(String, bool), AppError> {
+        let path = self.path_of(LOCAL_KEY_FILE)?;
+        if path.exists() {
+            let key = fs::read_to_string(&path)
+                .map_err(|e| AppError::Io(format!("读取本地密钥失败: {}", e)))?;
+            return Ok((key.trim().to_string(), false));
+        }
+
+        let key: String = rand::random::<[u8; 32]>()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        fs::write(&path, &key).map_err(|e| AppError::Io(format!("写入本地密钥失败: {}", e)))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+                .map_err(|e| AppError::Io(format!("设置本地密钥权限失败: {}", e)))?;
+        }
+        Ok((key, true))
+    }
+
+    /// 读取应用设置，文件不存在时使用默认值
+    pub fn read_settings(&self) -> Result<AppSettings, AppError> {
+        let path = self.path_of("settings.json")?;
+        if !path.exists() {
+            return Ok(AppSettings::default());
+        }
+        let json =
+            fs::read_to_string(&path).map_err(|e| AppError::Io(format!("读取设置失败: {}", e)))?;
+        serde_json::from_str(&json)
+            .map_err(|e| AppError::ConfigInvalid(format!("解析设置失败: {}", e)))
+    }
+
+    /// 写入应用设置
+    pub fn write_settings(&self, settings: &AppSettings) -> Result<(), AppError> {
+        let path = self.path_of("settings.json")?;
+        let json = serde_json::to_string_pretty(settings)
+            .map_err(|e| AppError::InvalidInput(format!("序列化设置失败: {}", e)))?;
+        fs::write(&path, json).map_err(|e| AppError::Io(format!("写入设置失败: {}", e)))
     }
 }
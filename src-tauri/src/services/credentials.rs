@@ -0,0 +1,65 @@
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::models::{D1Statement, SmmsUser};
+use crate::services::crypto::decrypt_password;
+use crate::services::d1::D1Client;
+
+/// `smms_user` 表中保存的凭证
+#[derive(Deserialize)]
+struct StoredUser {
+    username: String,
+    encrypted_password: String,
+    encrypted_api_token: Option<String>,
+}
+
+/// 从 D1 读取并解密 SM.MS 凭证，未指定用户名时取最早保存的用户
+pub async fn load_user(d1: &D1Client, username: Option<String>) -> Result<SmmsUser, AppError> {
+    // 派生解密密钥的材料（D1 后端为账号和数据库 ID）
+    let (account_id, database_id) = d1.store()?.key_material();
+
+    let statement = if let Some(user) = username.filter(|u| !u.trim().is_empty()) {
+        D1Statement::new(
+            "SELECT username, encrypted_password, encrypted_api_token \
+             FROM smms_user WHERE username = ? LIMIT 1",
+        )
+        .bind(user)
+    } else {
+        D1Statement::new(
+            "SELECT username, encrypted_password, encrypted_api_token \
+             FROM smms_user ORDER BY id ASC LIMIT 1",
+        )
+    };
+
+    let record = d1
+        .query_as::<StoredUser>(statement)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::AuthRequired("未找到 SM.MS 凭证".to_string()))?;
+
+    // 解密密码和 token
+    let password = decrypt_password(&record.encrypted_password, &account_id, &database_id)?;
+
+    let token = match record.encrypted_api_token.filter(|t| !t.is_empty()) {
+        Some(encrypted_token) => decrypt_password(&encrypted_token, &account_id, &database_id)?,
+        None => String::new(),
+    };
+
+    Ok(SmmsUser {
+        username: record.username,
+        password,
+        token,
+    })
+}
+
+/// 读取当前用户的 API Token，尚未登录时返回 `AuthRequired`
+pub async fn load_token(d1: &D1Client) -> Result<String, AppError> {
+    let user = load_user(d1, None).await?;
+    if user.token.is_empty() {
+        return Err(AppError::AuthRequired(
+            "请先登录 SM.MS 获取 token".to_string(),
+        ));
+    }
+    Ok(user.token)
+}
@@ -0,0 +1,558 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::StatusCode;
+
+use crate::error::AppError;
+
+use crate::models::{
+    D1BatchResult, D1Config, D1QueryMeta, D1QueryOutput, D1QueryResult, D1Response, D1Statement,
+    D1StatementResult, StorageKind,
+};
+use crate::services::retry::{AttemptError, Idempotency, RetryPolicy};
+use crate::services::rows;
+use crate::services::store::{MetadataStore, StoreFuture};
+use crate::services::usage::UsageMeter;
+
+/// D1 单条语句的绑定参数上限
+pub(crate) const MAX_BOUND_PARAMS: usize = 100;
+/// D1 单条 SQL 语句的长度上限（字节）
+const MAX_SQL_BYTES: usize = 100_000;
+/// 单次 batch 请求最多包含的语句数
+const MAX_BATCH_STATEMENTS: usize = 100;
+/// 单次 batch 请求体的大小上限（字节）
+const MAX_BATCH_BYTES: usize = 1024 * 1024;
+
+/// Cloudflare D1 存储后端，由 D1 配置构建
+pub struct D1Store {
+    http: reqwest::Client,
+    retry: RetryPolicy,
+    config: D1Config,
+    endpoint: String,
+    auth_header: HeaderValue,
+    /// 该配置对应的数据库结构是否已迁移到最新（配置变更时随存储一起重建）
+    schema_ready: Arc<AtomicBool>,
+}
+
+impl D1Store {
+    fn new(
+        http: reqwest::Client,
+        retry: RetryPolicy,
+        api_base: &str,
+        config: D1Config,
+    ) -> Result<Self, AppError> {
+        let endpoint = format!(
+            "{}/accounts/{}/d1/database/{}/query",
+            api_base, config.account_id, config.database_id
+        );
+        let auth_header = HeaderValue::from_str(&format!("Bearer {}", config.api_token))
+            .map_err(|_| AppError::ConfigInvalid("API Token 格式无效".to_string()))?;
+
+        Ok(Self {
+            http,
+            retry,
+            config,
+            endpoint,
+            auth_header,
+            schema_ready: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    async fn query_remote(&self, statement: D1Statement) -> Result<D1QueryOutput, AppError> {
+        check_limits(&statement).map_err(|e| e.context("查询失败"))?;
+        let idempotency = statement_idempotency(&statement.sql);
+        let result = self
+            .send(&statement, idempotency)
+            .await
+            .and_then(ensure_success)
+            .map_err(|e| e.context("查询失败"))?;
+
+        let (results, meta) = result
+            .result
+            .and_then(|results| results.into_iter().next())
+            .map(|first| (first.results, first.meta.unwrap_or_default()))
+            .unwrap_or_default();
+        Ok(D1QueryOutput { results, meta })
+    }
+
+    /// 语句作为独立条目发送，并按 D1 的请求限制自动拆分成多个 batch，每个 batch 是一个事务。
+    /// 某个 batch 被拒绝时二分重试以定位具体语句：该语句之前的语句照常提交，之后的不再执行。
+    async fn batch_remote(&self, statements: Vec<D1Statement>) -> Result<D1BatchResult, AppError> {
+        let mut outcome = D1BatchResult::default();
+
+        // 超出单条语句限制的直接拒绝，整个批量都不执行
+        for (index, statement) in statements.iter().enumerate() {
+            if let Err(error) = check_limits(statement) {
+                outcome.failed_index = Some(index);
+                outcome.error = Some(error);
+                return Ok(outcome);
+            }
+        }
+
+        for range in split_batch(&statements) {
+            if !self.run_chunk(&statements, range, &mut outcome).await {
+                break;
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// 执行一个 batch，返回是否全部成功
+    async fn run_chunk(
+        &self,
+        statements: &[D1Statement],
+        mut range: Range<usize>,
+        outcome: &mut D1BatchResult,
+    ) -> bool {
+        // `rejected` 非空表示 `range` 整体会被拒绝，接下来只发送前半段
+        let mut rejected: Option<AppError> = None;
+        loop {
+            if let Some(error) = rejected.as_ref().filter(|_| range.len() == 1) {
+                push_failure(outcome, range.start, error.clone());
+                return false;
+            }
+
+            let end = match rejected {
+                Some(_) => range.start + range.len() / 2,
+                None => range.end,
+            };
+            let chunk = &statements[range.start..end];
+            let body = serde_json::json!({ "batch": chunk });
+            let response = match self.send(&body, batch_idempotency(chunk)).await {
+                Ok(response) => response,
+                Err(error) => {
+                    // 请求本身失败，无法确定是哪条语句的问题
+                    push_failure(outcome, range.start, error.context("批量执行失败"));
+                    return false;
+                }
+            };
+
+            if response.success {
+                let results = response.result.unwrap_or_default();
+                push_results(outcome, range.start, chunk.len(), results);
+                if end == range.end {
+                    return true;
+                }
+                // 前半段已提交，被拒绝的语句在后半段
+                range.start = end;
+            } else {
+                rejected = Some(response_error(&response));
+                range.end = end;
+            }
+        }
+    }
+
+    /// 发送请求并解析响应，限流和网络错误按重试策略处理
+    ///
+    /// D1 返回的语句错误（`success = false`）不会重试，原样交给调用方处理。
+    async fn send<B: serde::Serialize + ?Sized>(
+        &self,
+        body: &B,
+        idempotency: Idempotency,
+    ) -> Result<D1Response, AppError> {
+        let http = &self.http;
+        let endpoint = &self.endpoint;
+        let auth_header = &self.auth_header;
+        self.retry
+            .run(idempotency, || async move {
+                let response = http
+                    .post(endpoint)
+                    .header(AUTHORIZATION, auth_header.clone())
+                    .json(body)
+                    .send()
+                    .await
+                    .map_err(|e| AttemptError::from_send("请求失败", e))?;
+
+                let status = response.status();
+                if let Some(error) = AttemptError::from_status(status, response.headers()) {
+                    return Err(error);
+                }
+
+                let parsed = response.json::<D1Response>().await;
+                if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+                    // Token 无效或没有该数据库的权限，需要用户修改配置
+                    let detail = parsed
+                        .map(|r| error_message(&r))
+                        .unwrap_or_else(|_| format!("HTTP {}", status));
+                    return Err(AttemptError::fatal(AppError::ConfigInvalid(format!(
+                        "D1 认证失败: {}",
+                        detail
+                    ))));
+                }
+                parsed.map_err(|e| AttemptError::from_body("解析响应失败", e))
+            })
+            .await
+            .result
+    }
+}
+
+impl MetadataStore for D1Store {
+    fn kind(&self) -> StorageKind {
+        StorageKind::D1
+    }
+
+    fn billable(&self) -> bool {
+        true
+    }
+
+    fn source(&self) -> String {
+        format!("{}/{}", self.config.account_id, self.config.database_id)
+    }
+
+    fn key_material(&self) -> (String, String) {
+        (
+            self.config.account_id.clone(),
+            self.config.database_id.clone(),
+        )
+    }
+
+    fn schema_ready(&self) -> Arc<AtomicBool> {
+        self.schema_ready.clone()
+    }
+
+    fn d1_config(&self) -> Option<&D1Config> {
+        Some(&self.config)
+    }
+
+    fn query(&self, statement: D1Statement) -> StoreFuture<'_, D1QueryOutput> {
+        Box::pin(self.query_remote(statement))
+    }
+
+    fn batch(&self, statements: Vec<D1Statement>) -> StoreFuture<'_, D1BatchResult> {
+        Box::pin(self.batch_remote(statements))
+    }
+}
+
+/// 元数据客户端，作为 Tauri 状态全局共享
+///
+/// 把语句交给当前启用的存储后端（D1 或本地 SQLite）执行，并统计用量。
+/// D1 后端共用同一个 `reqwest::Client`，所有请求复用连接池；
+/// 配置变更时只替换存储后端，不会重建 HTTP 客户端。
+pub struct D1Client {
+    http: reqwest::Client,
+    api_base: String,
+    retry: RetryPolicy,
+    store: RwLock<Option<Arc<dyn MetadataStore>>>,
+    /// 串行执行结构迁移，避免启动时与保存配置时的迁移并发
+    migrations: tokio::sync::Mutex<()>,
+    usage: UsageMeter,
+}
+
+impl D1Client {
+    /// 创建客户端，`config` 为空时需稍后调用 [`D1Client::reload`] 或 [`D1Client::use_store`]
+    ///
+    /// `api_base` 为 Cloudflare API 基础地址，例如 `https://api.cloudflare.com/client/v4`
+    pub fn new(api_base: impl Into<String>, config: Option<D1Config>) -> Self {
+        let http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(60))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()
+            .unwrap_or_default();
+
+        let client = Self {
+            http,
+            api_base: api_base.into().trim_end_matches('/').to_string(),
+            retry: RetryPolicy::default(),
+            store: RwLock::new(None),
+            migrations: tokio::sync::Mutex::new(()),
+            usage: UsageMeter::default(),
+        };
+        if let Some(config) = config {
+            // 配置文件内容无效时保持未配置状态，由用户重新保存
+            let _ = client.reload(config);
+        }
+        client
+    }
+
+    /// 替换重试策略（已启用的 D1 后端随之重建）
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        if let Ok(config) = self.config() {
+            let _ = self.reload(config);
+        }
+        self
+    }
+
+    /// 使用新的 D1 配置启用 D1 后端
+    pub fn reload(&self, config: D1Config) -> Result<(), AppError> {
+        let store = D1Store::new(
+            self.http.clone(),
+            self.retry.clone(),
+            &self.api_base,
+            config,
+        )?;
+        self.use_store(Arc::new(store));
+        Ok(())
+    }
+
+    /// 启用指定的存储后端
+    pub fn use_store(&self, store: Arc<dyn MetadataStore>) {
+        if let Ok(mut guard) = self.store.write() {
+            *guard = Some(store);
+        }
+    }
+
+    /// 停用存储后端（配置被删除时调用）
+    pub fn clear(&self) {
+        if let Ok(mut guard) = self.store.write() {
+            *guard = None;
+        }
+    }
+
+    /// 当前启用的存储后端
+    pub fn store(&self) -> Result<Arc<dyn MetadataStore>, AppError> {
+        self.store
+            .read()
+            .ok()
+            .and_then(|guard| guard.clone())
+            .ok_or_else(|| AppError::ConfigMissing("配置文件不存在".to_string()))
+    }
+
+    /// 当前启用的后端类型，未配置时为空
+    pub fn kind(&self) -> Option<StorageKind> {
+        self.store().ok().map(|store| store.kind())
+    }
+
+    /// 当前生效的 D1 配置，使用本地后端时返回 `ConfigMissing`
+    pub fn config(&self) -> Result<D1Config, AppError> {
+        self.store()?
+            .d1_config()
+            .cloned()
+            .ok_or_else(|| AppError::ConfigMissing("当前使用本地存储，未启用 D1".to_string()))
+    }
+
+    /// 当前数据库的标识（D1 为 `account_id/database_id`）
+    pub fn source(&self) -> Result<String, AppError> {
+        self.store().map(|store| store.source())
+    }
+
+    /// 当前数据库的结构是否已确认就绪
+    pub fn is_schema_ready(&self) -> bool {
+        self.store()
+            .map(|store| store.schema_ready().load(Ordering::Acquire))
+            .unwrap_or(false)
+    }
+
+    /// 当前数据库的就绪标记，迁移成功后置位；迁移期间后端被替换时只会影响旧后端的标记
+    pub(crate) fn schema_ready_flag(&self) -> Result<Arc<AtomicBool>, AppError> {
+        self.store().map(|store| store.schema_ready())
+    }
+
+    /// 本次会话的用量计数
+    pub fn usage(&self) -> &UsageMeter {
+        &self.usage
+    }
+
+    pub(crate) async fn lock_migrations(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.migrations.lock().await
+    }
+
+    /// 使用指定配置测试连通性（不影响当前后端）
+    pub async fn test_connection(&self, config: D1Config) -> Result<(), AppError> {
+        let store = D1Store::new(
+            self.http.clone(),
+            self.retry.clone(),
+            &self.api_base,
+            config,
+        )?;
+        let body = serde_json::json!({ "sql": "SELECT 1 as test" });
+        store
+            .send(&body, Idempotency::Idempotent)
+            .await
+            .and_then(ensure_success)
+            .map(|_| ())
+            .map_err(|e| e.context("连接失败"))
+    }
+
+    /// 执行单条带绑定参数的语句，返回结果行
+    pub async fn query(&self, statement: D1Statement) -> Result<Vec<serde_json::Value>, AppError> {
+        self.query_with_meta(statement)
+            .await
+            .map(|output| output.results)
+    }
+
+    /// 执行单条语句，同时返回读写行数和耗时
+    pub async fn query_with_meta(&self, statement: D1Statement) -> Result<D1QueryOutput, AppError> {
+        let store = self.store()?;
+        let sql = statement.sql.clone();
+        let output = store.query(statement).await?;
+        if store.billable() {
+            self.usage.record(&sql, &output.meta);
+        }
+        Ok(output)
+    }
+
+    /// 执行查询并把结果行解码为 `T`
+    pub async fn query_as<T: serde::de::DeserializeOwned>(
+        &self,
+        statement: D1Statement,
+    ) -> Result<Vec<T>, AppError> {
+        let rows = self.query(statement).await?;
+        rows::decode_rows(&rows)
+    }
+
+    /// 批量执行语句，返回每条语句的结果
+    ///
+    /// 遇到第一条被拒绝的语句即停止：之前的语句已提交，之后的不再执行。
+    pub async fn batch(&self, statements: Vec<D1Statement>) -> Result<D1BatchResult, AppError> {
+        if statements.is_empty() {
+            return Ok(D1BatchResult::default());
+        }
+
+        let store = self.store()?;
+        let sqls: Vec<String> = statements.iter().map(|s| s.sql.clone()).collect();
+        let mut outcome = store.batch(statements).await?;
+        for result in outcome.results.iter().filter(|r| r.success) {
+            if store.billable() {
+                self.usage.record(&sqls[result.index], &result.meta);
+            }
+            outcome.meta.add(&result.meta);
+        }
+        Ok(outcome)
+    }
+}
+
+fn error_message(response: &D1Response) -> String {
+    response
+        .errors
+        .iter()
+        .map(|e| format!("[{}] {}", e.code, e.message))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn response_error(response: &D1Response) -> AppError {
+    AppError::D1 {
+        code: response.errors.first().map(|e| e.code),
+        message: error_message(response),
+        statement: None,
+    }
+}
+
+fn ensure_success(response: D1Response) -> Result<D1Response, AppError> {
+    if response.success {
+        Ok(response)
+    } else {
+        Err(response_error(&response))
+    }
+}
+
+/// 记录已提交语句的结果
+fn push_results(
+    outcome: &mut D1BatchResult,
+    start: usize,
+    len: usize,
+    results: Vec<D1QueryResult>,
+) {
+    let mut results = results.into_iter();
+    for offset in 0..len {
+        let (rows, meta) = results
+            .next()
+            .map(|result| (result.results, result.meta.unwrap_or_default()))
+            .unwrap_or_default();
+        outcome.results.push(D1StatementResult {
+            index: start + offset,
+            success: true,
+            meta,
+            error: None,
+            results: rows,
+        });
+    }
+}
+
+pub(crate) fn push_failure(outcome: &mut D1BatchResult, index: usize, error: AppError) {
+    let error = match error {
+        AppError::D1 { code, message, .. } => AppError::D1 {
+            code,
+            message,
+            statement: Some(index),
+        },
+        other => other,
+    };
+    outcome.results.push(D1StatementResult {
+        index,
+        success: false,
+        meta: D1QueryMeta::default(),
+        error: Some(error.to_string()),
+        results: Vec::new(),
+    });
+    outcome.failed_index = Some(index);
+    outcome.error = Some(error);
+}
+
+/// 检查单条语句是否超出 D1 限制
+fn check_limits(statement: &D1Statement) -> Result<(), AppError> {
+    if statement.params.len() > MAX_BOUND_PARAMS {
+        return Err(AppError::InvalidInput(format!(
+            "绑定参数 {} 个，超过 D1 上限 {} 个",
+            statement.params.len(),
+            MAX_BOUND_PARAMS
+        )));
+    }
+    if statement.sql.len() > MAX_SQL_BYTES {
+        return Err(AppError::InvalidInput(format!(
+            "SQL 长度 {} 字节，超过 D1 上限 {} 字节",
+            statement.sql.len(),
+            MAX_SQL_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// 按语句数和请求体大小拆分批量
+fn split_batch(statements: &[D1Statement]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut bytes = 0;
+    for (index, statement) in statements.iter().enumerate() {
+        let size = serde_json::to_vec(statement).map_or(0, |v| v.len()) + 1;
+        if index > start
+            && (index - start >= MAX_BATCH_STATEMENTS || bytes + size > MAX_BATCH_BYTES)
+        {
+            ranges.push(start..index);
+            start = index;
+            bytes = 0;
+        }
+        bytes += size;
+    }
+    ranges.push(start..statements.len());
+    ranges
+}
+
+/// 所有语句都可重放时 batch 才可重放
+fn batch_idempotency(statements: &[D1Statement]) -> Idempotency {
+    if statements
+        .iter()
+        .all(|s| statement_idempotency(&s.sql) == Idempotency::Idempotent)
+    {
+        Idempotency::Idempotent
+    } else {
+        Idempotency::NonIdempotent
+    }
+}
+
+/// 按 SQL 类型判断语句能否安全重放
+///
+/// 查询、UPDATE、DELETE 以及带冲突处理的 INSERT 重复执行结果不变；
+/// 普通 INSERT、ALTER 以及不带 `IF [NOT] EXISTS` 的 DDL 重放会产生重复数据或报错。
+fn statement_idempotency(sql: &str) -> Idempotency {
+    let sql = sql.trim_start().to_ascii_uppercase();
+    let idempotent = if sql.starts_with("INSERT") {
+        sql.starts_with("INSERT OR ") || sql.contains("ON CONFLICT")
+    } else if sql.starts_with("CREATE") || sql.starts_with("DROP") {
+        sql.contains("IF NOT EXISTS") || sql.contains("IF EXISTS")
+    } else {
+        !sql.starts_with("ALTER")
+    };
+
+    if idempotent {
+        Idempotency::Idempotent
+    } else {
+        Idempotency::NonIdempotent
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::error::AppError;
+use crate::models::{DownloadFileInfo, JobStage};
+use crate::services::progress::{FileProgress, Progress};
+
+/// 打包结果
+#[derive(Debug)]
+pub struct ZipSummary {
+    pub succeeded: usize,
+    /// 失败的文件及原因
+    pub failed: Vec<String>,
+    /// 任务被取消、未打包的文件数
+    pub cancelled: usize,
+}
+
+/// 下载单个文件并保存到 `save_path`，返回文件大小
+pub async fn download_file(
+    url: &str,
+    save_path: &str,
+    progress: &Progress,
+) -> Result<u64, AppError> {
+    let filename = std::path::Path::new(save_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(save_path);
+    let file = progress.file(0, filename);
+    let result = save_file(url, save_path, &file).await;
+    match &result {
+        Ok(size) => file.done(*size),
+        Err(AppError::Cancelled(_)) => file.cancelled(),
+        Err(e) => file.failed(e.to_string()),
+    }
+    result
+}
+
+async fn save_file(
+    url: &str,
+    save_path: &str,
+    progress: &FileProgress<'_>,
+) -> Result<u64, AppError> {
+    let bytes = fetch(url, progress).await?;
+    let size = bytes.len() as u64;
+    progress.report(JobStage::Saving, size, Some(size));
+
+    let mut file =
+        File::create(save_path).map_err(|e| AppError::Io(format!("创建文件失败: {}", e)))?;
+    file.write_all(&bytes)
+        .map_err(|e| AppError::Io(format!("写入文件失败: {}", e)))?;
+    Ok(size)
+}
+
+/// 依次下载文件并打包成 ZIP，单个文件失败不影响其他文件
+///
+/// ZIP 中重名的文件会自动加上编号。任务被取消后不再下载剩余的文件，
+/// 已打包的文件照常写入 ZIP；一个文件都没有打包时删除 ZIP 并返回取消错误。
+pub async fn download_zip(
+    files: Vec<DownloadFileInfo>,
+    save_path: &str,
+    progress: &Progress,
+) -> Result<ZipSummary, AppError> {
+    let files = unique_filenames(files);
+    if files.is_empty() {
+        return Err(AppError::InvalidInput("没有要下载的文件".to_string()));
+    }
+
+    // 创建 zip 文件
+    let zip_file =
+        File::create(save_path).map_err(|e| AppError::Io(format!("创建 ZIP 文件失败: {}", e)))?;
+
+    let mut zip = ZipWriter::new(zip_file);
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(0o755);
+
+    let mut summary = ZipSummary {
+        succeeded: 0,
+        failed: Vec::new(),
+        cancelled: 0,
+    };
+
+    // 下载并添加每个文件到 zip
+    for (index, file_info) in files.iter().enumerate() {
+        let file = progress.file(index, &file_info.filename);
+        if progress.is_cancelled() {
+            file.cancelled();
+            summary.cancelled += 1;
+            continue;
+        }
+        match add_to_zip(&mut zip, file_info, &options, &file).await {
+            Ok(size) => {
+                file.done(size);
+                summary.succeeded += 1;
+            }
+            Err(AppError::Cancelled(_)) => {
+                file.cancelled();
+                summary.cancelled += 1;
+            }
+            Err(e) => {
+                file.failed(e.to_string());
+                summary
+                    .failed
+                    .push(format!("{}: {}", file_info.filename, e));
+            }
+        }
+    }
+
+    // 完成 zip 文件
+    zip.finish()
+        .map_err(|e| AppError::Io(format!("完成 ZIP 文件失败: {}", e)))?;
+
+    if summary.cancelled > 0 && summary.succeeded == 0 {
+        std::fs::remove_file(save_path)
+            .map_err(|e| AppError::Io(format!("删除 ZIP 文件失败: {}", e)))?;
+        return Err(AppError::Cancelled("任务已取消".to_string()));
+    }
+
+    Ok(summary)
+}
+
+/// 下载文件并添加到 zip，返回文件大小
+async fn add_to_zip(
+    zip: &mut ZipWriter<File>,
+    file_info: &DownloadFileInfo,
+    options: &FileOptions,
+    progress: &FileProgress<'_>,
+) -> Result<u64, AppError> {
+    let bytes = fetch(&file_info.url, progress).await?;
+    let size = bytes.len() as u64;
+    progress.report(JobStage::Zipping, size, Some(size));
+
+    zip.start_file(&file_info.filename, *options)
+        .map_err(|e| AppError::Io(format!("添加到 ZIP 失败: {}", e)))?;
+    zip.write_all(&bytes)
+        .map_err(|e| AppError::Io(format!("写入 ZIP 失败: {}", e)))?;
+    Ok(size)
+}
+
+/// 分块读取响应内容并发送下载进度，任务被取消时在读取下一块前停止
+async fn fetch(url: &str, progress: &FileProgress<'_>) -> Result<Vec<u8>, AppError> {
+    let mut response = reqwest::get(url)
+        .await
+        .map_err(|e| network_error("下载失败", e))?;
+
+    if !response.status().is_success() {
+        return Err(status_error("下载失败", response.status()));
+    }
+
+    let total = response.content_length();
+    progress.report(JobStage::Downloading, 0, total);
+    let mut bytes = Vec::with_capacity(total.unwrap_or(0) as usize);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| network_error("读取内容失败", e))?
+    {
+        if progress.is_cancelled() {
+            return Err(AppError::Cancelled("任务已取消".to_string()));
+        }
+        bytes.extend_from_slice(&chunk);
+        progress.transferred(JobStage::Downloading, bytes.len() as u64, total);
+    }
+    Ok(bytes)
+}
+
+/// 重名的文件在扩展名前加上 ` (2)`、` (3)` 等编号
+fn unique_filenames(files: Vec<DownloadFileInfo>) -> Vec<DownloadFileInfo> {
+    let mut used = HashSet::new();
+    files
+        .into_iter()
+        .map(|file| {
+            let (stem, extension) = match file.filename.rfind('.') {
+                Some(index) if index > 0 => file.filename.split_at(index),
+                _ => (file.filename.as_str(), ""),
+            };
+            let mut filename = file.filename.clone();
+            let mut number = 2;
+            while !used.insert(filename.to_lowercase()) {
+                filename = format!("{} ({}){}", stem, number, extension);
+                number += 1;
+            }
+            DownloadFileInfo {
+                url: file.url,
+                filename,
+            }
+        })
+        .collect()
+}
+
+fn network_error(context: &str, error: reqwest::Error) -> AppError {
+    AppError::Network {
+        message: format!("{}: {}", context, error),
+        status: None,
+        retryable: error.is_connect() || error.is_timeout(),
+    }
+}
+
+fn status_error(context: &str, status: reqwest::StatusCode) -> AppError {
+    AppError::Network {
+        message: format!("{}: HTTP {}", context, status),
+        status: Some(status.as_u16()),
+        retryable: status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+    }
+}
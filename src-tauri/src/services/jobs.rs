@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::AppError;
+
+/// 取消标记，任务在安全点检查后停止
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// 正在运行的长任务，按任务 id 记录各自的取消标记
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, CancelToken>>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记一个任务，任务结束（[`Job`] 被丢弃）时自动注销
+    ///
+    /// id 由调用方生成：调用方要用它筛选进度事件和取消任务，由这里生成的 id 调用方拿不到。
+    pub fn start(&self, id: String) -> Result<Job<'_>, AppError> {
+        if id.is_empty() {
+            return Err(AppError::InvalidInput("任务 id 不能为空".to_string()));
+        }
+        let token = CancelToken::new();
+        let mut jobs = self.lock();
+        if jobs.contains_key(&id) {
+            return Err(AppError::InvalidInput(format!("任务 {} 已在运行", id)));
+        }
+        jobs.insert(id.clone(), token.clone());
+        Ok(Job {
+            registry: self,
+            id,
+            token,
+        })
+    }
+
+    /// 请求取消任务，任务在下一个安全点停止并返回已完成的部分
+    pub fn cancel(&self, job_id: &str) -> Result<(), AppError> {
+        match self.lock().get(job_id) {
+            Some(token) => {
+                token.cancel();
+                Ok(())
+            }
+            None => Err(AppError::NotFound(format!(
+                "任务 {} 不存在或已结束",
+                job_id
+            ))),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, CancelToken>> {
+        // 登记表只有插入和删除，持锁时 panic 也不会留下不一致的状态
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 已登记的任务
+pub struct Job<'a> {
+    registry: &'a JobRegistry,
+    id: String,
+    token: CancelToken,
+}
+
+impl Job<'_> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn token(&self) -> &CancelToken {
+        &self.token
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+impl Drop for Job<'_> {
+    fn drop(&mut self) {
+        self.registry.lock().remove(&self.id);
+    }
+}
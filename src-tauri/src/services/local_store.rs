@@ -0,0 +1,166 @@
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+use rusqlite::Connection;
+
+use crate::error::AppError;
+use crate::models::{
+    D1BatchResult, D1QueryMeta, D1QueryOutput, D1Statement, D1StatementResult, StorageKind,
+};
+use crate::services::crypto::{decrypt_password, encrypt_password};
+use crate::services::d1::push_failure;
+use crate::services::mirror::run_query;
+use crate::services::store::{MetadataStore, StoreFuture};
+
+/// 本机 SQLite 存储后端，数据只保存在当前设备上
+///
+/// 表结构与 D1 相同，由同一套迁移创建。
+pub struct LocalStore {
+    conn: Mutex<Connection>,
+    schema_ready: Arc<AtomicBool>,
+    /// 本机随机生成的凭证加密密钥
+    key: String,
+}
+
+/// 旧版本本地后端固定使用的密钥材料
+const LEGACY_KEY_MATERIAL: (&str, &str) = ("local", "metadata");
+
+impl LocalStore {
+    /// 打开（必要时创建）数据库文件，凭证用 `key` 派生的密钥加密
+    pub fn open(path: &Path, key: String) -> Result<Self, AppError> {
+        let conn = Connection::open(path)
+            .map_err(|e| AppError::LocalDb(format!("打开本地数据库失败: {}", e)))?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            schema_ready: Arc::new(AtomicBool::new(false)),
+            key,
+        })
+    }
+
+    /// 把旧版本用固定密钥材料加密的凭证改用本机密钥重新加密，无法解密的保持不变
+    pub fn rekey_legacy_credentials(&self) -> Result<(), AppError> {
+        let conn = self.lock()?;
+        let exists: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'smms_user'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(store_error)?;
+        if !exists {
+            return Ok(());
+        }
+
+        let (legacy_account, legacy_database) = LEGACY_KEY_MATERIAL;
+        let (account, database) = self.key_material();
+        let rekey = |secret: String| -> Result<String, AppError> {
+            let plain = decrypt_password(&secret, legacy_account, legacy_database)?;
+            encrypt_password(&plain, &account, &database)
+        };
+        let users: Vec<(i64, String, Option<String>)> = conn
+            .prepare("SELECT id, encrypted_password, encrypted_api_token FROM smms_user")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+                    .collect()
+            })
+            .map_err(store_error)?;
+        for (id, password, token) in users {
+            let Ok(password) = rekey(password) else {
+                continue;
+            };
+            let Ok(token) = token.map(rekey).transpose() else {
+                continue;
+            };
+            conn.execute(
+                "UPDATE smms_user SET encrypted_password = ?, encrypted_api_token = ? WHERE id = ?",
+                rusqlite::params![password, token, id],
+            )
+            .map_err(store_error)?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>, AppError> {
+        self.conn
+            .lock()
+            .map_err(|_| AppError::LocalDb("本地数据库不可用".to_string()))
+    }
+
+    fn run(&self, statement: D1Statement) -> Result<D1QueryOutput, AppError> {
+        execute(&*self.lock()?, &statement).map_err(|e| e.context("查询失败"))
+    }
+
+    /// 在一个事务中按顺序执行，失败的语句不产生修改，之前的语句照常提交
+    fn run_batch(&self, statements: Vec<D1Statement>) -> Result<D1BatchResult, AppError> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction().map_err(store_error)?;
+        let mut outcome = D1BatchResult::default();
+        for (index, statement) in statements.iter().enumerate() {
+            match execute(&tx, statement) {
+                Ok(output) => outcome.results.push(D1StatementResult {
+                    index,
+                    success: true,
+                    meta: output.meta,
+                    error: None,
+                    results: output.results,
+                }),
+                Err(error) => {
+                    push_failure(&mut outcome, index, error);
+                    break;
+                }
+            }
+        }
+        tx.commit().map_err(store_error)?;
+        Ok(outcome)
+    }
+}
+
+impl MetadataStore for LocalStore {
+    fn kind(&self) -> StorageKind {
+        StorageKind::Local
+    }
+
+    fn source(&self) -> String {
+        "local".to_string()
+    }
+
+    fn key_material(&self) -> (String, String) {
+        ("local".to_string(), self.key.clone())
+    }
+
+    fn exportable_key_material(&self) -> Option<(String, String)> {
+        None
+    }
+
+    fn schema_ready(&self) -> Arc<AtomicBool> {
+        self.schema_ready.clone()
+    }
+
+    fn query(&self, statement: D1Statement) -> StoreFuture<'_, D1QueryOutput> {
+        Box::pin(async move { self.run(statement) })
+    }
+
+    fn batch(&self, statements: Vec<D1Statement>) -> StoreFuture<'_, D1BatchResult> {
+        Box::pin(async move { self.run_batch(statements) })
+    }
+}
+
+/// 执行一条语句，按 D1 的格式报告读写行数和耗时
+fn execute(conn: &Connection, statement: &D1Statement) -> Result<D1QueryOutput, AppError> {
+    let started = Instant::now();
+    let before = conn.total_changes();
+    let results = run_query(conn, statement).map_err(store_error)?;
+    let meta = D1QueryMeta {
+        rows_read: results.len() as u64,
+        rows_written: conn.total_changes().saturating_sub(before),
+        duration: started.elapsed().as_secs_f64() * 1000.0,
+        served_by: Some("local".to_string()),
+    };
+    Ok(D1QueryOutput { results, meta })
+}
+
+fn store_error(error: rusqlite::Error) -> AppError {
+    AppError::LocalDb(format!("本地数据库执行失败: {}", error))
+}
@@ -0,0 +1,387 @@
+use std::sync::atomic::Ordering;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+use crate::models::{D1Statement, SchemaVersion};
+use crate::services::d1::D1Client;
+
+/// 迁移中的一个步骤
+pub enum Step {
+    /// 直接执行的 SQL
+    Sql(&'static str),
+    /// 为已有表添加列，列已存在时跳过（兼容迁移框架之前创建的旧表）
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+/// 一次结构迁移，按 `version` 顺序执行，已发布的迁移不允许再修改
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+impl Migration {
+    /// 迁移内容的 SHA-256，用于发现已执行迁移被改动
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{}:{}\n", self.version, self.name));
+        for step in self.steps {
+            match step {
+                Step::Sql(sql) => hasher.update(format!("sql:{}\n", sql)),
+                Step::AddColumn {
+                    table,
+                    column,
+                    definition,
+                } => hasher.update(format!("add_column:{}.{} {}\n", table, column, definition)),
+            }
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// 全部迁移，新迁移只能追加在末尾
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_smms_user",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS smms_user (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE,
+                encrypted_password TEXT NOT NULL,
+                encrypted_api_token TEXT,
+                created_at DATETIME DEFAULT (datetime('now')),
+                updated_at DATETIME DEFAULT (datetime('now'))
+            )",
+        )],
+    },
+    Migration {
+        version: 2,
+        name: "create_smms_pictures",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS smms_pictures (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                file_hash TEXT NOT NULL UNIQUE,
+                filename TEXT NOT NULL,
+                store_name TEXT NOT NULL,
+                file_type TEXT NOT NULL,
+                width INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                size INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                url TEXT NOT NULL,
+                delete_url TEXT NOT NULL,
+                page_url TEXT NOT NULL,
+                is_favorite INTEGER DEFAULT 0,
+                created_at DATETIME NOT NULL,
+                updated_at DATETIME DEFAULT (datetime('now'))
+            )",
+        )],
+    },
+    Migration {
+        version: 3,
+        name: "smms_pictures_soft_delete_and_remark",
+        steps: &[
+            Step::AddColumn {
+                table: "smms_pictures",
+                column: "is_deleted",
+                definition: "INTEGER DEFAULT 0",
+            },
+            Step::AddColumn {
+                table: "smms_pictures",
+                column: "deleted_at",
+                definition: "DATETIME",
+            },
+            Step::AddColumn {
+                table: "smms_pictures",
+                column: "remark",
+                definition: "TEXT",
+            },
+        ],
+    },
+    Migration {
+        version: 4,
+        name: "smms_pictures_indexes",
+        steps: &[
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_smms_pictures_hash ON smms_pictures(file_hash)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_smms_pictures_created_at ON smms_pictures(created_at DESC)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_smms_pictures_updated_at ON smms_pictures(updated_at DESC)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_smms_pictures_favorite ON smms_pictures(is_favorite) WHERE is_favorite = 1"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_smms_pictures_type ON smms_pictures(file_type)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_smms_pictures_type_created ON smms_pictures(file_type, created_at DESC)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_smms_pictures_deleted ON smms_pictures(is_deleted)"),
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "create_saved_searches",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS saved_searches (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                params TEXT NOT NULL,
+                is_smart_album INTEGER NOT NULL DEFAULT 0,
+                created_at DATETIME DEFAULT (datetime('now')),
+                updated_at DATETIME DEFAULT (datetime('now'))
+            )",
+        )],
+    },
+    Migration {
+        version: 6,
+        name: "create_tags",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS tags (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    created_at DATETIME DEFAULT (datetime('now'))
+                )",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS picture_tags (
+                    picture_id INTEGER NOT NULL,
+                    tag_id INTEGER NOT NULL,
+                    created_at DATETIME DEFAULT (datetime('now')),
+                    PRIMARY KEY (picture_id, tag_id)
+                )",
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_picture_tags_tag ON picture_tags(tag_id)"),
+            // 标签数据的修订号，每次修改标签时递增，镜像据此判断是否需要重新拉取标签
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS tag_revision (
+                    id INTEGER PRIMARY KEY CHECK (id = 1),
+                    revision INTEGER NOT NULL
+                )",
+            ),
+        ],
+    },
+    Migration {
+        version: 7,
+        name: "create_albums",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS albums (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    cover_picture_id INTEGER,
+                    position INTEGER NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT (datetime('now')),
+                    updated_at DATETIME DEFAULT (datetime('now'))
+                )",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS album_pictures (
+                    album_id INTEGER NOT NULL,
+                    picture_id INTEGER NOT NULL,
+                    position INTEGER NOT NULL,
+                    added_at DATETIME DEFAULT (datetime('now')),
+                    PRIMARY KEY (album_id, picture_id)
+                )",
+            ),
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_album_pictures_picture ON album_pictures(picture_id)",
+            ),
+            // 相册数据的修订号，与标签相同
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS album_revision (
+                    id INTEGER PRIMARY KEY CHECK (id = 1),
+                    revision INTEGER NOT NULL
+                )",
+            ),
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "smms_pictures_content_sha256",
+        steps: &[
+            // 本地文件内容的 SHA-256，上传前据此判断是否已上传过（file_hash 是 SM.MS 的删除 hash）
+            Step::AddColumn {
+                table: "smms_pictures",
+                column: "content_sha256",
+                definition: "TEXT",
+            },
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_smms_pictures_content_sha256 ON smms_pictures(content_sha256)",
+            ),
+        ],
+    },
+];
+
+/// 当前应用支持的最新结构版本
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// 执行尚未应用的迁移，返回迁移后的结构版本
+///
+/// 已应用的迁移会校验 checksum；数据库版本高于应用支持的版本时拒绝执行。
+pub async fn migrate(d1: &D1Client) -> Result<SchemaVersion, AppError> {
+    let ready = d1.schema_ready_flag()?;
+    let _guard = d1.lock_migrations().await;
+
+    let version = run_pending(d1).await?;
+    ready.store(true, Ordering::Release);
+    Ok(version)
+}
+
+/// 确保当前配置的数据库结构已就绪
+///
+/// 每个配置只检查一次，结果缓存在 [`D1Client`] 中，配置变更后重新检查。
+pub async fn ensure_ready(d1: &D1Client) -> Result<(), AppError> {
+    if d1.is_schema_ready() {
+        return Ok(());
+    }
+
+    let ready = d1.schema_ready_flag()?;
+    let _guard = d1.lock_migrations().await;
+    // 等锁期间其他任务可能已经完成迁移
+    if !ready.load(Ordering::Acquire) {
+        run_pending(d1).await?;
+        ready.store(true, Ordering::Release);
+    }
+    Ok(())
+}
+
+async fn run_pending(d1: &D1Client) -> Result<SchemaVersion, AppError> {
+    let applied = applied_migrations(d1).await?;
+    let mut current = 0;
+    for (version, checksum) in &applied {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.version == *version)
+            .ok_or_else(|| {
+                AppError::Schema(format!(
+                    "数据库结构版本 {} 高于当前应用支持的版本 {}，请升级应用",
+                    version,
+                    latest_version()
+                ))
+            })?;
+        if migration.checksum() != *checksum {
+            return Err(AppError::Schema(format!(
+                "迁移 {}（{}）与数据库中的记录不一致，已执行的迁移不能修改",
+                migration.version, migration.name
+            )));
+        }
+        current = current.max(*version);
+    }
+
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > current).collect();
+    for migration in pending {
+        apply(d1, migration).await?;
+        current = migration.version;
+    }
+
+    Ok(SchemaVersion {
+        current,
+        latest: latest_version(),
+    })
+}
+
+/// 读取当前结构版本（不执行迁移）
+pub async fn schema_version(d1: &D1Client) -> Result<SchemaVersion, AppError> {
+    let current = applied_migrations(d1)
+        .await?
+        .iter()
+        .map(|(version, _)| *version)
+        .max()
+        .unwrap_or(0);
+
+    Ok(SchemaVersion {
+        current,
+        latest: latest_version(),
+    })
+}
+
+/// 已应用的迁移（版本号, checksum）
+async fn applied_migrations(d1: &D1Client) -> Result<Vec<(u32, String)>, AppError> {
+    d1.query(D1Statement::new(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at DATETIME DEFAULT (datetime('now'))
+        )",
+    ))
+    .await?;
+
+    let rows: Vec<AppliedMigration> = d1
+        .query_as(D1Statement::new(
+            "SELECT version, checksum FROM schema_migrations ORDER BY version",
+        ))
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.version, row.checksum))
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct AppliedMigration {
+    version: u32,
+    checksum: String,
+}
+
+/// 在同一个 batch 中执行迁移的全部步骤并记录版本
+async fn apply(d1: &D1Client, migration: &Migration) -> Result<(), AppError> {
+    let mut statements = Vec::new();
+    for step in migration.steps {
+        match step {
+            Step::Sql(sql) => statements.push(D1Statement::new(*sql)),
+            Step::AddColumn {
+                table,
+                column,
+                definition,
+            } => {
+                if !has_column(d1, table, column).await? {
+                    statements.push(D1Statement::new(format!(
+                        "ALTER TABLE {} ADD COLUMN {} {}",
+                        table, column, definition
+                    )));
+                }
+            }
+        }
+    }
+    statements.push(
+        D1Statement::new(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES (?, ?, ?)",
+        )
+        .bind(i64::from(migration.version))
+        .bind(migration.name)
+        .bind(migration.checksum()),
+    );
+
+    let sqls: Vec<String> = statements.iter().map(|s| s.sql.clone()).collect();
+    d1.batch(statements).await?.check(|index| {
+        format!(
+            "迁移 {}（{}）中的语句 `{}` ",
+            migration.version, migration.name, sqls[index]
+        )
+    })
+}
+
+async fn has_column(d1: &D1Client, table: &str, column: &str) -> Result<bool, AppError> {
+    #[derive(Deserialize)]
+    struct Count {
+        count: i64,
+    }
+
+    let rows: Vec<Count> = d1
+        .query_as(
+            D1Statement::new("SELECT COUNT(*) AS count FROM pragma_table_info(?) WHERE name = ?")
+                .bind(table)
+                .bind(column),
+        )
+        .await?;
+    Ok(rows.first().is_some_and(|row| row.count > 0))
+}
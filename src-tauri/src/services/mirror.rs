@@ -0,0 +1,847 @@
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::error::AppError;
+use crate::models::{D1Param, D1Statement};
+use crate::services::d1::{D1Client, MAX_BOUND_PARAMS};
+use crate::services::migrations;
+use crate::services::offline;
+use crate::services::rows;
+
+/// 后台从 D1 拉取变更的间隔
+pub const PULL_INTERVAL: Duration = Duration::from_secs(300);
+
+/// 每次请求从 D1 拉取的行数
+const PULL_PAGE_SIZE: i64 = 500;
+
+/// 本地表结构版本，与 D1 结构不同步时递增，打开时会重建镜像
+const MIRROR_SCHEMA_VERSION: i64 = 6;
+
+/// 镜像的列，与 D1 中 `smms_pictures` 的列一致
+const PICTURE_COLUMNS: &[&str] = &[
+    "id",
+    "file_hash",
+    "filename",
+    "store_name",
+    "file_type",
+    "width",
+    "height",
+    "size",
+    "path",
+    "url",
+    "delete_url",
+    "page_url",
+    "is_favorite",
+    "is_deleted",
+    "deleted_at",
+    "remark",
+    "content_sha256",
+    "created_at",
+    "updated_at",
+];
+
+/// 本地表结构：`smms_pictures` 以及标签、相册各表与 D1 相同（id 沿用 D1 的值），
+/// `mirror_state` 保存同步进度，`pictures_fts` 是文件名、存储名和备注的 trigram 全文索引，
+/// 由触发器与 `smms_pictures` 保持一致
+const SCHEMA: &str = "
+    DROP TABLE IF EXISTS album_pictures;
+    DROP TABLE IF EXISTS albums;
+    DROP TABLE IF EXISTS picture_tags;
+    DROP TABLE IF EXISTS tags;
+    DROP TABLE IF EXISTS pictures_fts;
+    DROP TABLE IF EXISTS smms_pictures;
+    DROP TABLE IF EXISTS mirror_state;
+    CREATE TABLE smms_pictures (
+        id INTEGER PRIMARY KEY,
+        file_hash TEXT NOT NULL UNIQUE,
+        filename TEXT NOT NULL,
+        store_name TEXT NOT NULL,
+        file_type TEXT NOT NULL,
+        width INTEGER NOT NULL,
+        height INTEGER NOT NULL,
+        size INTEGER NOT NULL,
+        path TEXT NOT NULL,
+        url TEXT NOT NULL,
+        delete_url TEXT NOT NULL,
+        page_url TEXT NOT NULL,
+        is_favorite INTEGER DEFAULT 0,
+        is_deleted INTEGER DEFAULT 0,
+        deleted_at DATETIME,
+        remark TEXT,
+        content_sha256 TEXT,
+        created_at DATETIME NOT NULL,
+        updated_at DATETIME DEFAULT (datetime('now'))
+    );
+    CREATE INDEX idx_smms_pictures_created_at ON smms_pictures(created_at DESC);
+    CREATE INDEX idx_smms_pictures_content_sha256 ON smms_pictures(content_sha256);
+    CREATE INDEX idx_smms_pictures_updated_at ON smms_pictures(updated_at DESC);
+    CREATE INDEX idx_smms_pictures_type ON smms_pictures(file_type);
+    CREATE INDEX idx_smms_pictures_deleted ON smms_pictures(is_deleted);
+    CREATE TABLE tags (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        created_at DATETIME
+    );
+    CREATE TABLE picture_tags (
+        picture_id INTEGER NOT NULL,
+        tag_id INTEGER NOT NULL,
+        PRIMARY KEY (picture_id, tag_id)
+    );
+    CREATE INDEX idx_picture_tags_tag ON picture_tags(tag_id);
+    CREATE TABLE albums (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        cover_picture_id INTEGER,
+        position INTEGER NOT NULL DEFAULT 0,
+        created_at DATETIME,
+        updated_at DATETIME
+    );
+    CREATE TABLE album_pictures (
+        album_id INTEGER NOT NULL,
+        picture_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (album_id, picture_id)
+    );
+    CREATE INDEX idx_album_pictures_picture ON album_pictures(picture_id);
+    CREATE TABLE mirror_state (
+        key TEXT PRIMARY KEY,
+        value TEXT
+    );
+    CREATE VIRTUAL TABLE pictures_fts USING fts5(
+        filename, store_name, remark,
+        content = 'smms_pictures', content_rowid = 'id',
+        tokenize = 'trigram case_sensitive 0'
+    );
+    CREATE TRIGGER smms_pictures_fts_insert AFTER INSERT ON smms_pictures BEGIN
+        INSERT INTO pictures_fts (rowid, filename, store_name, remark)
+        VALUES (new.id, new.filename, new.store_name, new.remark);
+    END;
+    CREATE TRIGGER smms_pictures_fts_delete AFTER DELETE ON smms_pictures BEGIN
+        INSERT INTO pictures_fts (pictures_fts, rowid, filename, store_name, remark)
+        VALUES ('delete', old.id, old.filename, old.store_name, old.remark);
+    END;
+    CREATE TRIGGER smms_pictures_fts_update AFTER UPDATE OF filename, store_name, remark ON smms_pictures BEGIN
+        INSERT INTO pictures_fts (pictures_fts, rowid, filename, store_name, remark)
+        VALUES ('delete', old.id, old.filename, old.store_name, old.remark);
+        INSERT INTO pictures_fts (rowid, filename, store_name, remark)
+        VALUES (new.id, new.filename, new.store_name, new.remark);
+    END;
+";
+
+/// 与图片分开同步的一组表（标签、相册）
+///
+/// 每次修改时递增 D1 中的修订号；拉取时修订号与镜像记录的不同才重新下载整组数据，
+/// 修改不影响图片的 `updated_at`，也不会与离线队列中的修改冲突。
+pub(crate) struct SyncGroup {
+    /// D1 中保存修订号的单行表
+    revision_table: &'static str,
+    /// 依次拉取和写入的表
+    tables: &'static [SyncTable],
+}
+
+struct SyncTable {
+    name: &'static str,
+    columns: &'static [&'static str],
+    /// 主键列，按主键分页拉取
+    key: &'static [&'static str],
+}
+
+/// 标签及图片标签
+pub(crate) const TAGS: SyncGroup = SyncGroup {
+    revision_table: "tag_revision",
+    tables: &[
+        SyncTable {
+            name: "tags",
+            columns: &["id", "name", "created_at"],
+            key: &["id"],
+        },
+        SyncTable {
+            name: "picture_tags",
+            columns: &["picture_id", "tag_id"],
+            key: &["picture_id", "tag_id"],
+        },
+    ],
+};
+
+/// 相册及相册中的图片
+pub(crate) const ALBUMS: SyncGroup = SyncGroup {
+    revision_table: "album_revision",
+    tables: &[
+        SyncTable {
+            name: "albums",
+            columns: &[
+                "id",
+                "name",
+                "cover_picture_id",
+                "position",
+                "created_at",
+                "updated_at",
+            ],
+            key: &["id"],
+        },
+        SyncTable {
+            name: "album_pictures",
+            columns: &["album_id", "picture_id", "position"],
+            key: &["album_id", "picture_id"],
+        },
+    ],
+};
+
+const SYNC_GROUPS: &[&SyncGroup] = &[&TAGS, &ALBUMS];
+
+impl SyncGroup {
+    /// 镜像 `mirror_state` 中记录已同步修订号的键
+    fn state_key(&self) -> &'static str {
+        self.revision_table
+    }
+
+    fn revision_query(&self) -> D1Statement {
+        D1Statement::new(format!(
+            "SELECT revision FROM {} WHERE id = 1",
+            self.revision_table
+        ))
+    }
+
+    /// 在 D1 上递增修订号并执行语句，返回新的修订号和各语句的结果行
+    ///
+    /// 修订号在批量的第一条递增，后面的语句中途失败时修订号也已变化，镜像下次拉取时会重新下载。
+    pub(crate) async fn execute(
+        &self,
+        d1: &D1Client,
+        statements: Vec<D1Statement>,
+    ) -> Result<(i64, Vec<Vec<Value>>), AppError> {
+        migrations::ensure_ready(d1).await?;
+        let mut batch = vec![D1Statement::new(format!(
+            "INSERT INTO {} (id, revision) VALUES (1, 1) \
+             ON CONFLICT(id) DO UPDATE SET revision = revision + 1 RETURNING revision",
+            self.revision_table
+        ))];
+        batch.extend(statements);
+        let result = d1.batch(batch).await?;
+        result.check(|index| match index {
+            0 => "修订号".to_string(),
+            index => format!("第 {} 条修改", index),
+        })?;
+
+        let mut results = result.results.into_iter().map(|r| r.results);
+        let revision = results
+            .next()
+            .and_then(|rows| rows.first().and_then(|row| row["revision"].as_i64()))
+            .ok_or_else(|| AppError::BadResponse("没有返回修订号".to_string()))?;
+        Ok((revision, results.collect()))
+    }
+
+    /// 把已在 D1 执行成功的修改写入镜像，镜像不是修改前的版本时改为从 D1 拉取
+    pub(crate) async fn write_through(
+        &self,
+        d1: &D1Client,
+        mirror: &LocalMirror,
+        revision: i64,
+        statements: &[D1Statement],
+    ) -> Result<(), AppError> {
+        if !mirror.write_group_changes(self, &d1.source()?, revision, statements) {
+            refresh(d1, mirror).await;
+        }
+        Ok(())
+    }
+
+    /// 把 D1 `RETURNING` 返回的 `table` 的行原样写入镜像的语句
+    ///
+    /// 关联行要以 D1 的结果为准：在镜像中连接 `smms_pictures` 重新计算时，
+    /// 尚未拉取到的图片的关联会被漏掉，而修订号照样前进。
+    pub(crate) fn replace_rows(&self, table: &str, rows: &[Value]) -> Vec<D1Statement> {
+        let table = self
+            .tables
+            .iter()
+            .find(|t| t.name == table)
+            .expect("同步组中没有该表");
+        let row = format!("({})", vec!["?"; table.columns.len()].join(", "));
+        rows.chunks(MAX_BOUND_PARAMS / table.columns.len())
+            .map(|chunk| D1Statement {
+                sql: format!(
+                    "INSERT OR REPLACE INTO {} ({}) VALUES {}",
+                    table.name,
+                    table.columns.join(", "),
+                    vec![row.as_str(); chunk.len()].join(", ")
+                ),
+                params: chunk
+                    .iter()
+                    .flat_map(|row| table.columns.iter().map(|column| json_param(&row[*column])))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// 在 D1 上执行语句，再把相同的语句写入镜像
+    pub(crate) async fn apply(
+        &self,
+        d1: &D1Client,
+        mirror: &LocalMirror,
+        statements: Vec<D1Statement>,
+    ) -> Result<(), AppError> {
+        let (revision, _) = self.execute(d1, statements.clone()).await?;
+        self.write_through(d1, mirror, revision, &statements).await
+    }
+}
+
+/// 从 D1 拉取的一组表的全部数据及其修订号
+struct GroupSnapshot {
+    group: &'static SyncGroup,
+    revision: i64,
+    tables: Vec<Vec<Value>>,
+}
+
+/// 镜像同步状态
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MirrorStatus {
+    /// 镜像对应的 D1 数据库（`account_id/database_id`），尚未同步时为空
+    pub source: Option<String>,
+    /// 最近一次成功拉取的时间（UTC）
+    pub last_synced_at: Option<String>,
+    /// 镜像中的图片数量（包含已删除）
+    pub pictures: i64,
+}
+
+/// `smms_pictures` 及标签的本地 SQLite 镜像
+///
+/// 读取由本地提供，离线时仍可浏览和搜索；D1 写入成功后同步写入镜像，
+/// 并定期按 `updated_at` 增量拉取其他设备的修改。
+pub struct LocalMirror {
+    conn: Mutex<Connection>,
+    /// 串行执行拉取
+    pulling: tokio::sync::Mutex<()>,
+    /// 串行重放离线队列
+    replaying: tokio::sync::Mutex<()>,
+}
+
+impl LocalMirror {
+    /// 打开（必要时创建）镜像数据库文件
+    pub fn open(path: &Path) -> Result<Self, AppError> {
+        let conn = Connection::open(path)
+            .map_err(|e| AppError::LocalDb(format!("打开本地镜像失败: {}", e)))?;
+        Self::with_connection(conn)
+    }
+
+    /// 内存中的镜像（无法创建镜像文件时使用，重启后需重新拉取）
+    pub fn in_memory() -> Result<Self, AppError> {
+        let conn = Connection::open_in_memory()
+            .map_err(|e| AppError::LocalDb(format!("创建本地镜像失败: {}", e)))?;
+        Self::with_connection(conn)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, AppError> {
+        let version: i64 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(local_error)?;
+        if version != MIRROR_SCHEMA_VERSION {
+            // 镜像只是缓存，结构变化时直接重建，下次拉取会重新下载全部数据
+            conn.execute_batch(SCHEMA).map_err(local_error)?;
+            conn.pragma_update(None, "user_version", MIRROR_SCHEMA_VERSION)
+                .map_err(local_error)?;
+        }
+        // `INSERT OR REPLACE` 替换行时只有开启递归触发器才会触发删除触发器，否则全文索引会残留旧内容
+        conn.pragma_update(None, "recursive_triggers", true)
+            .map_err(local_error)?;
+        // 离线队列不随镜像重建，尚未写入 D1 的修改不会丢失
+        conn.execute_batch(offline::JOURNAL_SCHEMA)
+            .map_err(local_error)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            pulling: tokio::sync::Mutex::new(()),
+            replaying: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub(crate) async fn lock_replay(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.replaying.lock().await
+    }
+
+    pub(crate) fn lock(&self) -> Result<MutexGuard<'_, Connection>, AppError> {
+        self.conn
+            .lock()
+            .map_err(|_| AppError::LocalDb("本地镜像不可用".to_string()))
+    }
+
+    /// 在镜像上执行查询，结果行与 D1 返回的格式相同
+    pub fn query(&self, statement: &D1Statement) -> Result<Vec<Value>, AppError> {
+        run_query(&*self.lock()?, statement).map_err(local_error)
+    }
+
+    /// 在同一次加锁中依次执行多条查询，结果来自镜像的同一时刻
+    pub fn query_many(&self, statements: &[D1Statement]) -> Result<Vec<Vec<Value>>, AppError> {
+        let conn = self.lock()?;
+        statements
+            .iter()
+            .map(|statement| run_query(&conn, statement).map_err(local_error))
+            .collect()
+    }
+
+    /// 在镜像上执行查询并把结果行解码为 `T`
+    pub fn query_as<T: DeserializeOwned>(
+        &self,
+        statement: &D1Statement,
+    ) -> Result<Vec<T>, AppError> {
+        rows::decode_rows(&self.query(statement)?)
+    }
+
+    /// 在一个事务中执行语句
+    pub fn execute(&self, statements: &[D1Statement]) -> Result<(), AppError> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction().map_err(local_error)?;
+        execute_on(&tx, statements)?;
+        tx.commit().map_err(local_error)
+    }
+
+    /// 把已在 D1 执行成功的更新写入镜像
+    ///
+    /// 写入失败不影响命令结果：D1 中的 `updated_at` 已更新，下次拉取会修正镜像。
+    pub fn write_through(&self, statements: &[D1Statement]) {
+        if let Err(e) = self.execute(statements) {
+            eprintln!("本地镜像写入失败: {}", e);
+        }
+    }
+
+    /// 当前同步状态
+    pub fn status(&self) -> Result<MirrorStatus, AppError> {
+        let conn = self.lock()?;
+        let pictures = conn
+            .query_row("SELECT COUNT(*) FROM smms_pictures", [], |row| row.get(0))
+            .map_err(local_error)?;
+        Ok(MirrorStatus {
+            source: read_state(&conn, "source")?,
+            last_synced_at: read_state(&conn, "last_synced_at")?,
+            pictures,
+        })
+    }
+
+    fn source(&self) -> Result<Option<String>, AppError> {
+        read_state(&*self.lock()?, "source")
+    }
+
+    fn cursor(&self) -> Result<Option<String>, AppError> {
+        read_state(&*self.lock()?, "cursor")
+    }
+
+    fn group_revision(&self, group: &SyncGroup) -> Result<Option<i64>, AppError> {
+        Ok(read_state(&*self.lock()?, group.state_key())?.and_then(|v| v.parse().ok()))
+    }
+
+    /// 把已在 D1 执行成功的一组修改写入镜像，`revision` 为修改后 D1 的修订号
+    ///
+    /// 只有镜像正好停在修改前的修订号时才写入并返回 true；否则其他设备也改过，
+    /// 镜像保留旧修订号，下次拉取时重新下载整组数据。
+    fn write_group_changes(
+        &self,
+        group: &SyncGroup,
+        source: &str,
+        revision: i64,
+        statements: &[D1Statement],
+    ) -> bool {
+        let result = self.lock().and_then(|mut conn| {
+            let tx = conn.transaction().map_err(local_error)?;
+            let current = read_state(&tx, group.state_key())?.and_then(|v| v.parse::<i64>().ok());
+            if read_state(&tx, "source")?.as_deref() != Some(source)
+                || current != Some(revision - 1)
+            {
+                return Ok(false);
+            }
+            execute_on(&tx, statements)?;
+            write_state(&tx, group.state_key(), &revision.to_string())?;
+            tx.commit().map_err(local_error)?;
+            Ok(true)
+        });
+        result.unwrap_or_else(|e| {
+            eprintln!("本地镜像写入失败: {}", e);
+            false
+        })
+    }
+
+    /// 写入拉取到的行并更新同步进度，`reset` 时先清空镜像；`groups` 中的各组整组替换
+    fn apply_pull(
+        &self,
+        source: &str,
+        rows: &[Value],
+        groups: &[GroupSnapshot],
+        reset: bool,
+    ) -> Result<(), AppError> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction().map_err(local_error)?;
+        if reset {
+            tx.execute("DELETE FROM smms_pictures", [])
+                .map_err(local_error)?;
+            tx.execute("DELETE FROM mirror_state", [])
+                .map_err(local_error)?;
+        }
+
+        let sql = format!(
+            "INSERT OR REPLACE INTO smms_pictures ({}) VALUES ({})",
+            PICTURE_COLUMNS.join(", "),
+            vec!["?"; PICTURE_COLUMNS.len()].join(", ")
+        );
+        {
+            let mut stmt = tx.prepare(&sql).map_err(local_error)?;
+            for row in rows {
+                let values = PICTURE_COLUMNS
+                    .iter()
+                    .map(|column| json_to_sql(row.get(*column).unwrap_or(&Value::Null)));
+                stmt.execute(params_from_iter(values))
+                    .map_err(local_error)?;
+            }
+        }
+
+        // 拉取的值会覆盖尚未写入 D1 的离线修改，重新应用到镜像上
+        let ids: Vec<i64> = rows.iter().filter_map(|row| row["id"].as_i64()).collect();
+        offline::reapply(&tx, source, &ids, reset)?;
+
+        for snapshot in groups {
+            replace_group(&tx, snapshot)?;
+        }
+
+        let cursor = rows
+            .iter()
+            .filter_map(|row| row.get("updated_at").and_then(Value::as_str))
+            .max();
+        if let Some(cursor) = cursor {
+            write_state(&tx, "cursor", cursor)?;
+        }
+        write_state(&tx, "source", source)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO mirror_state (key, value) VALUES ('last_synced_at', datetime('now'))",
+            [],
+        )
+        .map_err(local_error)?;
+        tx.commit().map_err(local_error)
+    }
+}
+
+/// 从 D1 拉取变更到镜像，返回拉取后的状态
+///
+/// 增量拉取 `updated_at` 不早于上次进度的行；`full` 或切换了数据库时重新下载全部数据。
+/// 全部页面拉取成功后才写入镜像，中途失败不会留下不完整的数据。
+pub async fn pull(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    full: bool,
+) -> Result<MirrorStatus, AppError> {
+    let source = d1.source()?;
+    migrations::ensure_ready(d1).await?;
+    let _guard = mirror.pulling.lock().await;
+
+    let reset = full || mirror.source()?.as_deref() != Some(source.as_str());
+    let since = if reset { None } else { mirror.cursor()? };
+
+    let columns = PICTURE_COLUMNS.join(", ");
+    let mut rows: Vec<Value> = Vec::new();
+    let mut revisions = None;
+    loop {
+        // 按 (updated_at, id) 分页，同一秒内更新的多行不会被跳过
+        let statement = match (&since, rows.last()) {
+            (None, None) => D1Statement::new(format!(
+                "SELECT {} FROM smms_pictures ORDER BY id LIMIT ?",
+                columns
+            ))
+            .bind(PULL_PAGE_SIZE),
+            (None, Some(last)) => D1Statement::new(format!(
+                "SELECT {} FROM smms_pictures WHERE id > ? ORDER BY id LIMIT ?",
+                columns
+            ))
+            .bind(last["id"].as_i64().unwrap_or_default())
+            .bind(PULL_PAGE_SIZE),
+            (Some(since), None) => D1Statement::new(format!(
+                "SELECT {} FROM smms_pictures WHERE updated_at >= ? ORDER BY updated_at, id LIMIT ?",
+                columns
+            ))
+            .bind(since)
+            .bind(PULL_PAGE_SIZE),
+            (Some(_), Some(last)) => {
+                let updated_at = last["updated_at"].as_str().unwrap_or_default().to_string();
+                D1Statement::new(format!(
+                    "SELECT {} FROM smms_pictures \
+                     WHERE updated_at > ? OR (updated_at = ? AND id > ?) \
+                     ORDER BY updated_at, id LIMIT ?",
+                    columns
+                ))
+                .bind(&updated_at)
+                .bind(&updated_at)
+                .bind(last["id"].as_i64().unwrap_or_default())
+                .bind(PULL_PAGE_SIZE)
+            }
+        };
+
+        let page = match revisions {
+            Some(_) => d1
+                .query(statement)
+                .await
+                .map_err(|e| e.context("同步本地镜像失败"))?,
+            None => {
+                let (page, first_revisions) = first_page(d1, statement).await?;
+                revisions = Some(first_revisions);
+                page
+            }
+        };
+        let done = (page.len() as i64) < PULL_PAGE_SIZE;
+        rows.extend(page);
+        if done {
+            break;
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (group, revision) in SYNC_GROUPS.iter().zip(revisions.unwrap_or_default()) {
+        if let Some(snapshot) = pull_group(d1, mirror, group, revision, reset).await? {
+            groups.push(snapshot);
+        }
+    }
+    mirror.apply_pull(&source, &rows, &groups, reset)?;
+    mirror.status()
+}
+
+/// 读取第一页，同一个请求中读取 D1 中各组的修订号（尚未修改过时为 0）
+async fn first_page(
+    d1: &D1Client,
+    statement: D1Statement,
+) -> Result<(Vec<Value>, Vec<i64>), AppError> {
+    let mut batch = vec![statement];
+    batch.extend(SYNC_GROUPS.iter().map(|group| group.revision_query()));
+    let result = d1.batch(batch).await?;
+    result
+        .check(|index| match index {
+            0 => "拉取图片".to_string(),
+            _ => "读取修订号".to_string(),
+        })
+        .map_err(|e| e.context("同步本地镜像失败"))?;
+
+    let mut results = result.results.into_iter().map(|r| r.results);
+    let page = results.next().unwrap_or_default();
+    let revisions = results
+        .map(|rows| {
+            rows.first()
+                .and_then(|row| row["revision"].as_i64())
+                .unwrap_or(0)
+        })
+        .collect();
+    Ok((page, revisions))
+}
+
+/// 修订号与镜像不同（或需要重新下载全部数据）时拉取整组数据
+async fn pull_group(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    group: &'static SyncGroup,
+    revision: i64,
+    reset: bool,
+) -> Result<Option<GroupSnapshot>, AppError> {
+    if !reset && mirror.group_revision(group)? == Some(revision) {
+        return Ok(None);
+    }
+
+    let mut tables = Vec::new();
+    for table in group.tables {
+        // 从未修改过，不需要读取
+        if revision == 0 {
+            tables.push(Vec::new());
+            continue;
+        }
+        tables.push(fetch_table(d1, table).await?);
+    }
+    Ok(Some(GroupSnapshot {
+        group,
+        revision,
+        tables,
+    }))
+}
+
+/// 按主键分页读取整张表
+async fn fetch_table(d1: &D1Client, table: &SyncTable) -> Result<Vec<Value>, AppError> {
+    let columns = table.columns.join(", ");
+    let key = table.key.join(", ");
+    let mut rows: Vec<Value> = Vec::new();
+    loop {
+        let statement = match rows.last() {
+            None => D1Statement::new(format!(
+                "SELECT {} FROM {} ORDER BY {} LIMIT ?",
+                columns, table.name, key
+            )),
+            Some(last) => {
+                let mut statement = D1Statement::new(format!(
+                    "SELECT {} FROM {} WHERE ({}) > ({}) ORDER BY {} LIMIT ?",
+                    columns,
+                    table.name,
+                    key,
+                    vec!["?"; table.key.len()].join(", "),
+                    key
+                ));
+                for column in table.key {
+                    statement = statement.bind(last[*column].as_i64().unwrap_or_default());
+                }
+                statement
+            }
+        }
+        .bind(PULL_PAGE_SIZE);
+
+        let page = d1
+            .query(statement)
+            .await
+            .map_err(|e| e.context("同步本地镜像失败"))?;
+        let done = (page.len() as i64) < PULL_PAGE_SIZE;
+        rows.extend(page);
+        if done {
+            return Ok(rows);
+        }
+    }
+}
+
+/// 用拉取到的数据替换镜像中的整组表
+fn replace_group(conn: &Connection, snapshot: &GroupSnapshot) -> Result<(), AppError> {
+    for (table, rows) in snapshot.group.tables.iter().zip(&snapshot.tables) {
+        conn.execute(&format!("DELETE FROM {}", table.name), [])
+            .map_err(local_error)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+                table.name,
+                table.columns.join(", "),
+                vec!["?"; table.columns.len()].join(", ")
+            ))
+            .map_err(local_error)?;
+        for row in rows {
+            let values = table
+                .columns
+                .iter()
+                .map(|column| json_to_sql(row.get(*column).unwrap_or(&Value::Null)));
+            stmt.execute(params_from_iter(values))
+                .map_err(local_error)?;
+        }
+    }
+    write_state(
+        conn,
+        snapshot.group.state_key(),
+        &snapshot.revision.to_string(),
+    )
+}
+
+/// 确保镜像可用于读取：尚未与当前数据库同步过时先拉取一次
+pub async fn ensure_synced(d1: &D1Client, mirror: &LocalMirror) -> Result<(), AppError> {
+    let source = d1.source()?;
+    if mirror.source()?.as_deref() == Some(source.as_str()) {
+        return Ok(());
+    }
+    pull(d1, mirror, false).await.map(|_| ())
+}
+
+/// D1 插入新记录后拉取变更（新记录的 id 由 D1 分配，无法直接写入镜像）
+///
+/// 拉取失败只记录日志，由后台的定期拉取补齐。
+pub async fn refresh(d1: &D1Client, mirror: &LocalMirror) {
+    if let Err(e) = pull(d1, mirror, false).await {
+        eprintln!("本地镜像同步失败: {}", e);
+    }
+}
+
+fn read_state(conn: &Connection, key: &str) -> Result<Option<String>, AppError> {
+    conn.query_row(
+        "SELECT value FROM mirror_state WHERE key = ?",
+        [key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(local_error)
+}
+
+fn write_state(conn: &Connection, key: &str, value: &str) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO mirror_state (key, value) VALUES (?, ?)",
+        [key, value],
+    )
+    .map(|_| ())
+    .map_err(local_error)
+}
+
+/// 在给定连接上执行一条语句，结果行与 D1 返回的格式相同
+pub(crate) fn run_query(
+    conn: &Connection,
+    statement: &D1Statement,
+) -> Result<Vec<Value>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&statement.sql)?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query(params_from_iter(statement.params.iter().map(to_sql)))?;
+
+    let mut results = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut object = Map::new();
+        for (index, column) in columns.iter().enumerate() {
+            object.insert(column.clone(), to_json(row.get_ref(index)?));
+        }
+        results.push(Value::Object(object));
+    }
+    Ok(results)
+}
+
+/// 在给定连接（或事务）上依次执行语句
+pub(crate) fn execute_on(conn: &Connection, statements: &[D1Statement]) -> Result<(), AppError> {
+    for statement in statements {
+        conn.execute(
+            &statement.sql,
+            params_from_iter(statement.params.iter().map(to_sql)),
+        )
+        .map_err(local_error)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn local_error(error: rusqlite::Error) -> AppError {
+    AppError::LocalDb(format!("本地镜像操作失败: {}", error))
+}
+
+fn to_sql(param: &D1Param) -> SqlValue {
+    match param {
+        D1Param::Null => SqlValue::Null,
+        D1Param::Integer(i) => SqlValue::Integer(*i),
+        D1Param::Real(f) => SqlValue::Real(*f),
+        D1Param::Text(s) => SqlValue::Text(s.clone()),
+    }
+}
+
+fn json_param(value: &Value) -> D1Param {
+    match value {
+        Value::Null => D1Param::Null,
+        Value::Bool(b) => D1Param::from(*b),
+        Value::Number(n) => n
+            .as_i64()
+            .map(D1Param::Integer)
+            .unwrap_or_else(|| D1Param::Real(n.as_f64().unwrap_or(0.0))),
+        Value::String(s) => D1Param::Text(s.clone()),
+        other => D1Param::Text(other.to_string()),
+    }
+}
+
+pub(crate) fn json_to_sql(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::Number(n) => n
+            .as_i64()
+            .map(SqlValue::Integer)
+            .unwrap_or_else(|| SqlValue::Real(n.as_f64().unwrap_or_default())),
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn to_json(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => json!(i),
+        ValueRef::Real(f) => json!(f),
+        ValueRef::Text(t) => json!(String::from_utf8_lossy(t)),
+        ValueRef::Blob(_) => Value::Null,
+    }
+}
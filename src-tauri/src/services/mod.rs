@@ -1,2 +1,24 @@
+pub mod albums;
+pub mod backup;
 pub mod config;
+pub mod credentials;
 pub mod crypto;
+pub mod d1;
+pub mod download;
+pub mod jobs;
+pub mod local_store;
+pub mod migrations;
+pub mod mirror;
+pub mod offline;
+pub mod pagination;
+pub mod picture_query;
+pub mod progress;
+pub mod retry;
+pub mod rows;
+pub mod saved_search;
+pub mod search;
+pub mod smms;
+pub mod store;
+pub mod tags;
+pub mod upload;
+pub mod usage;
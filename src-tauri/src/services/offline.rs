@@ -0,0 +1,777 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::models::{D1Statement, SmmsDeleteResponse};
+use crate::services::credentials;
+use crate::services::d1::D1Client;
+use crate::services::mirror::{execute_on, local_error, LocalMirror};
+use crate::services::smms::SmmsClient;
+
+/// 离线队列的表结构
+///
+/// `pending_operations` 按顺序记录尚未写入 D1 的修改；`pending_originals` 保存这些修改
+/// 涉及的图片在 D1 中的值，放弃修改时用来恢复镜像。
+pub(crate) const JOURNAL_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS pending_operations (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        source TEXT NOT NULL,
+        edit TEXT NOT NULL,
+        base TEXT NOT NULL,
+        status TEXT NOT NULL DEFAULT 'pending',
+        error TEXT,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        created_at DATETIME DEFAULT (datetime('now'))
+    );
+    CREATE TABLE IF NOT EXISTS pending_originals (
+        id INTEGER PRIMARY KEY,
+        is_favorite INTEGER,
+        is_deleted INTEGER,
+        deleted_at DATETIME,
+        remark TEXT
+    );
+";
+
+/// 单条 `IN (...)` 语句中最多放入的 id 数量（D1 限制每条语句最多 100 个绑定参数）
+pub(crate) const MAX_IDS_PER_STATEMENT: usize = 90;
+
+/// 可离线执行的图片修改
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PictureEdit {
+    /// 收藏或取消收藏
+    Favorite { id: i64, is_favorite: bool },
+    /// 修改一张或多张图片的备注
+    Remark {
+        ids: Vec<i64>,
+        remark: Option<String>,
+    },
+    /// 删除图片（先删除 SM.MS 上的文件，再软删除记录）
+    Delete { id: i64 },
+}
+
+impl PictureEdit {
+    /// 涉及的图片 id
+    pub fn ids(&self) -> Vec<i64> {
+        match self {
+            PictureEdit::Favorite { id, .. } | PictureEdit::Delete { id } => vec![*id],
+            PictureEdit::Remark { ids, .. } => ids.clone(),
+        }
+    }
+
+    /// 操作说明，用于提示和队列展示
+    pub fn describe(&self) -> String {
+        match self {
+            PictureEdit::Favorite { id, is_favorite } => {
+                format!("{}收藏图片 {}", if *is_favorite { "" } else { "取消" }, id)
+            }
+            PictureEdit::Remark { ids, .. } if ids.len() == 1 => {
+                format!("修改图片 {} 的备注", ids[0])
+            }
+            PictureEdit::Remark { ids, .. } => format!("修改 {} 张图片的备注", ids.len()),
+            PictureEdit::Delete { id } => format!("删除图片 {}", id),
+        }
+    }
+
+    /// 只保留 `ids` 中的图片，没有交集时返回 `None`
+    fn restrict(&self, ids: &BTreeSet<i64>) -> Option<PictureEdit> {
+        match self {
+            PictureEdit::Remark { ids: own, remark } => {
+                let kept: Vec<i64> = own.iter().copied().filter(|id| ids.contains(id)).collect();
+                (!kept.is_empty()).then(|| PictureEdit::Remark {
+                    ids: kept,
+                    remark: remark.clone(),
+                })
+            }
+            other => other
+                .ids()
+                .iter()
+                .any(|id| ids.contains(id))
+                .then(|| other.clone()),
+        }
+    }
+
+    /// 在 `table` 上执行修改的语句
+    ///
+    /// `remote` 时同时刷新 `updated_at` 并返回修改后的版本（用于 D1）；
+    /// 本地语句不修改 `updated_at`，镜像中保留的仍是 D1 的版本。
+    fn statements(&self, table: &str, remote: bool) -> Vec<D1Statement> {
+        let (stamp, returning) = if remote {
+            (
+                ", updated_at = datetime('now')",
+                " RETURNING id, updated_at",
+            )
+        } else {
+            ("", "")
+        };
+        match self {
+            PictureEdit::Favorite { id, is_favorite } => vec![D1Statement::new(format!(
+                "UPDATE {} SET is_favorite = ?{} WHERE id = ?{}",
+                table, stamp, returning
+            ))
+            .bind(*is_favorite)
+            .bind(*id)],
+            PictureEdit::Remark { ids, remark } => ids
+                .chunks(MAX_IDS_PER_STATEMENT)
+                .map(|chunk| {
+                    let statement = D1Statement::new(format!(
+                        "UPDATE {} SET remark = ?{} WHERE id IN ({}){}",
+                        table,
+                        stamp,
+                        vec!["?"; chunk.len()].join(", "),
+                        returning
+                    ))
+                    .bind(remark.as_ref());
+                    chunk.iter().fold(statement, |stmt, id| stmt.bind(*id))
+                })
+                .collect(),
+            PictureEdit::Delete { id } => vec![D1Statement::new(format!(
+                "UPDATE {} SET is_deleted = 1, deleted_at = datetime('now'){} WHERE id = ?{}",
+                table, stamp, returning
+            ))
+            .bind(*id)],
+        }
+    }
+}
+
+/// 队列中操作的状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    /// 等待重放
+    Pending,
+    /// 图片在加入队列后被其他设备修改，需要用户决定是否放弃
+    Conflict,
+    /// D1 或 SM.MS 拒绝了该操作
+    Failed,
+}
+
+impl OperationStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            OperationStatus::Pending => "pending",
+            OperationStatus::Conflict => "conflict",
+            OperationStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "conflict" => OperationStatus::Conflict,
+            "failed" => OperationStatus::Failed,
+            _ => OperationStatus::Pending,
+        }
+    }
+}
+
+/// 离线队列中的一条操作
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PendingOperation {
+    /// 队列序号，按此顺序重放
+    pub seq: i64,
+    /// 操作所属的 D1 数据库（`account_id/database_id`）
+    pub source: String,
+    pub edit: PictureEdit,
+    pub description: String,
+    /// 加入队列时各图片的 `updated_at`，重放前与 D1 比较以发现冲突
+    pub base: BTreeMap<i64, Option<String>>,
+    pub status: OperationStatus,
+    /// 最近一次重放失败的原因
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub created_at: String,
+}
+
+/// 执行修改的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditOutcome {
+    /// 已写入 D1，`retries` 为调用 SM.MS 接口时的重试次数
+    Applied { message: String, retries: u32 },
+    /// D1 或 SM.MS 不可达，已加入离线队列并应用到本地镜像
+    Queued { seq: i64, message: String },
+}
+
+impl EditOutcome {
+    pub fn into_message(self) -> String {
+        match self {
+            EditOutcome::Applied { message, .. } | EditOutcome::Queued { message, .. } => message,
+        }
+    }
+}
+
+/// 一次重放的结果
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ReplayReport {
+    /// 成功写入 D1 的操作数
+    pub applied: usize,
+    /// 新发现冲突的操作数
+    pub conflicts: usize,
+    /// 被拒绝的操作数
+    pub failed: usize,
+    /// 仍在等待重放的操作数（网络仍不可用）
+    pub remaining: usize,
+}
+
+/// 修改图片：在线时直接写入 D1，网络不可用时加入离线队列
+///
+/// 队列中还有等待重放的操作时先重放，保证修改按顺序到达 D1。
+pub async fn apply(
+    d1: &D1Client,
+    smms: &SmmsClient,
+    mirror: &LocalMirror,
+    edit: PictureEdit,
+) -> Result<EditOutcome, AppError> {
+    let source = d1.source()?;
+    if count_pending(mirror, &source)? > 0 && replay(d1, smms, mirror).await?.remaining > 0 {
+        return enqueue(mirror, &source, edit);
+    }
+
+    match execute(d1, smms, &edit).await {
+        Ok(executed) => {
+            let mut statements = edit.statements("smms_pictures", false);
+            statements.extend(version_statements(&executed.versions));
+            mirror.write_through(&statements);
+            Ok(EditOutcome::Applied {
+                message: executed.message,
+                retries: executed.retries,
+            })
+        }
+        Err(error) if is_offline(&error) => enqueue(mirror, &source, edit),
+        Err(error) => Err(error),
+    }
+}
+
+/// 按顺序重放当前数据库的等待中操作，网络仍不可用时停止
+///
+/// 重放前比较 D1 中的 `updated_at` 与加入队列时的值，不一致的标记为冲突并跳过。
+pub async fn replay(
+    d1: &D1Client,
+    smms: &SmmsClient,
+    mirror: &LocalMirror,
+) -> Result<ReplayReport, AppError> {
+    let source = d1.source()?;
+    let _guard = mirror.lock_replay().await;
+    let mut report = ReplayReport::default();
+
+    let mut after = 0;
+    while let Some(operation) = next_pending(mirror, &source, after)? {
+        after = operation.seq;
+
+        let current = match remote_versions(d1, &operation.edit.ids()).await {
+            Ok(current) => current,
+            Err(error) if is_offline(&error) => {
+                mark(
+                    mirror,
+                    operation.seq,
+                    OperationStatus::Pending,
+                    &error.to_string(),
+                )?;
+                break;
+            }
+            Err(error) => {
+                mark(
+                    mirror,
+                    operation.seq,
+                    OperationStatus::Failed,
+                    &error.to_string(),
+                )?;
+                report.failed += 1;
+                continue;
+            }
+        };
+        if let Some((status, message)) = check_base(&operation, &current) {
+            match status {
+                OperationStatus::Conflict => report.conflicts += 1,
+                _ => report.failed += 1,
+            }
+            mark(mirror, operation.seq, status, &message)?;
+            continue;
+        }
+
+        match execute(d1, smms, &operation.edit).await {
+            Ok(executed) => {
+                complete(mirror, &operation, &executed.versions)?;
+                report.applied += 1;
+            }
+            Err(error) if is_offline(&error) => {
+                mark(
+                    mirror,
+                    operation.seq,
+                    OperationStatus::Pending,
+                    &error.to_string(),
+                )?;
+                break;
+            }
+            Err(error) => {
+                mark(
+                    mirror,
+                    operation.seq,
+                    OperationStatus::Failed,
+                    &error.to_string(),
+                )?;
+                report.failed += 1;
+            }
+        }
+    }
+
+    report.remaining = count_pending(mirror, &source)?;
+    Ok(report)
+}
+
+/// 队列中的全部操作（包括冲突和失败的），按重放顺序排列
+pub fn pending_operations(mirror: &LocalMirror) -> Result<Vec<PendingOperation>, AppError> {
+    load(&*mirror.lock()?, None)
+}
+
+/// 放弃队列中的操作（`seqs` 为空时放弃全部），并把镜像恢复为 D1 中的值
+pub fn discard(mirror: &LocalMirror, seqs: Option<Vec<i64>>) -> Result<usize, AppError> {
+    let mut conn = mirror.lock()?;
+    let tx = conn.transaction().map_err(local_error)?;
+
+    let operations = load(&tx, None)?;
+    let (discarded, kept): (Vec<_>, Vec<_>) = operations
+        .into_iter()
+        .partition(|op| seqs.as_ref().is_none_or(|seqs| seqs.contains(&op.seq)));
+
+    for operation in &discarded {
+        tx.execute(
+            "DELETE FROM pending_operations WHERE seq = ?",
+            [operation.seq],
+        )
+        .map_err(local_error)?;
+    }
+
+    // 恢复为 D1 中的值，再重新应用其余仍在队列中的修改
+    let affected: BTreeSet<i64> = discarded.iter().flat_map(|op| op.edit.ids()).collect();
+    for id in &affected {
+        tx.execute(
+            "UPDATE smms_pictures SET \
+             (is_favorite, is_deleted, deleted_at, remark) = \
+             (SELECT is_favorite, is_deleted, deleted_at, remark FROM pending_originals WHERE id = ?1) \
+             WHERE id = ?1 AND EXISTS (SELECT 1 FROM pending_originals WHERE id = ?1)",
+            [id],
+        )
+        .map_err(local_error)?;
+    }
+    for operation in &kept {
+        if let Some(edit) = operation.edit.restrict(&affected) {
+            execute_on(&tx, &edit.statements("smms_pictures", false))?;
+        }
+    }
+    prune_originals(&tx, &kept)?;
+
+    tx.commit().map_err(local_error)?;
+    Ok(discarded.len())
+}
+
+/// 拉取覆盖镜像后重新应用队列中的修改
+///
+/// 被拉取的行即 D1 中的最新值，先记录为原始值，再按顺序应用该数据库的全部排队修改。
+pub(crate) fn reapply(
+    conn: &Connection,
+    source: &str,
+    pulled: &[i64],
+    reset: bool,
+) -> Result<(), AppError> {
+    if reset {
+        conn.execute("DELETE FROM pending_originals", [])
+            .map_err(local_error)?;
+    }
+    let operations = load(conn, Some(source))?;
+    let touched: BTreeSet<i64> = operations.iter().flat_map(|op| op.edit.ids()).collect();
+    let pulled: BTreeSet<i64> = pulled
+        .iter()
+        .copied()
+        .filter(|id| touched.contains(id))
+        .collect();
+    if pulled.is_empty() {
+        return Ok(());
+    }
+
+    snapshot(conn, &pulled, true)?;
+    for operation in &operations {
+        if let Some(edit) = operation.edit.restrict(&pulled) {
+            execute_on(conn, &edit.statements("smms_pictures", false))?;
+        }
+    }
+    Ok(())
+}
+
+/// D1 或 SM.MS 不可达（请求未完成或服务暂时不可用）
+fn is_offline(error: &AppError) -> bool {
+    matches!(error, AppError::Network { .. })
+}
+
+/// 在线执行的结果
+struct Executed {
+    message: String,
+    /// D1 返回的新 `updated_at`
+    versions: BTreeMap<i64, String>,
+    retries: u32,
+}
+
+/// 在 D1（删除时还有 SM.MS）上执行修改
+async fn execute(
+    d1: &D1Client,
+    smms: &SmmsClient,
+    edit: &PictureEdit,
+) -> Result<Executed, AppError> {
+    let (message, retries) = match edit {
+        PictureEdit::Delete { id } => delete_remote(d1, smms, *id).await?,
+        PictureEdit::Favorite { id, is_favorite } => (
+            format!(
+                "图片 {} 已{}收藏",
+                id,
+                if *is_favorite { "" } else { "取消" }
+            ),
+            0,
+        ),
+        PictureEdit::Remark { ids, .. } if ids.len() == 1 => ("备注更新成功".to_string(), 0),
+        PictureEdit::Remark { ids, .. } => (format!("成功更新 {} 张图片的备注", ids.len()), 0),
+    };
+
+    let result = d1.batch(edit.statements("smms_pictures", true)).await?;
+    result.check(|_| edit.describe())?;
+    let versions = result
+        .results
+        .iter()
+        .flat_map(|statement| &statement.results)
+        .filter_map(|row| Some((row["id"].as_i64()?, row["updated_at"].as_str()?.to_string())))
+        .collect();
+
+    Ok(Executed {
+        message,
+        versions,
+        retries,
+    })
+}
+
+/// 删除图片所需的字段
+#[derive(Deserialize)]
+struct PictureDeleteInfo {
+    delete_url: String,
+    filename: String,
+}
+
+/// 删除 SM.MS 上的文件，返回提示和重试次数
+async fn delete_remote(
+    d1: &D1Client,
+    smms: &SmmsClient,
+    id: i64,
+) -> Result<(String, u32), AppError> {
+    let statement =
+        D1Statement::new("SELECT delete_url, filename FROM smms_pictures WHERE id = ?").bind(id);
+    let PictureDeleteInfo {
+        delete_url,
+        filename,
+    } = d1
+        .query_as(statement)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::NotFound(format!("图片 {} 不存在", id)))?;
+
+    // delete_url 格式: https://sm.ms/delete/HASH
+    let hash = delete_url
+        .rsplit('/')
+        .next()
+        .ok_or_else(|| AppError::BadResponse("无法从 delete_url 中提取 hash".to_string()))?;
+
+    let token = credentials::load_token(d1).await?;
+    let delete = smms.delete(&token, hash).await;
+    let retries = delete.retries();
+    let response: SmmsDeleteResponse = delete.result?;
+
+    if response.success {
+        Ok((format!("图片 {} 删除成功", filename), retries))
+    } else if response.message.to_lowercase().contains("already deleted") {
+        Ok((
+            format!("图片 {} 已标记为删除（服务器上已不存在）", filename),
+            retries,
+        ))
+    } else {
+        Err(AppError::smms(
+            &response.code,
+            format!("删除失败: {}", response.message),
+        ))
+    }
+}
+
+/// D1 中各图片当前的 `updated_at`，不存在的图片不在结果中
+async fn remote_versions(
+    d1: &D1Client,
+    ids: &[i64],
+) -> Result<BTreeMap<i64, Option<String>>, AppError> {
+    let statements = ids
+        .chunks(MAX_IDS_PER_STATEMENT)
+        .map(|chunk| {
+            let statement = D1Statement::new(format!(
+                "SELECT id, updated_at FROM smms_pictures WHERE id IN ({})",
+                vec!["?"; chunk.len()].join(", ")
+            ));
+            chunk.iter().fold(statement, |stmt, id| stmt.bind(*id))
+        })
+        .collect();
+    let result = d1.batch(statements).await?;
+    result.check(|_| "读取图片版本".to_string())?;
+
+    Ok(result
+        .results
+        .iter()
+        .flat_map(|statement| &statement.results)
+        .filter_map(|row| {
+            let updated_at = row["updated_at"].as_str().map(str::to_string);
+            Some((row["id"].as_i64()?, updated_at))
+        })
+        .collect())
+}
+
+/// 检查图片在加入队列后是否被删除或被其他设备修改，返回应标记的状态和原因
+fn check_base(
+    operation: &PendingOperation,
+    current: &BTreeMap<i64, Option<String>>,
+) -> Option<(OperationStatus, String)> {
+    for id in operation.edit.ids() {
+        let Some(version) = current.get(&id) else {
+            return Some((OperationStatus::Failed, format!("图片 {} 不存在", id)));
+        };
+        // 加入队列时镜像中还没有该图片，无法判断是否冲突
+        if let Some(base) = operation.base.get(&id) {
+            if base != version {
+                return Some((
+                    OperationStatus::Conflict,
+                    format!(
+                        "图片 {} 已在其他设备上修改（{} → {}）",
+                        id,
+                        base.as_deref().unwrap_or("-"),
+                        version.as_deref().unwrap_or("-")
+                    ),
+                ));
+            }
+        }
+    }
+    None
+}
+
+/// 把修改加入队列并应用到镜像
+fn enqueue(mirror: &LocalMirror, source: &str, edit: PictureEdit) -> Result<EditOutcome, AppError> {
+    let mut conn = mirror.lock()?;
+    let tx = conn.transaction().map_err(local_error)?;
+
+    let mut base = BTreeMap::new();
+    for id in edit.ids() {
+        let version: Option<Option<String>> = tx
+            .query_row(
+                "SELECT updated_at FROM smms_pictures WHERE id = ?",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(local_error)?;
+        if let Some(version) = version {
+            base.insert(id, version);
+        }
+    }
+
+    snapshot(&tx, &edit.ids().into_iter().collect(), false)?;
+    execute_on(&tx, &edit.statements("smms_pictures", false))?;
+    tx.execute(
+        "INSERT INTO pending_operations (source, edit, base) VALUES (?, ?, ?)",
+        params![source, to_json(&edit)?, to_json(&base)?],
+    )
+    .map_err(local_error)?;
+    let seq = tx.last_insert_rowid();
+    tx.commit().map_err(local_error)?;
+
+    Ok(EditOutcome::Queued {
+        seq,
+        message: format!(
+            "网络不可用，已将「{}」加入离线队列，恢复连接后自动同步",
+            edit.describe()
+        ),
+    })
+}
+
+/// 操作已写入 D1：移出队列，记录新版本，并把同一图片上后续操作的基准版本更新为新版本
+fn complete(
+    mirror: &LocalMirror,
+    operation: &PendingOperation,
+    versions: &BTreeMap<i64, String>,
+) -> Result<(), AppError> {
+    let mut conn = mirror.lock()?;
+    let tx = conn.transaction().map_err(local_error)?;
+
+    tx.execute(
+        "DELETE FROM pending_operations WHERE seq = ?",
+        [operation.seq],
+    )
+    .map_err(local_error)?;
+    // D1 中的值已包含该修改，放弃后续操作时应恢复到这里
+    execute_on(&tx, &operation.edit.statements("pending_originals", false))?;
+    execute_on(&tx, &version_statements(versions))?;
+
+    let kept = load(&tx, None)?;
+    for later in kept
+        .iter()
+        .filter(|op| op.seq > operation.seq && op.source == operation.source)
+    {
+        let mut base = later.base.clone();
+        for (id, version) in versions {
+            if let (Some(entry), Some(old)) = (base.get_mut(id), operation.base.get(id)) {
+                if entry == old {
+                    *entry = Some(version.clone());
+                }
+            }
+        }
+        if base != later.base {
+            tx.execute(
+                "UPDATE pending_operations SET base = ? WHERE seq = ?",
+                params![to_json(&base)?, later.seq],
+            )
+            .map_err(local_error)?;
+        }
+    }
+    prune_originals(&tx, &kept)?;
+
+    tx.commit().map_err(local_error)
+}
+
+/// 记录重放结果
+fn mark(
+    mirror: &LocalMirror,
+    seq: i64,
+    status: OperationStatus,
+    error: &str,
+) -> Result<(), AppError> {
+    mirror
+        .lock()?
+        .execute(
+            "UPDATE pending_operations SET status = ?, error = ?, attempts = attempts + 1 WHERE seq = ?",
+            params![status.as_str(), error, seq],
+        )
+        .map(|_| ())
+        .map_err(local_error)
+}
+
+fn next_pending(
+    mirror: &LocalMirror,
+    source: &str,
+    after: i64,
+) -> Result<Option<PendingOperation>, AppError> {
+    Ok(load(&*mirror.lock()?, Some(source))?
+        .into_iter()
+        .find(|op| op.seq > after && op.status == OperationStatus::Pending))
+}
+
+fn count_pending(mirror: &LocalMirror, source: &str) -> Result<usize, AppError> {
+    mirror
+        .lock()?
+        .query_row(
+            "SELECT COUNT(*) FROM pending_operations WHERE source = ? AND status = 'pending'",
+            [source],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|count| count as usize)
+        .map_err(local_error)
+}
+
+/// 读取队列，`source` 为空时返回所有数据库的操作
+fn load(conn: &Connection, source: Option<&str>) -> Result<Vec<PendingOperation>, AppError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT seq, source, edit, base, status, error, attempts, created_at \
+             FROM pending_operations WHERE ?1 IS NULL OR source = ?1 ORDER BY seq",
+        )
+        .map_err(local_error)?;
+    let rows = stmt
+        .query_map([source], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, u32>(6)?,
+                row.get::<_, Option<String>>(7)?,
+            ))
+        })
+        .map_err(local_error)?;
+
+    let mut operations = Vec::new();
+    for row in rows {
+        let (seq, source, edit, base, status, error, attempts, created_at) =
+            row.map_err(local_error)?;
+        let edit: PictureEdit = from_json(&edit)?;
+        operations.push(PendingOperation {
+            seq,
+            source,
+            description: edit.describe(),
+            edit,
+            base: from_json(&base)?,
+            status: OperationStatus::parse(&status),
+            error,
+            attempts,
+            created_at: created_at.unwrap_or_default(),
+        });
+    }
+    Ok(operations)
+}
+
+/// 记录图片在 D1 中的值，`replace` 为否时保留已有记录
+fn snapshot(conn: &Connection, ids: &BTreeSet<i64>, replace: bool) -> Result<(), AppError> {
+    let sql = format!(
+        "INSERT OR {} INTO pending_originals (id, is_favorite, is_deleted, deleted_at, remark) \
+         SELECT id, is_favorite, is_deleted, deleted_at, remark FROM smms_pictures WHERE id = ?",
+        if replace { "REPLACE" } else { "IGNORE" }
+    );
+    let mut stmt = conn.prepare(&sql).map_err(local_error)?;
+    for id in ids {
+        stmt.execute([id]).map_err(local_error)?;
+    }
+    Ok(())
+}
+
+/// 删除不再被队列引用的原始值
+fn prune_originals(conn: &Connection, kept: &[PendingOperation]) -> Result<(), AppError> {
+    let referenced: BTreeSet<i64> = kept.iter().flat_map(|op| op.edit.ids()).collect();
+    let stored: Vec<i64> = {
+        let mut stmt = conn
+            .prepare("SELECT id FROM pending_originals")
+            .map_err(local_error)?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(local_error)?
+            .collect::<Result<_, _>>()
+            .map_err(local_error)?;
+        ids
+    };
+    for id in stored.into_iter().filter(|id| !referenced.contains(id)) {
+        conn.execute("DELETE FROM pending_originals WHERE id = ?", [id])
+            .map_err(local_error)?;
+    }
+    Ok(())
+}
+
+/// 把 D1 返回的新版本写入镜像
+fn version_statements(versions: &BTreeMap<i64, String>) -> Vec<D1Statement> {
+    versions
+        .iter()
+        .map(|(id, version)| {
+            D1Statement::new("UPDATE smms_pictures SET updated_at = ? WHERE id = ?")
+                .bind(version)
+                .bind(*id)
+        })
+        .collect()
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, AppError> {
+    serde_json::to_string(value)
+        .map_err(|e| AppError::LocalDb(format!("离线队列序列化失败: {}", e)))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: &str) -> Result<T, AppError> {
+    serde_json::from_str(value).map_err(|e| AppError::LocalDb(format!("离线队列记录损坏: {}", e)))
+}
@@ -0,0 +1,119 @@
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::AppError;
+use crate::models::D1Param;
+
+/// 列表排序：排序列和方向，排序列相同时按 `id` 同向排序，保证顺序唯一
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: &'static str,
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// 解析前端的 `order_by`（如 `created_at_desc`），无法识别时按创建时间倒序
+    pub fn parse(order_by: Option<&str>) -> Self {
+        let (column, descending) = match order_by.unwrap_or("created_at_desc") {
+            "created_at_asc" => ("created_at", false),
+            "updated_at_asc" => ("updated_at", false),
+            "updated_at_desc" => ("updated_at", true),
+            "size_asc" => ("size", false),
+            "size_desc" => ("size", true),
+            "width_asc" => ("width", false),
+            "width_desc" => ("width", true),
+            "height_asc" => ("height", false),
+            "height_desc" => ("height", true),
+            "filename_asc" => ("filename", false),
+            "filename_desc" => ("filename", true),
+            _ => ("created_at", true),
+        };
+        Self { column, descending }
+    }
+
+    fn direction(&self) -> &'static str {
+        if self.descending {
+            "DESC"
+        } else {
+            "ASC"
+        }
+    }
+
+    /// 游标中记录的排序名称，换了排序方式的游标不能继续使用
+    fn name(&self) -> String {
+        format!("{}_{}", self.column, self.direction().to_lowercase())
+    }
+
+    /// 排序表达式，文件名不区分大小写
+    fn expr(&self) -> String {
+        match self.column {
+            "filename" => "filename COLLATE NOCASE".to_string(),
+            column => column.to_string(),
+        }
+    }
+
+    /// `ORDER BY` 子句的内容
+    pub fn order_clause(&self) -> String {
+        format!("{} {dir}, id {dir}", self.expr(), dir = self.direction())
+    }
+}
+
+/// 游标内容：上一页最后一行的排序列值和 id
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: String,
+    value: Value,
+    id: i64,
+}
+
+/// 根据一页的最后一行生成下一页的游标
+pub fn next_cursor(sort: &SortKey, last: &Value) -> String {
+    let cursor = Cursor {
+        sort: sort.name(),
+        value: last.get(sort.column).cloned().unwrap_or(Value::Null),
+        id: last["id"].as_i64().unwrap_or_default(),
+    };
+    let json = serde_json::to_vec(&cursor).unwrap_or_default();
+    general_purpose::URL_SAFE_NO_PAD.encode(json)
+}
+
+/// 把游标转换为 WHERE 条件：只取排在游标所指行之后的行
+pub fn keyset_condition(sort: &SortKey, cursor: &str) -> Result<(String, Vec<D1Param>), AppError> {
+    let invalid = || AppError::InvalidInput("分页游标无效，请从第一页重新加载".to_string());
+    let cursor: Cursor = general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(invalid)?;
+    if cursor.sort != sort.name() {
+        return Err(invalid());
+    }
+
+    let column = sort.expr();
+    let op = if sort.descending { "<" } else { ">" };
+    let value = match cursor.value {
+        Value::String(s) => D1Param::Text(s),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => D1Param::Integer(i),
+            None => D1Param::Real(n.as_f64().ok_or_else(invalid)?),
+        },
+        // SQLite 中 NULL 排在最前：升序时所有非空值都在其后，降序时只剩同为 NULL 的行
+        Value::Null => {
+            let sql = if sort.descending {
+                format!("({column} IS NULL AND id < ?)")
+            } else {
+                format!("({column} IS NOT NULL OR id > ?)")
+            };
+            return Ok((sql, vec![cursor.id.into()]));
+        }
+        _ => return Err(invalid()),
+    };
+
+    let mut sql = format!("({column} {op} ? OR ({column} = ? AND id {op} ?))");
+    if sort.descending {
+        // 降序时 NULL 排在最后，同样属于游标之后
+        sql = format!("({sql} OR {column} IS NULL)");
+    }
+    Ok((sql, vec![value.clone(), value, cursor.id.into()]))
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::AppError;
+use crate::models::{
+    D1Param, D1Statement, Orientation, PictureFacets, PicturePage, PictureQueryParams,
+    PictureQueryResult, SmmsPicture,
+};
+use crate::services::mirror::LocalMirror;
+use crate::services::pagination::{self, SortKey};
+use crate::services::rows;
+
+/// 游标分页未指定 `limit` 时的每页数量
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+
+#[derive(Deserialize)]
+struct CountRow {
+    count: i64,
+}
+
+#[derive(Deserialize)]
+struct FavoriteRow {
+    favorites: i64,
+    not_favorites: i64,
+}
+
+#[derive(Deserialize)]
+struct DeletedRow {
+    active: i64,
+    deleted: i64,
+}
+
+/// 统计匹配筛选条件的图片数量
+pub fn count(mirror: &LocalMirror, params: &PictureQueryParams) -> Result<i64, AppError> {
+    let rows: Vec<CountRow> = mirror.query_as(&count_statement(params)?)?;
+    Ok(rows.first().map_or(0, |row| row.count))
+}
+
+/// 按 `limit`/`offset` 查询图片列表，两者都未指定时返回全部
+pub fn list(
+    mirror: &LocalMirror,
+    params: &PictureQueryParams,
+) -> Result<Vec<SmmsPicture>, AppError> {
+    let (conditions, mut binds) = filter(params)?;
+    let sort = SortKey::parse(params.order_by.as_deref());
+    let mut sql = format!(
+        "SELECT * FROM smms_pictures{} ORDER BY {}",
+        conditions,
+        sort.order_clause()
+    );
+
+    // 分页（SQLite 要求 OFFSET 前必须有 LIMIT，-1 表示不限制）
+    if params.limit.is_some() || params.offset.is_some() {
+        sql.push_str(" LIMIT ? OFFSET ?");
+        binds.push(params.limit.unwrap_or(-1).into());
+        binds.push(params.offset.unwrap_or(0).into());
+    }
+
+    mirror.query_as(&D1Statement { sql, params: binds })
+}
+
+/// 按游标（或偏移）查询一页图片
+pub fn page(mirror: &LocalMirror, params: &PictureQueryParams) -> Result<PicturePage, AppError> {
+    let page = PageQuery::new(params)?;
+    page.finish(&mirror.query(&page.statement)?)
+}
+
+/// 在镜像的同一时刻查询当前页、匹配总数和分面计数
+pub fn query(
+    mirror: &LocalMirror,
+    params: &PictureQueryParams,
+) -> Result<PictureQueryResult, AppError> {
+    let page = PageQuery::new(params)?;
+    let statements = [
+        page.statement.clone(),
+        count_statement(params)?,
+        file_type_facet(params)?,
+        favorite_facet(params)?,
+        deleted_facet(params)?,
+    ];
+    let results = mirror.query_many(&statements)?;
+    let [items, total, file_types, favorites, deleted] = &results[..] else {
+        return Err(AppError::LocalDb("查询结果数量不匹配".to_string()));
+    };
+
+    let page = page.finish(items)?;
+    let total: Vec<CountRow> = rows::decode_rows(total)?;
+    let favorites: Vec<FavoriteRow> = rows::decode_rows(favorites)?;
+    let deleted: Vec<DeletedRow> = rows::decode_rows(deleted)?;
+    let mut facets = PictureFacets {
+        file_types: rows::decode_rows(file_types)?,
+        ..Default::default()
+    };
+    if let Some(row) = favorites.first() {
+        facets.favorites = row.favorites;
+        facets.not_favorites = row.not_favorites;
+    }
+    if let Some(row) = deleted.first() {
+        facets.active = row.active;
+        facets.deleted = row.deleted;
+    }
+
+    Ok(PictureQueryResult {
+        items: page.items,
+        next_cursor: page.next_cursor,
+        total: total.first().map_or(0, |row| row.count),
+        facets,
+    })
+}
+
+/// 一页查询的语句以及生成下一页游标所需的信息
+struct PageQuery {
+    statement: D1Statement,
+    sort: SortKey,
+    limit: i64,
+}
+
+impl PageQuery {
+    fn new(params: &PictureQueryParams) -> Result<Self, AppError> {
+        let limit = params.limit.filter(|l| *l > 0).unwrap_or(DEFAULT_PAGE_SIZE);
+        let sort = SortKey::parse(params.order_by.as_deref());
+        let (mut conditions, mut binds) = filter(params)?;
+        if let Some(cursor) = params.cursor.as_deref() {
+            if params.offset.is_some() {
+                return Err(AppError::InvalidInput(
+                    "cursor 与 offset 不能同时使用".to_string(),
+                ));
+            }
+            let (condition, values) = pagination::keyset_condition(&sort, cursor)?;
+            conditions.push_str(&format!(" AND {}", condition));
+            binds.extend(values);
+        }
+
+        let mut sql = format!(
+            "SELECT * FROM smms_pictures{} ORDER BY {} LIMIT ?",
+            conditions,
+            sort.order_clause()
+        );
+        binds.push(limit.into());
+        if let Some(offset) = params.offset {
+            sql.push_str(" OFFSET ?");
+            binds.push(offset.into());
+        }
+
+        Ok(Self {
+            statement: D1Statement { sql, params: binds },
+            sort,
+            limit,
+        })
+    }
+
+    fn finish(&self, rows: &[Value]) -> Result<PicturePage, AppError> {
+        // 不足一页说明已经到底
+        let next_cursor = match rows.last() {
+            Some(last) if rows.len() as i64 == self.limit => {
+                Some(pagination::next_cursor(&self.sort, last))
+            }
+            _ => None,
+        };
+        Ok(PicturePage {
+            items: rows::decode_rows(rows)?,
+            next_cursor,
+        })
+    }
+}
+
+fn count_statement(params: &PictureQueryParams) -> Result<D1Statement, AppError> {
+    let (conditions, binds) = filter(params)?;
+    Ok(D1Statement {
+        sql: format!("SELECT COUNT(*) AS count FROM smms_pictures{}", conditions),
+        params: binds,
+    })
+}
+
+/// 各文件类型的数量，不受文件类型筛选影响
+fn file_type_facet(params: &PictureQueryParams) -> Result<D1Statement, AppError> {
+    let (conditions, binds) = filter(&PictureQueryParams {
+        file_type: None,
+        file_types: None,
+        ..params.clone()
+    })?;
+    Ok(D1Statement {
+        sql: format!(
+            "SELECT file_type AS value, COUNT(*) AS count FROM smms_pictures{} \
+             GROUP BY file_type ORDER BY file_type",
+            conditions
+        ),
+        params: binds,
+    })
+}
+
+/// 收藏与未收藏的数量，不受收藏筛选影响
+fn favorite_facet(params: &PictureQueryParams) -> Result<D1Statement, AppError> {
+    let (conditions, binds) = filter(&PictureQueryParams {
+        is_favorite: None,
+        ..params.clone()
+    })?;
+    Ok(D1Statement {
+        sql: format!(
+            "SELECT COALESCE(SUM(is_favorite = 1), 0) AS favorites, \
+             COALESCE(SUM(is_favorite = 0), 0) AS not_favorites FROM smms_pictures{}",
+            conditions
+        ),
+        params: binds,
+    })
+}
+
+/// 未删除与已删除的数量，不受删除状态筛选影响
+fn deleted_facet(params: &PictureQueryParams) -> Result<D1Statement, AppError> {
+    let (conditions, binds) = filter(&PictureQueryParams {
+        include_deleted: None,
+        ..params.clone()
+    })?;
+    Ok(D1Statement {
+        sql: format!(
+            "SELECT COALESCE(SUM(is_deleted = 0), 0) AS active, \
+             COALESCE(SUM(is_deleted = 1), 0) AS deleted FROM smms_pictures{}",
+            conditions
+        ),
+        params: binds,
+    })
+}
+
+/// 将关键字转换为 LIKE 模式，转义其中的通配符（配合 `ESCAPE '\'` 使用）
+pub(crate) fn like_pattern(keyword: &str) -> String {
+    let escaped = keyword
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// 根据查询参数构建 WHERE 子句及绑定参数（列表、计数和分面共用）
+pub fn filter(params: &PictureQueryParams) -> Result<(String, Vec<D1Param>), AppError> {
+    let mut sql = String::from(" WHERE 1=1");
+    let mut binds = Vec::new();
+
+    // 删除状态筛选：None=全部, Some(false)=仅未删除, Some(true)=仅已删除
+    match params.include_deleted {
+        Some(false) => sql.push_str(" AND is_deleted = 0"),
+        Some(true) => sql.push_str(" AND is_deleted = 1"),
+        None => {} // 全部显示，不添加条件
+    }
+
+    // 追加筛选条件
+    let file_types: Vec<&String> = params
+        .file_type
+        .iter()
+        .chain(params.file_types.iter().flatten())
+        .filter(|ft| !ft.is_empty())
+        .collect();
+    if !file_types.is_empty() {
+        sql.push_str(&format!(
+            " AND file_type IN ({})",
+            vec!["?"; file_types.len()].join(", ")
+        ));
+        binds.extend(file_types.into_iter().map(D1Param::from));
+    }
+    if let Some(fav) = params.is_favorite {
+        sql.push_str(" AND is_favorite = ?");
+        binds.push(fav.into());
+    }
+
+    // 文件名、存储名、备注模糊搜索
+    let keywords = [
+        ("filename", &params.filename),
+        ("store_name", &params.store_name),
+        ("remark", &params.remark),
+    ];
+    for (column, keyword) in keywords {
+        if let Some(keyword) = keyword.as_ref().filter(|k| !k.is_empty()) {
+            sql.push_str(&format!(" AND {} LIKE ? ESCAPE '\\'", column));
+            binds.push(like_pattern(keyword).into());
+        }
+    }
+
+    // 时间范围
+    let dates = [
+        ("created_at", &params.created_from, &params.created_to),
+        ("updated_at", &params.updated_from, &params.updated_to),
+    ];
+    for (column, from, to) in dates {
+        if let Some(from) = from.as_deref().filter(|v| !v.trim().is_empty()) {
+            let (value, _) = parse_date_bound(from)?;
+            sql.push_str(&format!(" AND {} >= ?", column));
+            binds.push(value.into());
+        }
+        if let Some(to) = to.as_deref().filter(|v| !v.trim().is_empty()) {
+            // 只有日期时包含当天的全部时间
+            let (value, date_only) = parse_date_bound(to)?;
+            if date_only {
+                sql.push_str(&format!(" AND {} < date(?, '+1 day')", column));
+            } else {
+                sql.push_str(&format!(" AND {} <= ?", column));
+            }
+            binds.push(value.into());
+        }
+    }
+
+    // 大小和尺寸范围
+    let ranges = [
+        ("size", params.min_size, params.max_size),
+        ("width", params.min_width, params.max_width),
+        ("height", params.min_height, params.max_height),
+    ];
+    for (column, min, max) in ranges {
+        if let Some(min) = min {
+            sql.push_str(&format!(" AND {} >= ?", column));
+            binds.push(min.into());
+        }
+        if let Some(max) = max {
+            sql.push_str(&format!(" AND {} <= ?", column));
+            binds.push(max.into());
+        }
+    }
+
+    // 宽高比和方向（高度为 0 的异常记录不参与比较）
+    if let Some(min) = params.min_aspect_ratio {
+        sql.push_str(" AND CAST(width AS REAL) / NULLIF(height, 0) >= ?");
+        binds.push(min.into());
+    }
+    if let Some(max) = params.max_aspect_ratio {
+        sql.push_str(" AND CAST(width AS REAL) / NULLIF(height, 0) <= ?");
+        binds.push(max.into());
+    }
+    match params.orientation {
+        Some(Orientation::Landscape) => sql.push_str(" AND width > height"),
+        Some(Orientation::Portrait) => sql.push_str(" AND width < height"),
+        Some(Orientation::Square) => sql.push_str(" AND width = height"),
+        None => {}
+    }
+
+    // 标签
+    if let Some(tags) = tag_ids(&params.tags_all) {
+        sql.push_str(&format!(
+            " AND id IN (SELECT picture_id FROM picture_tags WHERE tag_id IN ({}) \
+             GROUP BY picture_id HAVING COUNT(*) = {})",
+            vec!["?"; tags.len()].join(", "),
+            tags.len()
+        ));
+        binds.extend(tags.into_iter().map(D1Param::from));
+    }
+    if let Some(tags) = tag_ids(&params.tags_any) {
+        sql.push_str(&format!(
+            " AND id IN (SELECT picture_id FROM picture_tags WHERE tag_id IN ({}))",
+            vec!["?"; tags.len()].join(", ")
+        ));
+        binds.extend(tags.into_iter().map(D1Param::from));
+    }
+    if let Some(tags) = tag_ids(&params.tags_none) {
+        sql.push_str(&format!(
+            " AND id NOT IN (SELECT picture_id FROM picture_tags WHERE tag_id IN ({}))",
+            vec!["?"; tags.len()].join(", ")
+        ));
+        binds.extend(tags.into_iter().map(D1Param::from));
+    }
+    if let Some(album_id) = params.album_id {
+        sql.push_str(" AND id IN (SELECT picture_id FROM album_pictures WHERE album_id = ?)");
+        binds.push(D1Param::from(album_id));
+    }
+
+    Ok((sql, binds))
+}
+
+/// 去重后的标签 id，为空时不筛选
+fn tag_ids(ids: &Option<Vec<i64>>) -> Option<Vec<i64>> {
+    let ids: BTreeSet<i64> = ids.iter().flatten().copied().collect();
+    (!ids.is_empty()).then(|| ids.into_iter().collect())
+}
+
+/// 校验时间筛选值，返回规范化的值（`T` 换成空格）以及是否只有日期
+fn parse_date_bound(value: &str) -> Result<(String, bool), AppError> {
+    let value = value.trim().replacen('T', " ", 1);
+    let matches = |pattern: &str| {
+        value.len() == pattern.len()
+            && value.bytes().zip(pattern.bytes()).all(|(c, p)| match p {
+                b'9' => c.is_ascii_digit(),
+                _ => c == p,
+            })
+    };
+    if matches("9999-99-99") {
+        Ok((value, true))
+    } else if matches("9999-99-99 99:99:99") {
+        Ok((value, false))
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "时间格式无效: {}，应为 YYYY-MM-DD 或 YYYY-MM-DD HH:MM:SS",
+            value
+        )))
+    }
+}
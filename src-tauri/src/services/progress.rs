@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tauri::{AppHandle, Emitter, Runtime};
+
+use crate::models::{JobProgress, JobStage};
+use crate::services::jobs::{CancelToken, Job};
+
+/// 进度事件名
+pub const PROGRESS_EVENT: &str = "job-progress";
+
+/// 下载时每传输这么多字节发送一次进度
+const REPORT_STEP: u64 = 256 * 1024;
+
+type Sink = Arc<dyn Fn(JobProgress) + Send + Sync>;
+
+/// 一个任务（一次上传或下载命令）的进度发送器
+#[derive(Clone)]
+pub struct Progress {
+    job_id: String,
+    file_count: usize,
+    sink: Sink,
+    cancel: CancelToken,
+}
+
+impl Progress {
+    pub fn new(
+        job_id: impl Into<String>,
+        file_count: usize,
+        sink: impl Fn(JobProgress) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            job_id: job_id.into(),
+            file_count,
+            sink: Arc::new(sink),
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// 以 [`PROGRESS_EVENT`] 事件发送给前端，并跟随任务的取消标记
+    pub fn to_app<R: Runtime>(app: &AppHandle<R>, job: &Job<'_>, file_count: usize) -> Self {
+        let app = app.clone();
+        Self::new(job.id(), file_count, move |event| {
+            if let Err(e) = app.emit(PROGRESS_EVENT, event) {
+                eprintln!("发送进度事件失败: {}", e);
+            }
+        })
+        .with_cancel_token(job.token().clone())
+    }
+
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// 不发送任何事件
+    pub fn silent(file_count: usize) -> Self {
+        Self::new(String::new(), file_count, |_| {})
+    }
+
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    /// 任务是否已被取消
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// 第 `index` 个文件的进度
+    pub fn file(&self, index: usize, filename: &str) -> FileProgress<'_> {
+        FileProgress {
+            progress: self,
+            index,
+            filename: filename.to_string(),
+            reported: AtomicU64::new(0),
+        }
+    }
+}
+
+/// 单个文件的进度
+pub struct FileProgress<'a> {
+    progress: &'a Progress,
+    index: usize,
+    filename: String,
+    /// 上次发送传输进度时的字节数
+    reported: AtomicU64,
+}
+
+impl FileProgress<'_> {
+    pub fn report(&self, stage: JobStage, bytes: u64, total: Option<u64>) {
+        self.send(stage, bytes, total, None);
+    }
+
+    /// 所属任务是否已被取消
+    pub fn is_cancelled(&self) -> bool {
+        self.progress.is_cancelled()
+    }
+
+    pub fn done(&self, bytes: u64) {
+        self.send(JobStage::Done, bytes, Some(bytes), None);
+    }
+
+    pub fn failed(&self, message: impl Into<String>) {
+        self.send(JobStage::Failed, 0, None, Some(message.into()));
+    }
+
+    pub fn cancelled(&self) {
+        self.send(JobStage::Cancelled, 0, None, None);
+    }
+
+    /// 传输中的进度，距上次发送不足 [`REPORT_STEP`] 字节且尚未传输完时不发送
+    pub fn transferred(&self, stage: JobStage, bytes: u64, total: Option<u64>) {
+        let reported = self.reported.load(Ordering::Relaxed);
+        if bytes.saturating_sub(reported) < REPORT_STEP && Some(bytes) != total {
+            return;
+        }
+        self.reported.store(bytes, Ordering::Relaxed);
+        self.report(stage, bytes, total);
+    }
+
+    fn send(&self, stage: JobStage, bytes: u64, total: Option<u64>, message: Option<String>) {
+        (self.progress.sink)(JobProgress {
+            job_id: self.progress.job_id.clone(),
+            file_index: self.index,
+            file_count: self.progress.file_count,
+            filename: self.filename.clone(),
+            stage,
+            bytes,
+            total,
+            message,
+        });
+    }
+}
@@ -0,0 +1,232 @@
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+use crate::error::AppError;
+
+/// 请求能否安全重放
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Idempotency {
+    /// 重复执行结果相同（查询、UPSERT、删除等）
+    Idempotent,
+    /// 重复执行可能产生副作用（如上传），只在服务端明确未处理时重试
+    NonIdempotent,
+}
+
+/// 失败是否值得重试
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryHint {
+    /// 不重试（参数错误、鉴权失败等）
+    Never,
+    /// 服务端可能已处理（超时、502/504、没有 `Retry-After` 的 503 等），只有幂等请求才重试
+    Transient,
+    /// 服务端明确拒绝、未处理（连接失败、429、带 `Retry-After` 的 503），可在等待后重试
+    Rejected { retry_after: Option<Duration> },
+}
+
+/// 单次尝试的错误
+#[derive(Debug)]
+pub struct AttemptError {
+    pub error: AppError,
+    pub hint: RetryHint,
+}
+
+impl AttemptError {
+    pub fn fatal(error: AppError) -> Self {
+        Self {
+            error,
+            hint: RetryHint::Never,
+        }
+    }
+
+    fn network(message: String, status: Option<u16>, hint: RetryHint) -> Self {
+        Self {
+            error: AppError::Network {
+                message,
+                status,
+                retryable: hint != RetryHint::Never,
+            },
+            hint,
+        }
+    }
+
+    /// 根据 reqwest 发送错误分类
+    pub fn from_send(context: &str, error: reqwest::Error) -> Self {
+        let hint = if error.is_connect() {
+            // 连接未建立，请求一定没有到达服务端
+            RetryHint::Rejected { retry_after: None }
+        } else if error.is_timeout() || error.is_request() {
+            RetryHint::Transient
+        } else {
+            RetryHint::Never
+        };
+        Self::network(format!("{}: {}", context, error), None, hint)
+    }
+
+    /// 读取或解析响应体失败：格式错误不重试，读取中断按暂时性错误处理
+    pub fn from_body(context: &str, error: reqwest::Error) -> Self {
+        let message = format!("{}: {}", context, error);
+        if error.is_decode() {
+            Self::fatal(AppError::BadResponse(message))
+        } else {
+            Self::network(message, None, RetryHint::Transient)
+        }
+    }
+
+    /// 根据 HTTP 状态码分类，成功或普通客户端错误返回 `None`
+    pub fn from_status(status: StatusCode, headers: &HeaderMap) -> Option<Self> {
+        let hint = match status {
+            StatusCode::TOO_MANY_REQUESTS => RetryHint::Rejected {
+                retry_after: parse_retry_after(headers),
+            },
+            // 代理或源站也可能在接收请求体之后返回 503，只有明确要求稍后重试时才视为未处理
+            StatusCode::SERVICE_UNAVAILABLE => match parse_retry_after(headers) {
+                Some(retry_after) => RetryHint::Rejected {
+                    retry_after: Some(retry_after),
+                },
+                None => RetryHint::Transient,
+            },
+            StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::GATEWAY_TIMEOUT => RetryHint::Transient,
+            _ => return None,
+        };
+        Some(Self::network(
+            format!("HTTP {}", status),
+            Some(status.as_u16()),
+            hint,
+        ))
+    }
+}
+
+/// 带尝试次数的执行结果
+#[derive(Debug)]
+pub struct Retried<T> {
+    pub result: Result<T, AppError>,
+    /// 实际发出的请求次数（至少为 1）
+    pub attempts: u32,
+}
+
+impl<T> Retried<T> {
+    /// 额外的重试次数
+    pub fn retries(&self) -> u32 {
+        self.attempts.saturating_sub(1)
+    }
+
+    /// 继续处理成功结果，保留尝试次数
+    pub fn and_then<U>(self, f: impl FnOnce(T) -> Result<U, AppError>) -> Retried<U> {
+        Retried {
+            result: self.result.and_then(f),
+            attempts: self.attempts,
+        }
+    }
+}
+
+/// 指数退避重试策略（full jitter）
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// 最多尝试次数（包含第一次）
+    pub max_attempts: u32,
+    /// 第一次重试前的退避上限
+    pub base_delay: Duration,
+    /// 单次等待上限；服务端要求的 `Retry-After` 超过此值时放弃重试
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试的策略
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// 执行 `operation`，按策略重试可重试的失败
+    pub async fn run<T, F, Fut>(&self, idempotency: Idempotency, mut operation: F) -> Retried<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AttemptError>>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match operation().await {
+                Ok(value) => {
+                    return Retried {
+                        result: Ok(value),
+                        attempts,
+                    }
+                }
+                Err(error) => error,
+            };
+
+            let delay = match (error.hint, idempotency) {
+                _ if attempts >= self.max_attempts => None,
+                (RetryHint::Rejected { retry_after }, _) => self.delay_for(attempts, retry_after),
+                (RetryHint::Transient, Idempotency::Idempotent) => self.delay_for(attempts, None),
+                _ => None,
+            };
+
+            match delay {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => {
+                    let error = if attempts > 1 {
+                        error
+                            .error
+                            .map_message(|m| format!("{}（已尝试 {} 次）", m, attempts))
+                    } else {
+                        error.error
+                    };
+                    return Retried {
+                        result: Err(error),
+                        attempts,
+                    };
+                }
+            }
+        }
+    }
+
+    /// 计算第 `attempt` 次失败后的等待时间，`Retry-After` 过长时返回 `None`
+    fn delay_for(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+
+        let exponent = attempt.saturating_sub(1).min(16);
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        let millis = ceiling.as_millis() as u64;
+        Some(Duration::from_millis(
+            rand::thread_rng().gen_range(0..=millis),
+        ))
+    }
+}
+
+/// 解析 `Retry-After`（秒数或 HTTP 日期）
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::error::AppError;
+
+/// 把 D1 返回的一行结果解码为 `T`，错误信息包含行号和列名
+///
+/// 兼容 SQLite 的常见情况：整数表示布尔值、NULL 对应 `Option`、
+/// 数字以字符串形式返回（反之亦然）。
+pub fn decode_row<T: DeserializeOwned>(index: usize, row: &Value) -> Result<T, AppError> {
+    let object = row
+        .as_object()
+        .ok_or_else(|| AppError::Decode(format!("记录 {} 不是对象", index)))?;
+
+    T::deserialize(RowDeserializer { row: object }).map_err(|e| {
+        AppError::Decode(match e.column {
+            Some(column) => format!("记录 {} 的 {} 字段无效: {}", index, column, e.message),
+            None => format!("记录 {} 解析失败: {}", index, e.message),
+        })
+    })
+}
+
+/// 解码全部结果行
+pub fn decode_rows<T: DeserializeOwned>(rows: &[Value]) -> Result<Vec<T>, AppError> {
+    rows.iter()
+        .enumerate()
+        .map(|(index, row)| decode_row(index, row))
+        .collect()
+}
+
+/// 字段为 NULL 时使用默认值，配合 `#[serde(default, deserialize_with = ...)]` 使用
+pub fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug)]
+struct RowError {
+    column: Option<String>,
+    message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "{}: {}", column, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for RowError {}
+
+impl de::Error for RowError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self {
+            column: None,
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RowError {
+    fn from(error: serde_json::Error) -> Self {
+        de::Error::custom(error)
+    }
+}
+
+/// 整行：以列名为键的 map
+struct RowDeserializer<'a> {
+    row: &'a Map<String, Value>,
+}
+
+impl<'de, 'a> Deserializer<'de> for RowDeserializer<'a> {
+    type Error = RowError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        visitor.visit_map(RowAccess {
+            columns: self.row.iter(),
+            current: None,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct RowAccess<'a> {
+    columns: serde_json::map::Iter<'a>,
+    current: Option<(&'a String, &'a Value)>,
+}
+
+impl<'de, 'a> MapAccess<'de> for RowAccess<'a> {
+    type Error = RowError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, RowError> {
+        match self.columns.next() {
+            Some((column, value)) => {
+                self.current = Some((column, value));
+                seed.deserialize(column.as_str().into_deserializer())
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, RowError> {
+        let (column, value) = self
+            .current
+            .take()
+            .ok_or_else(|| <RowError as de::Error>::custom("缺少列值"))?;
+        seed.deserialize(ColumnDeserializer { value })
+            .map_err(|mut e| {
+                e.column.get_or_insert_with(|| column.clone());
+                e
+            })
+    }
+}
+
+/// 单列的值，按目标类型宽松转换
+struct ColumnDeserializer<'a> {
+    value: &'a Value,
+}
+
+impl ColumnDeserializer<'_> {
+    fn invalid(&self, expected: &str) -> RowError {
+        de::Error::custom(format!("期望{}，实际为 {}", expected, self.value))
+    }
+
+    fn integer(&self) -> Result<i128, RowError> {
+        match self.value {
+            Value::Number(n) => n
+                .as_i64()
+                .map(i128::from)
+                .or_else(|| n.as_u64().map(i128::from))
+                .or_else(|| n.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i128)),
+            Value::String(s) => s.trim().parse().ok(),
+            Value::Bool(b) => Some(i128::from(*b)),
+            _ => None,
+        }
+        .ok_or_else(|| self.invalid("整数"))
+    }
+
+    fn float(&self) -> Result<f64, RowError> {
+        match self.value {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| self.invalid("数字"))
+    }
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident => $visit:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+                let value = self.integer()?;
+                let value = <$ty>::try_from(value).map_err(|_| self.invalid(stringify!($ty)))?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for ColumnDeserializer<'a> {
+    type Error = RowError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        self.value
+            .clone()
+            .deserialize_any(visitor)
+            .map_err(RowError::from)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        let value = match self.value {
+            Value::Bool(b) => *b,
+            Value::String(s) if s.eq_ignore_ascii_case("true") => true,
+            Value::String(s) if s.eq_ignore_ascii_case("false") => false,
+            _ => self.integer().map_err(|_| self.invalid("布尔值"))? != 0,
+        };
+        visitor.visit_bool(value)
+    }
+
+    deserialize_integer! {
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        visitor.visit_f32(self.float()? as f32)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        visitor.visit_f64(self.float()?)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        match self.value {
+            Value::String(s) => visitor.visit_str(s),
+            Value::Number(n) => visitor.visit_string(n.to_string()),
+            _ => Err(self.invalid("文本")),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RowError> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, RowError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
@@ -0,0 +1,165 @@
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::models::{D1Statement, PictureQueryParams, SavedSearch, SavedSearchUpdate};
+use crate::services::d1::D1Client;
+use crate::services::migrations;
+use crate::services::picture_query;
+
+/// `saved_searches` 表中的一行，筛选条件以 JSON 保存
+#[derive(Deserialize)]
+struct SavedSearchRow {
+    id: i64,
+    name: String,
+    params: String,
+    is_smart_album: i64,
+    created_at: String,
+    updated_at: String,
+}
+
+impl TryFrom<SavedSearchRow> for SavedSearch {
+    type Error = AppError;
+
+    fn try_from(row: SavedSearchRow) -> Result<Self, AppError> {
+        let params = serde_json::from_str(&row.params).map_err(|e| {
+            AppError::Decode(format!(
+                "保存的搜索「{}」的筛选条件无法解析: {}",
+                row.name, e
+            ))
+        })?;
+        Ok(SavedSearch {
+            id: row.id,
+            name: row.name,
+            params,
+            smart_album: row.is_smart_album != 0,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+/// 列出保存的搜索，`smart_albums_only` 为 true 时只返回智能相册
+pub async fn list(d1: &D1Client, smart_albums_only: bool) -> Result<Vec<SavedSearch>, AppError> {
+    migrations::ensure_ready(d1).await?;
+    let sql = if smart_albums_only {
+        "SELECT * FROM saved_searches WHERE is_smart_album = 1 ORDER BY name COLLATE NOCASE, id"
+    } else {
+        "SELECT * FROM saved_searches ORDER BY name COLLATE NOCASE, id"
+    };
+    d1.query_as::<SavedSearchRow>(D1Statement::new(sql))
+        .await?
+        .into_iter()
+        .map(SavedSearch::try_from)
+        .collect()
+}
+
+/// 按 id 读取保存的搜索
+pub async fn get(d1: &D1Client, id: i64) -> Result<SavedSearch, AppError> {
+    migrations::ensure_ready(d1).await?;
+    let statement = D1Statement::new("SELECT * FROM saved_searches WHERE id = ?").bind(id);
+    single(d1, statement, id).await
+}
+
+/// 新建保存的搜索
+pub async fn create(
+    d1: &D1Client,
+    name: &str,
+    params: &PictureQueryParams,
+    smart_album: bool,
+) -> Result<SavedSearch, AppError> {
+    migrations::ensure_ready(d1).await?;
+    let name = validate_name(d1, name, None).await?;
+    let statement = D1Statement::new(
+        "INSERT INTO saved_searches (name, params, is_smart_album) VALUES (?, ?, ?) RETURNING *",
+    )
+    .bind(name)
+    .bind(stored_params(params)?)
+    .bind(smart_album);
+    let rows = d1.query_as::<SavedSearchRow>(statement).await?;
+    rows.into_iter()
+        .next()
+        .ok_or_else(|| AppError::BadResponse("保存搜索后没有返回记录".to_string()))?
+        .try_into()
+}
+
+/// 修改保存的搜索，未提供的字段保持不变
+pub async fn update(
+    d1: &D1Client,
+    id: i64,
+    changes: &SavedSearchUpdate,
+) -> Result<SavedSearch, AppError> {
+    migrations::ensure_ready(d1).await?;
+    let name = match changes.name.as_deref() {
+        Some(name) => Some(validate_name(d1, name, Some(id)).await?),
+        None => None,
+    };
+    let params = changes.params.as_ref().map(stored_params).transpose()?;
+    let statement = D1Statement::new(
+        "UPDATE saved_searches SET \
+         name = COALESCE(?, name), \
+         params = COALESCE(?, params), \
+         is_smart_album = COALESCE(?, is_smart_album), \
+         updated_at = datetime('now') \
+         WHERE id = ? RETURNING *",
+    )
+    .bind(name)
+    .bind(params)
+    .bind(changes.smart_album)
+    .bind(id);
+    single(d1, statement, id).await
+}
+
+/// 删除保存的搜索
+pub async fn delete(d1: &D1Client, id: i64) -> Result<(), AppError> {
+    migrations::ensure_ready(d1).await?;
+    let statement =
+        D1Statement::new("DELETE FROM saved_searches WHERE id = ? RETURNING id").bind(id);
+    if d1.query(statement).await?.is_empty() {
+        return Err(not_found(id));
+    }
+    Ok(())
+}
+
+async fn single(d1: &D1Client, statement: D1Statement, id: i64) -> Result<SavedSearch, AppError> {
+    d1.query_as::<SavedSearchRow>(statement)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found(id))?
+        .try_into()
+}
+
+fn not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("保存的搜索 {} 不存在", id))
+}
+
+/// 名称去掉首尾空白后不能为空，也不能与其他保存的搜索重名
+async fn validate_name(d1: &D1Client, name: &str, id: Option<i64>) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("名称不能为空".to_string()));
+    }
+    let statement = D1Statement::new("SELECT id FROM saved_searches WHERE name = ? AND id != ?")
+        .bind(name)
+        .bind(id.unwrap_or(0));
+    if !d1.query(statement).await?.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "已存在名为「{}」的搜索",
+            name
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/// 校验筛选条件并序列化为 JSON，分页参数不保存
+fn stored_params(params: &PictureQueryParams) -> Result<String, AppError> {
+    let params = PictureQueryParams {
+        limit: None,
+        offset: None,
+        cursor: None,
+        ..params.clone()
+    };
+    picture_query::filter(&params)?;
+    serde_json::to_string(&params)
+        .map_err(|e| AppError::InvalidInput(format!("筛选条件无法保存: {}", e)))
+}
@@ -0,0 +1,262 @@
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::models::{D1Statement, PictureSearchHit, PictureSearchPage, SmmsPicture};
+use crate::services::mirror::LocalMirror;
+use crate::services::picture_query;
+use crate::services::rows;
+
+/// 高亮标记，转义 HTML 后再替换为 `<mark>`，避免文件名中的标签被当作 HTML
+const MARK_START: char = '\u{1}';
+const MARK_END: char = '\u{2}';
+
+/// 备注片段最多包含的 token 数，trigram 分词下约等于字符数
+const SNIPPET_TOKENS: i64 = 48;
+
+/// trigram 分词的最短可搜索长度，更短的词改用 LIKE 匹配
+const TRIGRAM_CHARS: usize = 3;
+
+/// bm25 各列权重：文件名、存储名、备注
+const RANK: &str = "bm25(pictures_fts, 10.0, 5.0, 1.0)";
+
+#[derive(Deserialize)]
+struct HitRow {
+    rank: f64,
+    filename_highlight: Option<String>,
+    store_name_highlight: Option<String>,
+    remark_snippet: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CountRow {
+    count: i64,
+}
+
+/// 查询中的一个词
+struct Term {
+    text: String,
+    prefix: bool,
+}
+
+/// 把用户输入转换为 FTS5 查询，没有可搜索的词时返回 `None`
+///
+/// 索引按 trigram 分词，每个词匹配任意位置的子串（中文无需分词）；双引号括起的部分
+/// 连同其中的空格按原文匹配，多个词之间为 AND。每个词都加上引号，输入中的 FTS5
+/// 运算符和标点不会导致语法错误。
+pub fn fts_query(input: &str) -> Option<String> {
+    let terms = parse_terms(input);
+    (!terms.is_empty()).then(|| fts_expression(&terms))
+}
+
+fn fts_expression(terms: &[Term]) -> String {
+    terms
+        .iter()
+        .map(|term| {
+            let star = if term.prefix { "*" } else { "" };
+            format!("\"{}\"{}", term.text.replace('"', "\"\""), star)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_terms(input: &str) -> Vec<Term> {
+    let mut terms = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('"') {
+            // 未闭合的引号视为延续到末尾
+            let end = after.find('"').unwrap_or(after.len());
+            push_term(&mut terms, &after[..end], false);
+            rest = after.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '"')
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            let prefix = word.ends_with('*');
+            push_term(&mut terms, word.trim_end_matches('*'), prefix);
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    terms
+}
+
+fn push_term(terms: &mut Vec<Term>, text: &str, prefix: bool) {
+    // 不含字母数字的词没有搜索意义
+    if !text.chars().any(char::is_alphanumeric) {
+        return;
+    }
+    terms.push(Term {
+        text: text.to_string(),
+        prefix,
+    });
+}
+
+/// 在本地镜像中全文搜索未删除的图片，按相关度排序
+///
+/// 含有少于 3 个字符的词时 trigram 索引无法匹配，整个查询改用 LIKE，结果按 id 排序。
+pub fn search(
+    mirror: &LocalMirror,
+    query: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<PictureSearchPage, AppError> {
+    let terms = parse_terms(query);
+    if terms.is_empty() {
+        return Err(AppError::InvalidInput("请输入搜索关键字".to_string()));
+    }
+    if terms
+        .iter()
+        .any(|term| term.text.chars().count() < TRIGRAM_CHARS)
+    {
+        return search_like(mirror, &terms, limit, offset);
+    }
+    let fts = fts_expression(&terms);
+
+    let from = "FROM pictures_fts JOIN smms_pictures p ON p.id = pictures_fts.rowid \
+                WHERE pictures_fts MATCH ? AND p.is_deleted = 0";
+    let statement = D1Statement::new(format!(
+        "SELECT p.*, {RANK} AS rank, \
+         highlight(pictures_fts, 0, char(1), char(2)) AS filename_highlight, \
+         highlight(pictures_fts, 1, char(1), char(2)) AS store_name_highlight, \
+         snippet(pictures_fts, 2, char(1), char(2), '…', {SNIPPET_TOKENS}) AS remark_snippet \
+         {from} ORDER BY rank, p.id LIMIT ? OFFSET ?"
+    ))
+    .bind(&fts)
+    .bind(limit)
+    .bind(offset);
+    let rows = mirror.query(&statement)?;
+    let pictures: Vec<SmmsPicture> = rows::decode_rows(&rows)?;
+    let hits: Vec<HitRow> = rows::decode_rows(&rows)?;
+
+    let count: Vec<CountRow> = mirror
+        .query_as(&D1Statement::new(format!("SELECT COUNT(*) AS count {from}")).bind(&fts))?;
+
+    let items = pictures
+        .into_iter()
+        .zip(hits)
+        .map(|(picture, hit)| PictureSearchHit {
+            filename_highlight: highlight_html(
+                hit.filename_highlight
+                    .as_deref()
+                    .unwrap_or(&picture.filename),
+            ),
+            store_name_highlight: highlight_html(
+                hit.store_name_highlight
+                    .as_deref()
+                    .unwrap_or(&picture.store_name),
+            ),
+            // 备注未命中时 snippet 只是备注开头，不作为片段返回
+            remark_snippet: hit
+                .remark_snippet
+                .filter(|snippet| snippet.contains(MARK_START))
+                .map(|snippet| highlight_html(&snippet)),
+            rank: hit.rank,
+            picture,
+        })
+        .collect();
+
+    Ok(PictureSearchPage {
+        items,
+        total: count.first().map_or(0, |row| row.count),
+    })
+}
+
+/// 用 LIKE 逐词匹配文件名、存储名和备注（不区分 ASCII 大小写），在 Rust 中标出命中位置
+fn search_like(
+    mirror: &LocalMirror,
+    terms: &[Term],
+    limit: i64,
+    offset: i64,
+) -> Result<PictureSearchPage, AppError> {
+    let condition = "(p.filename LIKE ? ESCAPE '\\' OR p.store_name LIKE ? ESCAPE '\\' \
+                     OR p.remark LIKE ? ESCAPE '\\')";
+    let from = format!(
+        "FROM smms_pictures p WHERE p.is_deleted = 0 AND {}",
+        vec![condition; terms.len()].join(" AND ")
+    );
+    let bind = |statement: D1Statement| {
+        terms.iter().fold(statement, |statement, term| {
+            let pattern = picture_query::like_pattern(&term.text);
+            statement.bind(&pattern).bind(&pattern).bind(pattern)
+        })
+    };
+
+    let statement = bind(D1Statement::new(format!(
+        "SELECT p.* {from} ORDER BY p.id LIMIT ? OFFSET ?"
+    )))
+    .bind(limit)
+    .bind(offset);
+    let pictures: Vec<SmmsPicture> = mirror.query_as(&statement)?;
+    let count: Vec<CountRow> = mirror.query_as(&bind(D1Statement::new(format!(
+        "SELECT COUNT(*) AS count {from}"
+    ))))?;
+
+    let items = pictures
+        .into_iter()
+        .map(|picture| PictureSearchHit {
+            filename_highlight: highlight_html(&mark_terms(&picture.filename, terms)),
+            store_name_highlight: highlight_html(&mark_terms(&picture.store_name, terms)),
+            remark_snippet: picture
+                .remark
+                .as_deref()
+                .map(|remark| mark_terms(remark, terms))
+                .filter(|remark| remark.contains(MARK_START))
+                .map(|remark| highlight_html(&remark)),
+            rank: 0.0,
+            picture,
+        })
+        .collect();
+
+    Ok(PictureSearchPage {
+        items,
+        total: count.first().map_or(0, |row| row.count),
+    })
+}
+
+/// 用高亮标记括起各词在文本中的出现位置，与 LIKE 一样只忽略 ASCII 大小写
+fn mark_terms(text: &str, terms: &[Term]) -> String {
+    let bytes = text.as_bytes();
+    let mut marked = String::with_capacity(text.len());
+    let mut start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let hit = terms.iter().map(|term| term.text.as_bytes()).find(|term| {
+            bytes
+                .get(i..i + term.len())
+                .is_some_and(|slice| slice.eq_ignore_ascii_case(term))
+        });
+        match hit {
+            Some(term) if text.is_char_boundary(i) => {
+                marked.push_str(&text[start..i]);
+                marked.push(MARK_START);
+                marked.push_str(&text[i..i + term.len()]);
+                marked.push(MARK_END);
+                i += term.len();
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    marked.push_str(&text[start..]);
+    marked
+}
+
+/// 转义 HTML 并把高亮标记替换为 `<mark>`
+fn highlight_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            MARK_START => html.push_str("<mark>"),
+            MARK_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            _ => html.push(c),
+        }
+    }
+    html
+}
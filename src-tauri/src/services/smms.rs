@@ -0,0 +1,157 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::stream::{self, StreamExt};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+use crate::error::AppError;
+use crate::models::{
+    SmmsDeleteResponse, SmmsTokenResponse, SmmsUploadHistoryResponse, SmmsUploadResponse,
+};
+use crate::services::retry::{AttemptError, Idempotency, Retried, RetryPolicy};
+
+/// 上传请求体每块的字节数
+const UPLOAD_CHUNK: usize = 64 * 1024;
+
+/// SM.MS API 客户端，作为 Tauri 状态全局共享
+pub struct SmmsClient {
+    http: reqwest::Client,
+    base_url: String,
+    retry: RetryPolicy,
+}
+
+impl SmmsClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(120))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// 替换重试策略
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// 发送请求并解析 JSON，按重试策略处理限流和网络错误
+    ///
+    /// `build` 每次尝试都会被调用，以便重新构造请求体（如 multipart 表单）。
+    async fn send<T, F>(&self, idempotency: Idempotency, context: &str, build: F) -> Retried<T>
+    where
+        T: DeserializeOwned,
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let build = &build;
+        self.retry
+            .run(idempotency, || async move {
+                let response = build()
+                    .send()
+                    .await
+                    .map_err(|e| AttemptError::from_send(context, e))?;
+
+                let status = response.status();
+                if let Some(error) = AttemptError::from_status(status, response.headers()) {
+                    return Err(error);
+                }
+                if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+                    return Err(AttemptError::fatal(AppError::AuthRequired(format!(
+                        "SM.MS 凭证无效（HTTP {}），请重新登录",
+                        status.as_u16()
+                    ))));
+                }
+
+                // 先读取文本，区分读取中断与格式错误
+                let text = response
+                    .text()
+                    .await
+                    .map_err(|e| AttemptError::from_body("读取响应失败", e))?;
+                serde_json::from_str(&text).map_err(|e| {
+                    AttemptError::fatal(AppError::BadResponse(format!("解析响应失败: {}", e)))
+                })
+            })
+            .await
+    }
+
+    /// 使用用户名密码换取 API Token
+    pub async fn token(&self, username: &str, password: &str) -> Retried<SmmsTokenResponse> {
+        let params = [("username", username), ("password", password)];
+
+        self.send(Idempotency::Idempotent, "请求失败", || {
+            self.http.post(self.url("token")).form(&params)
+        })
+        .await
+    }
+
+    /// 获取指定页的上传历史
+    pub async fn upload_history(
+        &self,
+        token: &str,
+        page: i32,
+    ) -> Retried<SmmsUploadHistoryResponse> {
+        self.send(Idempotency::Idempotent, "请求失败", || {
+            self.http
+                .get(self.url("upload_history"))
+                .header("Authorization", token)
+                .query(&[("page", page.to_string())])
+        })
+        .await
+    }
+
+    /// 上传单个文件，请求体分块发送，每交给连接一块就以累计字节数调用 `sent`
+    ///
+    /// 上传不是幂等操作，只在服务端明确未处理（连接失败、429、带 `Retry-After` 的 503）时重试。
+    /// 重试时从 0 重新计数。
+    pub async fn upload(
+        &self,
+        token: &str,
+        filename: &str,
+        data: Vec<u8>,
+        sent: impl Fn(u64) + Clone + Send + Sync + 'static,
+    ) -> Retried<SmmsUploadResponse> {
+        let data = Arc::new(data);
+        self.send(Idempotency::NonIdempotent, "上传请求失败", || {
+            let total = data.len();
+            let (data, sent) = (data.clone(), sent.clone());
+            let chunks = stream::iter((0..total).step_by(UPLOAD_CHUNK)).map(move |start| {
+                let end = (start + UPLOAD_CHUNK).min(total);
+                sent(end as u64);
+                Ok::<_, std::io::Error>(data[start..end].to_vec())
+            });
+            let part = reqwest::multipart::Part::stream_with_length(
+                reqwest::Body::wrap_stream(chunks),
+                total as u64,
+            )
+            .file_name(filename.to_string());
+            let form = reqwest::multipart::Form::new().part("smfile", part);
+            self.http
+                .post(self.url("upload"))
+                .header("Authorization", token)
+                .multipart(form)
+        })
+        .await
+    }
+
+    /// 通过删除 hash 删除图片
+    pub async fn delete(&self, token: &str, hash: &str) -> Retried<SmmsDeleteResponse> {
+        self.send(Idempotency::Idempotent, "删除请求失败", || {
+            self.http
+                .get(self.url(&format!("delete/{}", hash)))
+                .header("Authorization", token)
+        })
+        .await
+    }
+}
@@ -0,0 +1,76 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::models::{D1BatchResult, D1Config, D1QueryOutput, D1Statement, StorageKind};
+use crate::services::config::ConfigStore;
+use crate::services::d1::D1Client;
+use crate::services::local_store::LocalStore;
+
+/// 本地元数据数据库的文件名
+pub const LOCAL_DATABASE_FILE: &str = "metadata.sqlite";
+
+/// 存储后端返回的 future
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, AppError>> + Send + 'a>>;
+
+/// 元数据存储后端
+///
+/// 语句统一使用 SQLite 方言，命令层的 SQL 不区分后端。
+/// 批量执行的语义与 D1 相同：遇到第一条失败的语句即停止，之前的语句已提交。
+pub trait MetadataStore: Send + Sync {
+    fn kind(&self) -> StorageKind;
+
+    /// 数据库标识，本地镜像和离线队列按此区分数据来源
+    fn source(&self) -> String;
+
+    /// 派生凭证加密密钥的材料，更换后已保存的 SM.MS 凭证无法解密
+    fn key_material(&self) -> (String, String);
+
+    /// 可以写入备份的密钥材料，恢复到其他数据库时据此重新加密凭证
+    ///
+    /// 密钥材料本身是机密时返回 `None`，备份中的凭证只能在原数据库解密。
+    fn exportable_key_material(&self) -> Option<(String, String)> {
+        Some(self.key_material())
+    }
+
+    /// 该数据库的结构是否已迁移到最新（切换后端时随存储一起重建）
+    fn schema_ready(&self) -> Arc<AtomicBool>;
+
+    /// D1 后端的配置，其他后端为空
+    fn d1_config(&self) -> Option<&D1Config> {
+        None
+    }
+
+    /// 语句是否计入 D1 用量（只有 D1 后端按读写行数计费）
+    fn billable(&self) -> bool {
+        false
+    }
+
+    /// 执行单条语句
+    fn query(&self, statement: D1Statement) -> StoreFuture<'_, D1QueryOutput>;
+
+    /// 按顺序执行多条语句
+    fn batch(&self, statements: Vec<D1Statement>) -> StoreFuture<'_, D1BatchResult>;
+}
+
+/// 按设置启用存储后端：本地后端打开配置目录下的数据库文件，D1 后端使用已保存的配置
+pub fn activate(d1: &D1Client, config: &ConfigStore, kind: StorageKind) -> Result<(), AppError> {
+    match kind {
+        StorageKind::Local => {
+            let (key, created) = config.local_key()?;
+            let store = LocalStore::open(&config.path_of(LOCAL_DATABASE_FILE)?, key)?;
+            if created {
+                store.rekey_legacy_credentials()?;
+            }
+            d1.use_store(Arc::new(store));
+        }
+        StorageKind::D1 => match config.read_d1_config() {
+            Ok(d1_config) => d1.reload(d1_config)?,
+            Err(AppError::ConfigMissing(_)) => d1.clear(),
+            Err(e) => return Err(e),
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::models::{D1Param, D1Statement, PictureTags, Tag, TagUsage};
+use crate::services::d1::D1Client;
+use crate::services::migrations;
+use crate::services::mirror::{LocalMirror, TAGS};
+use crate::services::offline::MAX_IDS_PER_STATEMENT;
+use crate::services::rows;
+
+/// 标签名称的最大长度（字符）
+const MAX_NAME_CHARS: usize = 64;
+
+/// 一次最多处理的标签数量（D1 每条语句最多 100 个绑定参数）
+const MAX_TAGS_PER_CALL: usize = 50;
+
+#[derive(Deserialize)]
+struct PictureTagRow {
+    picture_id: i64,
+    id: i64,
+    name: String,
+    created_at: Option<String>,
+}
+
+/// 全部标签及使用次数，按名称排序
+pub fn list(mirror: &LocalMirror) -> Result<Vec<TagUsage>, AppError> {
+    mirror.query_as(&D1Statement::new(
+        "SELECT t.id, t.name, t.created_at, COUNT(p.id) AS count FROM tags t \
+         LEFT JOIN picture_tags pt ON pt.tag_id = t.id \
+         LEFT JOIN smms_pictures p ON p.id = pt.picture_id AND p.is_deleted = 0 \
+         GROUP BY t.id ORDER BY t.name COLLATE NOCASE, t.id",
+    ))
+}
+
+/// 各图片的标签，没有标签的图片也会返回（`tags` 为空）
+pub fn picture_tags(mirror: &LocalMirror, ids: &[i64]) -> Result<Vec<PictureTags>, AppError> {
+    let mut tags: BTreeMap<i64, Vec<Tag>> = ids.iter().map(|id| (*id, Vec::new())).collect();
+    for chunk in ids.chunks(MAX_IDS_PER_STATEMENT) {
+        let statement = D1Statement {
+            sql: format!(
+                "SELECT pt.picture_id, t.id, t.name, t.created_at FROM picture_tags pt \
+                 JOIN tags t ON t.id = pt.tag_id WHERE pt.picture_id IN ({}) \
+                 ORDER BY t.name COLLATE NOCASE",
+                placeholders(chunk.len())
+            ),
+            params: chunk.iter().map(|id| D1Param::from(*id)).collect(),
+        };
+        for row in mirror.query_as::<PictureTagRow>(&statement)? {
+            tags.entry(row.picture_id).or_default().push(Tag {
+                id: row.id,
+                name: row.name,
+                created_at: row.created_at,
+            });
+        }
+    }
+    Ok(tags
+        .into_iter()
+        .map(|(picture_id, tags)| PictureTags { picture_id, tags })
+        .collect())
+}
+
+/// 新建标签，已存在同名标签时直接返回该标签
+pub async fn create(d1: &D1Client, mirror: &LocalMirror, name: &str) -> Result<Tag, AppError> {
+    let mut tags = tag_pictures(d1, mirror, &[], &[name.to_string()]).await?;
+    tags.pop()
+        .ok_or_else(|| AppError::BadResponse("创建标签后没有返回记录".to_string()))
+}
+
+/// 给图片添加标签（按名称，不存在的标签会自动创建），返回涉及的标签
+pub async fn tag_pictures(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    ids: &[i64],
+    names: &[String],
+) -> Result<Vec<Tag>, AppError> {
+    let names = validate_names(names)?;
+
+    let mut statements: Vec<D1Statement> = names
+        .iter()
+        .map(|name| {
+            D1Statement::new("INSERT INTO tags (name) VALUES (?) ON CONFLICT(name) DO NOTHING")
+                .bind(name)
+        })
+        .collect();
+    let select_index = statements.len();
+    statements.push(D1Statement {
+        sql: format!(
+            "SELECT id, name, created_at FROM tags WHERE name IN ({}) ORDER BY name COLLATE NOCASE",
+            placeholders(names.len())
+        ),
+        params: names.iter().map(D1Param::from).collect(),
+    });
+    // 按名称查找标签 id，只为 D1 中存在的图片添加；镜像按 D1 返回的关联写入
+    let mut links = Vec::new();
+    for name in &names {
+        for chunk in ids.chunks(MAX_IDS_PER_STATEMENT) {
+            let mut params = vec![D1Param::from(name)];
+            params.extend(chunk.iter().map(|id| D1Param::from(*id)));
+            links.push(D1Statement {
+                sql: format!(
+                    "INSERT OR IGNORE INTO picture_tags (picture_id, tag_id) \
+                     SELECT p.id, t.id FROM smms_pictures p, tags t \
+                     WHERE t.name = ? AND p.id IN ({}) RETURNING picture_id, tag_id",
+                    placeholders(chunk.len())
+                ),
+                params,
+            });
+        }
+    }
+    statements.extend(links);
+
+    let (revision, results) = TAGS.execute(d1, statements).await?;
+    let tags: Vec<Tag> = rows::decode_rows(&results[select_index])?;
+
+    // 新标签的 id 由 D1 分配，镜像按 id 写入
+    let mut local: Vec<D1Statement> = tags
+        .iter()
+        .map(|tag| {
+            D1Statement::new("INSERT OR REPLACE INTO tags (id, name, created_at) VALUES (?, ?, ?)")
+                .bind(tag.id)
+                .bind(&tag.name)
+                .bind(tag.created_at.clone())
+        })
+        .collect();
+    let linked: Vec<serde_json::Value> = results[select_index + 1..].concat();
+    local.extend(TAGS.replace_rows("picture_tags", &linked));
+    TAGS.write_through(d1, mirror, revision, &local).await?;
+    Ok(tags)
+}
+
+/// 移除图片上的标签
+pub async fn untag_pictures(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    ids: &[i64],
+    tag_ids: &[i64],
+) -> Result<(), AppError> {
+    let tag_ids = validate_ids(tag_ids)?;
+    let statements: Vec<D1Statement> = tag_ids
+        .iter()
+        .flat_map(|tag_id| {
+            ids.chunks(MAX_IDS_PER_STATEMENT).map(move |chunk| {
+                let mut params = vec![D1Param::from(*tag_id)];
+                params.extend(chunk.iter().map(|id| D1Param::from(*id)));
+                D1Statement {
+                    sql: format!(
+                        "DELETE FROM picture_tags WHERE tag_id = ? AND picture_id IN ({})",
+                        placeholders(chunk.len())
+                    ),
+                    params,
+                }
+            })
+        })
+        .collect();
+    if statements.is_empty() {
+        return Ok(());
+    }
+    TAGS.apply(d1, mirror, statements).await
+}
+
+/// 重命名标签，新名称不能与其他标签重复（需要合并时请使用 [`merge`]）
+pub async fn rename(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    id: i64,
+    name: &str,
+) -> Result<Tag, AppError> {
+    let name = validate_name(name)?;
+    migrations::ensure_ready(d1).await?;
+    require_tags(d1, &[id]).await?;
+    let conflict = D1Statement::new("SELECT id FROM tags WHERE name = ? AND id != ?")
+        .bind(&name)
+        .bind(id);
+    if !d1.query(conflict).await?.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "已存在名为「{}」的标签，可以将两个标签合并",
+            name
+        )));
+    }
+
+    let update = D1Statement::new("UPDATE tags SET name = ? WHERE id = ?")
+        .bind(&name)
+        .bind(id);
+    TAGS.apply(d1, mirror, vec![update]).await?;
+    let tag = d1
+        .query_as::<Tag>(
+            D1Statement::new("SELECT id, name, created_at FROM tags WHERE id = ?").bind(id),
+        )
+        .await?;
+    tag.into_iter().next().ok_or_else(|| not_found(id))
+}
+
+/// 把 `sources` 合并到 `target`：来源标签的图片改用目标标签，然后删除来源标签
+pub async fn merge(
+    d1: &D1Client,
+    mirror: &LocalMirror,
+    sources: &[i64],
+    target: i64,
+) -> Result<(), AppError> {
+    let sources: Vec<i64> = validate_ids(sources)?
+        .into_iter()
+        .filter(|id| *id != target)
+        .collect();
+    if sources.is_empty() {
+        return Err(AppError::InvalidInput("请选择要合并的其他标签".to_string()));
+    }
+    migrations::ensure_ready(d1).await?;
+    let mut all = sources.clone();
+    all.push(target);
+    require_tags(d1, &all).await?;
+
+    let in_sources = placeholders(sources.len());
+    let source_params = || sources.iter().map(|id| D1Param::from(*id));
+    let mut move_params = vec![D1Param::from(target)];
+    move_params.extend(source_params());
+    let statements = vec![
+        D1Statement {
+            sql: format!(
+                "INSERT OR IGNORE INTO picture_tags (picture_id, tag_id) \
+                 SELECT picture_id, ? FROM picture_tags WHERE tag_id IN ({})",
+                in_sources
+            ),
+            params: move_params,
+        },
+        D1Statement {
+            sql: format!("DELETE FROM picture_tags WHERE tag_id IN ({})", in_sources),
+            params: source_params().collect(),
+        },
+        D1Statement {
+            sql: format!("DELETE FROM tags WHERE id IN ({})", in_sources),
+            params: source_params().collect(),
+        },
+    ];
+    TAGS.apply(d1, mirror, statements).await
+}
+
+/// 删除标签及其与图片的关联
+pub async fn delete(d1: &D1Client, mirror: &LocalMirror, id: i64) -> Result<(), AppError> {
+    migrations::ensure_ready(d1).await?;
+    require_tags(d1, &[id]).await?;
+    let statements = vec![
+        D1Statement::new("DELETE FROM picture_tags WHERE tag_id = ?").bind(id),
+        D1Statement::new("DELETE FROM tags WHERE id = ?").bind(id),
+    ];
+    TAGS.apply(d1, mirror, statements).await
+}
+
+/// 确认标签都存在
+async fn require_tags(d1: &D1Client, ids: &[i64]) -> Result<(), AppError> {
+    let statement = D1Statement {
+        sql: format!(
+            "SELECT id FROM tags WHERE id IN ({})",
+            placeholders(ids.len())
+        ),
+        params: ids.iter().map(|id| D1Param::from(*id)).collect(),
+    };
+    let found: BTreeSet<i64> = d1
+        .query(statement)
+        .await?
+        .iter()
+        .filter_map(|row| row["id"].as_i64())
+        .collect();
+    match ids.iter().find(|id| !found.contains(id)) {
+        Some(id) => Err(not_found(*id)),
+        None => Ok(()),
+    }
+}
+
+fn not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("标签 {} 不存在", id))
+}
+
+fn validate_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("标签名称不能为空".to_string()));
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(AppError::InvalidInput(format!(
+            "标签名称不能超过 {} 个字符",
+            MAX_NAME_CHARS
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/// 校验并去重标签名称（与 SQLite 的 NOCASE 一致，只忽略 ASCII 大小写），保留第一次出现的写法
+fn validate_names(names: &[String]) -> Result<Vec<String>, AppError> {
+    let mut seen = BTreeSet::new();
+    let mut result = Vec::new();
+    for name in names {
+        let name = validate_name(name)?;
+        if seen.insert(name.to_ascii_lowercase()) {
+            result.push(name);
+        }
+    }
+    if result.is_empty() {
+        return Err(AppError::InvalidInput("请至少指定一个标签".to_string()));
+    }
+    if result.len() > MAX_TAGS_PER_CALL {
+        return Err(AppError::InvalidInput(format!(
+            "一次最多处理 {} 个标签",
+            MAX_TAGS_PER_CALL
+        )));
+    }
+    Ok(result)
+}
+
+fn validate_ids(ids: &[i64]) -> Result<Vec<i64>, AppError> {
+    let ids: Vec<i64> = ids
+        .iter()
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if ids.is_empty() {
+        return Err(AppError::InvalidInput("请至少指定一个标签".to_string()));
+    }
+    if ids.len() > MAX_TAGS_PER_CALL {
+        return Err(AppError::InvalidInput(format!(
+            "一次最多处理 {} 个标签",
+            MAX_TAGS_PER_CALL
+        )));
+    }
+    Ok(ids)
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
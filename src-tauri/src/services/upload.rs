@@ -0,0 +1,568 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::stream::{self, StreamExt};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+
+use crate::error::AppError;
+use crate::models::{D1Statement, JobStage, SmmsUploadData, UploadResult};
+use crate::services::d1::D1Client;
+use crate::services::mirror::LocalMirror;
+use crate::services::progress::Progress;
+use crate::services::smms::SmmsClient;
+
+/// 默认同时上传的文件数
+pub const DEFAULT_CONCURRENCY: usize = 3;
+
+/// 同时上传的文件数上限，避免触发 SM.MS 的频率限制
+pub const MAX_CONCURRENCY: usize = 8;
+
+/// 每次写入 D1 的记录数上限（一个批量请求）
+const INSERT_BATCH_SIZE: usize = 20;
+
+/// 第一条记录完成后最多等待多久就写入 D1，不等凑满一批
+const INSERT_FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 单个文件的上传结果，成功的还需要写入数据库
+enum Uploaded {
+    Failed(UploadResult),
+    /// 已有相同内容的图片，没有上传；`update` 把本次指定的备注写到已有记录
+    Duplicate {
+        index: usize,
+        bytes: u64,
+        update: Option<D1Statement>,
+        result: UploadResult,
+    },
+    /// 与本次调用中序号为 `first` 的文件内容相同，没有上传，结果沿用那个文件的
+    Copy {
+        index: usize,
+        first: usize,
+        bytes: u64,
+    },
+    Succeeded {
+        index: usize,
+        filename: String,
+        data: SmmsUploadData,
+        attempts: u32,
+        /// 文件大小（字节）
+        bytes: u64,
+        content_sha256: String,
+    },
+}
+
+/// 与待上传文件内容相同的已有记录
+#[derive(Deserialize)]
+struct Existing {
+    id: i64,
+    filename: String,
+    url: String,
+    remark: Option<String>,
+}
+
+/// 校验并发数，未指定时使用默认值
+pub fn concurrency(value: Option<usize>) -> Result<usize, AppError> {
+    match value {
+        None => Ok(DEFAULT_CONCURRENCY),
+        Some(value) if (1..=MAX_CONCURRENCY).contains(&value) => Ok(value),
+        Some(_) => Err(AppError::InvalidInput(format!(
+            "同时上传的数量必须在 1 到 {} 之间",
+            MAX_CONCURRENCY
+        ))),
+    }
+}
+
+/// 以最多 `concurrency` 个并发上传文件，结果与 `file_paths` 顺序一致
+///
+/// 上传与写入同时进行：完成的文件交给写入端，凑满 [`INSERT_BATCH_SIZE`] 条或等待
+/// [`INSERT_FLUSH_INTERVAL`] 后合并为一个 D1 批量请求写入，写入期间其他上传照常进行。
+/// 每个文件经过读取、上传、写入数据库几个阶段，进度通过 `progress` 发送。
+///
+/// 任务被取消后不再开始新的上传，已上传到图床的文件仍会写入数据库。
+///
+/// 指定 `dedup` 时先按文件内容的 SHA-256 在镜像中查找，已上传过的文件直接返回已有记录的地址，
+/// 指定了 `remark` 时改写已有记录的备注；同一次调用中内容相同的文件只上传先读完的一个，
+/// 其余沿用它的结果。
+/// 只能发现记录了内容 hash 的图片，从 SM.MS 导入的历史图片没有这项信息。
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_files(
+    d1: &D1Client,
+    smms: &SmmsClient,
+    token: &str,
+    file_paths: &[String],
+    remark: Option<&str>,
+    concurrency: usize,
+    dedup: Option<&LocalMirror>,
+    progress: &Progress,
+) -> Vec<UploadResult> {
+    // 本次调用中各内容 hash 第一个出现的文件序号，上传时读取文件后查找
+    let first_copies = Mutex::new(HashMap::new());
+    let first_copies = dedup.map(|_| &first_copies);
+
+    // 需要写入 D1 的结果交给写入端，其余的直接得到结果
+    let (finished, mut received) = mpsc::unbounded_channel();
+    let uploads = async move {
+        let mut settled = Vec::new();
+        let mut copies = Vec::new();
+        stream::iter(0..file_paths.len())
+            .map(|index| async move {
+                let uploaded = upload_file(
+                    smms,
+                    token,
+                    index,
+                    &file_paths[index],
+                    remark,
+                    dedup,
+                    first_copies,
+                    progress,
+                )
+                .await;
+                (index, uploaded)
+            })
+            .buffer_unordered(concurrency)
+            .for_each(|(index, uploaded)| {
+                match uploaded {
+                    Uploaded::Failed(result)
+                    | Uploaded::Duplicate {
+                        update: None,
+                        result,
+                        ..
+                    } => settled.push((index, result)),
+                    Uploaded::Copy {
+                        index,
+                        first,
+                        bytes,
+                    } => copies.push((index, first, bytes)),
+                    uploaded => {
+                        let _ = finished.send((index, uploaded));
+                    }
+                }
+                async {}
+            })
+            .await;
+        (settled, copies)
+    };
+    let writer = async {
+        let mut stored = Vec::new();
+        while let Some(first) = received.recv().await {
+            let mut group = vec![first];
+            let flush = tokio::time::sleep(INSERT_FLUSH_INTERVAL);
+            tokio::pin!(flush);
+            while group.len() < INSERT_BATCH_SIZE {
+                tokio::select! {
+                    uploaded = received.recv() => match uploaded {
+                        Some(uploaded) => group.push(uploaded),
+                        None => break,
+                    },
+                    _ = &mut flush => break,
+                }
+            }
+            let (indexes, group): (Vec<usize>, Vec<Uploaded>) = group.into_iter().unzip();
+            stored.extend(
+                indexes
+                    .into_iter()
+                    .zip(store(d1, group, remark, progress).await),
+            );
+        }
+        stored
+    };
+    let ((settled, copies), stored) = tokio::join!(uploads, writer);
+
+    // 按序号放回输入顺序
+    let mut results: Vec<Option<UploadResult>> = file_paths.iter().map(|_| None).collect();
+    for (index, result) in settled.into_iter().chain(stored) {
+        results[index] = Some(result);
+    }
+
+    let copies: Vec<(usize, UploadResult)> = copies
+        .into_iter()
+        .filter_map(|(index, first, bytes)| {
+            let first = results[first].as_ref()?;
+            let result = same_as(index, &file_paths[index], bytes, first, remark, progress);
+            Some((index, result))
+        })
+        .collect();
+    for (index, result) in copies {
+        results[index] = Some(result);
+    }
+    results.into_iter().flatten().collect()
+}
+
+/// 与本次调用中另一个文件内容相同的文件，沿用那个文件的结果
+fn same_as(
+    index: usize,
+    file_path: &str,
+    bytes: u64,
+    first: &UploadResult,
+    remark: Option<&str>,
+    progress: &Progress,
+) -> UploadResult {
+    let filename = file_name(file_path);
+    let file = progress.file(index, &filename);
+    let message = if first.success {
+        file.done(bytes);
+        format!("与本次上传的 {} 内容相同，未重复上传", first.filename)
+    } else if first.cancelled {
+        file.cancelled();
+        first.message.clone()
+    } else {
+        let message = format!(
+            "与本次上传的 {} 内容相同: {}",
+            first.filename, first.message
+        );
+        file.failed(message.clone());
+        message
+    };
+    UploadResult {
+        filename,
+        success: first.success,
+        message,
+        url: first.url.clone(),
+        remark: remark.map(str::to_string),
+        attempts: 0,
+        cancelled: first.cancelled,
+        duplicate: first.success,
+    }
+}
+
+fn file_name(file_path: &str) -> String {
+    Path::new(file_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+/// 读取并上传单个文件
+///
+/// 指定 `first_copies` 时登记文件内容的 hash，已有同一次调用中的其他文件登记过时不再上传。
+#[allow(clippy::too_many_arguments)]
+async fn upload_file(
+    smms: &SmmsClient,
+    token: &str,
+    index: usize,
+    file_path: &str,
+    remark: Option<&str>,
+    dedup: Option<&LocalMirror>,
+    first_copies: Option<&Mutex<HashMap<String, usize>>>,
+    progress: &Progress,
+) -> Uploaded {
+    let filename = file_name(file_path);
+    let file = progress.file(index, &filename);
+    let failed = |message: String, attempts: u32| {
+        file.failed(message.clone());
+        Uploaded::Failed(UploadResult {
+            filename: filename.clone(),
+            success: false,
+            message,
+            url: None,
+            remark: remark.map(str::to_string),
+            attempts,
+            cancelled: false,
+            duplicate: false,
+        })
+    };
+    let cancelled = || {
+        file.cancelled();
+        Uploaded::Failed(UploadResult {
+            filename: filename.clone(),
+            success: false,
+            message: "任务已取消，未上传".to_string(),
+            url: None,
+            remark: remark.map(str::to_string),
+            attempts: 0,
+            cancelled: true,
+            duplicate: false,
+        })
+    };
+
+    if progress.is_cancelled() {
+        return cancelled();
+    }
+    file.report(JobStage::Reading, 0, None);
+    let file_data = match tokio::fs::read(file_path).await {
+        Ok(data) => data,
+        Err(e) => return failed(format!("读取文件失败: {}", e), 0),
+    };
+    if progress.is_cancelled() {
+        return cancelled();
+    }
+    let bytes = file_data.len() as u64;
+    let content_sha256 = content_sha256(&file_data);
+    if let Some(first_copies) = first_copies {
+        let first = *first_copies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(content_sha256.clone())
+            .or_insert(index);
+        if first != index {
+            return Uploaded::Copy {
+                index,
+                first,
+                bytes,
+            };
+        }
+    }
+    if let Some(mirror) = dedup {
+        match find_existing(mirror, &content_sha256) {
+            Ok(Some(existing)) => {
+                // 指定了新的备注时改写已有记录的备注，随同一批写入 D1
+                let update = remark
+                    .filter(|remark| existing.remark.as_deref() != Some(*remark))
+                    .map(|remark| {
+                        D1Statement::new(
+                            "UPDATE smms_pictures SET remark = ?, updated_at = datetime('now') \
+                             WHERE id = ?",
+                        )
+                        .bind(remark)
+                        .bind(existing.id)
+                    });
+                match update {
+                    Some(_) => file.report(JobStage::Saving, bytes, Some(bytes)),
+                    None => file.done(bytes),
+                }
+                return Uploaded::Duplicate {
+                    index,
+                    bytes,
+                    update,
+                    result: UploadResult {
+                        message: format!("已存在相同内容的图片 {}，未重复上传", existing.filename),
+                        filename,
+                        success: true,
+                        url: Some(existing.url),
+                        remark: remark.map(str::to_string).or(existing.remark),
+                        attempts: 0,
+                        cancelled: false,
+                        duplicate: true,
+                    },
+                };
+            }
+            Ok(None) => {}
+            Err(e) => return failed(format!("查找相同内容的图片失败: {}", e), 0),
+        }
+    }
+    file.report(JobStage::Uploading, 0, Some(bytes));
+
+    // 请求体在 reqwest 中发送，经通道把已发送的字节数转为本文件的进度事件
+    let (sent, mut sent_bytes) = mpsc::unbounded_channel();
+    let upload = {
+        let upload = smms.upload(token, &filename, file_data, move |bytes| {
+            let _ = sent.send(bytes);
+        });
+        tokio::pin!(upload);
+        loop {
+            tokio::select! {
+                upload = &mut upload => break upload,
+                Some(sent) = sent_bytes.recv() => {
+                    file.transferred(JobStage::Uploading, sent, Some(bytes));
+                }
+            }
+        }
+    };
+    while let Ok(sent) = sent_bytes.try_recv() {
+        file.transferred(JobStage::Uploading, sent, Some(bytes));
+    }
+    let attempts = upload.attempts;
+    let response = match upload.result {
+        Ok(response) => response,
+        Err(e) => return failed(e.to_string(), attempts),
+    };
+    if !response.success {
+        return failed(format!("上传失败: {}", response.message), attempts);
+    }
+    match response.data {
+        Some(data) => {
+            file.report(JobStage::Saving, bytes, Some(bytes));
+            Uploaded::Succeeded {
+                index,
+                filename,
+                data,
+                attempts,
+                bytes,
+                content_sha256,
+            }
+        }
+        None => failed("上传响应中没有数据".to_string(), attempts),
+    }
+}
+
+/// 把一组上传成功的记录（以及已有记录的备注修改）用一个批量请求写入 D1，返回这一组的结果，
+/// 顺序与 `group` 一致
+///
+/// D1 批量在第一条失败的语句处停止，之前的记录已经写入，之后的记录不会执行。
+async fn store(
+    d1: &D1Client,
+    group: Vec<Uploaded>,
+    remark: Option<&str>,
+    progress: &Progress,
+) -> Vec<UploadResult> {
+    let statements: Vec<D1Statement> = group
+        .iter()
+        .filter_map(|uploaded| match uploaded {
+            Uploaded::Succeeded {
+                data,
+                content_sha256,
+                ..
+            } => Some(insert_statement(data, remark, content_sha256)),
+            Uploaded::Duplicate { update, .. } => update.clone(),
+            Uploaded::Failed(_) | Uploaded::Copy { .. } => None,
+        })
+        .collect();
+
+    let outcome = if statements.is_empty() {
+        Ok(None)
+    } else {
+        d1.batch(statements).await.map(Some)
+    };
+    let error_at = |statement_index: usize| match &outcome {
+        Ok(Some(batch)) => match batch.failed_index {
+            Some(failed) if failed == statement_index => Some(
+                batch
+                    .error
+                    .as_ref()
+                    .map_or_else(|| "未知错误".to_string(), ToString::to_string),
+            ),
+            Some(failed) if failed < statement_index => {
+                Some("同一批中前面的记录写入失败，未执行".to_string())
+            }
+            _ => None,
+        },
+        Ok(None) => None,
+        Err(e) => Some(e.to_string()),
+    };
+
+    let mut statement_index = 0;
+    let mut next_error = || {
+        let error = error_at(statement_index);
+        statement_index += 1;
+        error
+    };
+    group
+        .into_iter()
+        .map(|uploaded| match uploaded {
+            Uploaded::Failed(result) => result,
+            Uploaded::Copy { .. } => unreachable!("本次调用中的重复文件不交给写入端"),
+            Uploaded::Duplicate {
+                update: None,
+                result,
+                ..
+            } => result,
+            Uploaded::Duplicate {
+                index,
+                bytes,
+                update: Some(_),
+                mut result,
+            } => {
+                let file = progress.file(index, &result.filename);
+                match next_error() {
+                    None => file.done(bytes),
+                    Some(e) => {
+                        result.success = false;
+                        result.message = format!("{}，但备注写入失败: {}", result.message, e);
+                        file.failed(result.message.clone());
+                    }
+                }
+                result
+            }
+            Uploaded::Succeeded {
+                index,
+                filename,
+                data,
+                attempts,
+                bytes,
+                ..
+            } => {
+                let error = next_error();
+                let file = progress.file(index, &filename);
+                let message = match &error {
+                    None => {
+                        file.done(bytes);
+                        "上传成功".to_string()
+                    }
+                    Some(e) => {
+                        let message = format!("上传成功但数据库插入失败: {}", e);
+                        file.failed(message.clone());
+                        message
+                    }
+                };
+                UploadResult {
+                    filename,
+                    success: error.is_none(),
+                    message,
+                    url: Some(data.url),
+                    remark: remark.map(str::to_string),
+                    attempts,
+                    cancelled: false,
+                    duplicate: false,
+                }
+            }
+        })
+        .collect()
+}
+
+/// 文件内容的 SHA-256（小写十六进制）
+pub fn content_sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 在镜像中查找内容相同、未删除的图片
+fn find_existing(mirror: &LocalMirror, content_sha256: &str) -> Result<Option<Existing>, AppError> {
+    let statement = D1Statement::new(
+        "SELECT id, filename, url, remark FROM smms_pictures \
+         WHERE content_sha256 = ? AND is_deleted = 0 ORDER BY id LIMIT 1",
+    )
+    .bind(content_sha256);
+    Ok(mirror.query_as(&statement)?.into_iter().next())
+}
+
+fn insert_statement(
+    data: &SmmsUploadData,
+    remark: Option<&str>,
+    content_sha256: &str,
+) -> D1Statement {
+    D1Statement::new(
+        "INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, remark, content_sha256, created_at, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'), datetime('now')) \
+         ON CONFLICT(file_hash) DO UPDATE SET \
+         filename = excluded.filename, \
+         store_name = excluded.store_name, \
+         width = excluded.width, \
+         height = excluded.height, \
+         size = excluded.size, \
+         path = excluded.path, \
+         url = excluded.url, \
+         delete_url = excluded.delete_url, \
+         page_url = excluded.page_url, \
+         remark = excluded.remark, \
+         content_sha256 = excluded.content_sha256, \
+         updated_at = excluded.updated_at",
+    )
+    .bind(&data.hash)
+    .bind(&data.filename)
+    .bind(&data.store_name)
+    .bind(file_type_of(&data.filename))
+    .bind(data.width)
+    .bind(data.height)
+    .bind(data.size)
+    .bind(&data.path)
+    .bind(&data.url)
+    .bind(&data.delete_url)
+    .bind(&data.page_url)
+    .bind(remark)
+    .bind(content_sha256)
+}
+
+/// 从文件名中提取小写扩展名作为文件类型
+pub(crate) fn file_type_of(filename: &str) -> String {
+    filename
+        .rsplit('.')
+        .next()
+        .unwrap_or("unknown")
+        .to_lowercase()
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::models::D1QueryMeta;
+
+/// 单个 SQL 模板的累计开销
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct D1OperationUsage {
+    /// 归一化后的 SQL（合并空白，`IN (?, ?, ...)` 折叠为一个占位符）
+    pub sql: String,
+    pub count: u64,
+    pub rows_read: u64,
+    pub rows_written: u64,
+    /// 累计耗时（毫秒）
+    pub duration: f64,
+}
+
+/// 本次会话的 D1 用量
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct D1Usage {
+    /// 开始计数的时间（Unix 秒）
+    pub since: u64,
+    /// 已执行的语句数
+    pub statements: u64,
+    pub rows_read: u64,
+    pub rows_written: u64,
+    pub duration: f64,
+    /// 按读取行数从高到低排列
+    pub operations: Vec<D1OperationUsage>,
+}
+
+/// D1 用量计数器，随 [`crate::services::d1::D1Client`] 共享
+pub struct UsageMeter {
+    state: Mutex<UsageState>,
+}
+
+struct UsageState {
+    since: u64,
+    total: D1QueryMeta,
+    statements: u64,
+    operations: HashMap<String, D1OperationUsage>,
+}
+
+impl UsageState {
+    fn new() -> Self {
+        Self {
+            since: now(),
+            total: D1QueryMeta::default(),
+            statements: 0,
+            operations: HashMap::new(),
+        }
+    }
+
+    fn usage(&self) -> D1Usage {
+        let mut operations: Vec<D1OperationUsage> = self.operations.values().cloned().collect();
+        operations.sort_by(|a, b| {
+            b.rows_read
+                .cmp(&a.rows_read)
+                .then(b.rows_written.cmp(&a.rows_written))
+                .then(a.sql.cmp(&b.sql))
+        });
+        D1Usage {
+            since: self.since,
+            statements: self.statements,
+            rows_read: self.total.rows_read,
+            rows_written: self.total.rows_written,
+            duration: self.total.duration,
+            operations,
+        }
+    }
+}
+
+impl Default for UsageMeter {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(UsageState::new()),
+        }
+    }
+}
+
+impl UsageMeter {
+    /// 记录一条已执行语句的开销
+    pub fn record(&self, sql: &str, meta: &D1QueryMeta) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.total.add(meta);
+        state.statements += 1;
+
+        let key = normalize_sql(sql);
+        let entry = state
+            .operations
+            .entry(key.clone())
+            .or_insert_with(|| D1OperationUsage {
+                sql: key,
+                count: 0,
+                rows_read: 0,
+                rows_written: 0,
+                duration: 0.0,
+            });
+        entry.count += 1;
+        entry.rows_read += meta.rows_read;
+        entry.rows_written += meta.rows_written;
+        entry.duration += meta.duration;
+    }
+
+    /// 当前累计用量
+    pub fn snapshot(&self) -> D1Usage {
+        let Ok(state) = self.state.lock() else {
+            return UsageState::new().usage();
+        };
+        state.usage()
+    }
+
+    /// 清零并重新开始计数
+    pub fn reset(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            *state = UsageState::new();
+        }
+    }
+}
+
+/// 合并空白并折叠连续的占位符，使同一模板的语句归为一类
+fn normalize_sql(sql: &str) -> String {
+    let mut normalized = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    while normalized.contains("?, ?") {
+        normalized = normalized.replace("?, ?", "?");
+    }
+    normalized
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
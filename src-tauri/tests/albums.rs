@@ -0,0 +1,260 @@
+//! 相册：增删改、相册内与相册间的顺序、封面、相册筛选和整本导出
+mod common;
+
+use std::io::Read;
+
+use common::TestApp;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{Album, DownloadFileInfo, PictureQueryParams};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// 5 张图片，id 为 1..=5，地址指向测试服务器的 `/files/{id}.png`
+async fn library() -> TestApp {
+    let t = TestApp::new().await;
+    t.seed_generated_pictures(5, &format!("{}/files/", t.server.uri()))
+        .await;
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    t
+}
+
+async fn album(t: &TestApp, name: &str, picture_ids: &[i64]) -> Album {
+    let album = create_album(t.d1_client(), t.mirror(), name.into())
+        .await
+        .unwrap();
+    if picture_ids.is_empty() {
+        return album;
+    }
+    add_pictures_to_album(t.d1_client(), t.mirror(), album.id, picture_ids.to_vec())
+        .await
+        .unwrap()
+}
+
+async fn order(t: &TestApp, id: i64) -> Vec<i64> {
+    list_album_pictures(t.d1_client(), t.mirror(), id, None, None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| p.id)
+        .collect()
+}
+
+fn cover(t: &TestApp, id: i64) -> String {
+    format!("{}/files/{}.png", t.server.uri(), id)
+}
+
+#[tokio::test]
+async fn albums_keep_user_order_and_covers() {
+    let t = library().await;
+    let trip = album(&t, "Trip", &[3, 1, 3, 2]).await;
+    assert_eq!(trip.picture_count, 3);
+    assert_eq!(trip.cover_url, Some(cover(&t, 3)), "默认封面是第一张图片");
+
+    // 已在相册中的图片保持原位置，不存在的图片忽略
+    add_pictures_to_album(t.d1_client(), t.mirror(), trip.id, vec![1, 4, 99])
+        .await
+        .unwrap();
+    assert_eq!(order(&t, trip.id).await, vec![3, 1, 2, 4]);
+
+    let trip = reorder_album_pictures(t.d1_client(), t.mirror(), trip.id, vec![4, 2])
+        .await
+        .unwrap();
+    assert_eq!(order(&t, trip.id).await, vec![4, 2, 3, 1]);
+    assert_eq!(trip.cover_url, Some(cover(&t, 4)));
+    let error = reorder_album_pictures(t.d1_client(), t.mirror(), trip.id, vec![5])
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+
+    let trip = set_album_cover(t.d1_client(), t.mirror(), trip.id, Some(3))
+        .await
+        .unwrap();
+    assert_eq!(trip.cover_picture_id, Some(3));
+    assert_eq!(trip.cover_url, Some(cover(&t, 3)));
+    let error = set_album_cover(t.d1_client(), t.mirror(), trip.id, Some(5))
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+
+    // 移除封面图片后恢复默认封面
+    let trip = remove_pictures_from_album(t.d1_client(), t.mirror(), trip.id, vec![3, 4])
+        .await
+        .unwrap();
+    assert_eq!(trip.cover_picture_id, None);
+    assert_eq!(trip.cover_url, Some(cover(&t, 2)));
+    assert_eq!(trip.picture_count, 2);
+    assert_eq!(order(&t, trip.id).await, vec![2, 1]);
+}
+
+#[tokio::test]
+async fn albums_can_be_renamed_reordered_filtered_and_deleted() {
+    let t = library().await;
+    let trip = album(&t, "Trip", &[1, 2]).await;
+    let work = album(&t, "Work", &[2, 3]).await;
+    let empty = album(&t, "Empty", &[]).await;
+    assert_eq!(empty.cover_url, None);
+
+    let error = rename_album(t.d1_client(), t.mirror(), work.id, "trip".into())
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+    let error = create_album(t.d1_client(), t.mirror(), " TRIP ".into())
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+    rename_album(t.d1_client(), t.mirror(), work.id, "Office".into())
+        .await
+        .unwrap();
+
+    let albums = reorder_albums(t.d1_client(), t.mirror(), vec![empty.id, work.id])
+        .await
+        .unwrap();
+    let names: Vec<&str> = albums.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(names, vec!["Empty", "Office", "Trip"]);
+
+    // 一张图片可以属于多个相册，相册可与其他筛选条件组合
+    let in_album = |id: i64| PictureQueryParams {
+        album_id: Some(id),
+        order_by: Some("created_at_asc".into()),
+        ..Default::default()
+    };
+    let ids = |params: PictureQueryParams| async {
+        query_smms_pictures(t.d1_client(), t.mirror(), params)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|p| p.id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(in_album(trip.id)).await, vec![1, 2]);
+    assert_eq!(ids(in_album(work.id)).await, vec![2, 3]);
+    let combined = PictureQueryParams {
+        max_width: Some(5),
+        ..in_album(work.id)
+    };
+    assert_eq!(ids(combined).await, Vec::<i64>::new());
+
+    delete_album(t.d1_client(), t.mirror(), trip.id)
+        .await
+        .unwrap();
+    assert_eq!(ids(in_album(trip.id)).await, Vec::<i64>::new());
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures"), 5);
+    let error = delete_album(t.d1_client(), t.mirror(), trip.id)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "not_found");
+}
+
+#[tokio::test]
+async fn album_changes_from_other_devices_reach_the_mirror() {
+    let t = library().await;
+    let trip = album(&t, "Trip", &[1]).await;
+
+    t.sql(&format!(
+        "INSERT INTO album_pictures (album_id, picture_id, position) VALUES ({}, 5, 10);
+         UPDATE album_revision SET revision = revision + 1;",
+        trip.id
+    ));
+    assert_eq!(order(&t, trip.id).await, vec![1]);
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    assert_eq!(order(&t, trip.id).await, vec![1, 5]);
+
+    // 本机镜像落后时，本机修改后改为重新拉取
+    t.sql(&format!(
+        "DELETE FROM album_pictures WHERE album_id = {} AND picture_id = 1;
+         UPDATE album_revision SET revision = revision + 1;",
+        trip.id
+    ));
+    add_pictures_to_album(t.d1_client(), t.mirror(), trip.id, vec![2])
+        .await
+        .unwrap();
+    assert_eq!(order(&t, trip.id).await, vec![5, 2]);
+}
+
+#[tokio::test]
+async fn whole_album_is_exported_as_zip_in_album_order() {
+    let t = library().await;
+    for id in 1..=5 {
+        Mock::given(method("GET"))
+            .and(path(format!("/files/{id}.png")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(format!("image-{id}")))
+            .mount(&t.server)
+            .await;
+    }
+    let trip = album(&t, "Trip", &[3, 1]).await;
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("trip.zip");
+    let files = vec![
+        DownloadFileInfo {
+            url: cover(&t, 5),
+            filename: "extra.png".into(),
+        },
+        DownloadFileInfo {
+            url: cover(&t, 4),
+            filename: "extra.png".into(),
+        },
+    ];
+
+    download_files_as_zip(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.jobs(),
+        files,
+        target.to_string_lossy().into_owned(),
+        Some(trip.id),
+        "download".into(),
+    )
+    .await
+    .unwrap();
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(target).unwrap()).unwrap();
+    let names: Vec<String> = (0..archive.len())
+        .map(|i| archive.by_index(i).unwrap().name().to_string())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["extra.png", "extra (2).png", "1_shot3.png", "2_shot1.png"]
+    );
+    let mut content = String::new();
+    archive
+        .by_name("1_shot3.png")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "image-3");
+
+    let error = download_files_as_zip(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.jobs(),
+        Vec::new(),
+        dir.path().join("none.zip").to_string_lossy().into_owned(),
+        Some(999),
+        "download".into(),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.code(), "not_found");
+}
+
+#[tokio::test]
+async fn pictures_not_yet_in_the_mirror_are_kept_in_albums() {
+    let t = library().await;
+    // 其他设备刚上传的图片，本机镜像还没有拉取
+    t.sql(
+        "INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, created_at)
+         VALUES ('h6', 'shot6.png', 's.png', 'png', 10, 10, 1, '/p', 'https://u/6', 'https://d', 'https://p', '2024-01-06 00:00:00');",
+    );
+    let trip = album(&t, "Trip", &[6, 1]).await;
+
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    assert_eq!(order(&t, trip.id).await, vec![6, 1]);
+}
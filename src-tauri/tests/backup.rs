@@ -0,0 +1,175 @@
+//! 备份与恢复：导出为 SQLite 文件或 SQL 文本，恢复到空数据库并核对行数
+mod common;
+
+use common::{smms_item, TestApp};
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{BackupFormat, StorageKind, TableCount};
+
+/// 包含凭证、600 张图片（超过一页）和本地修改的测试应用
+async fn library() -> TestApp {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[smms_item("h0", "first.png")]).await;
+    t.seed_generated_pictures(599, "https://u/").await;
+    t.sql("UPDATE smms_pictures SET remark = 'it''s mine', is_favorite = 1 WHERE id = 1;");
+    t
+}
+
+fn rows(report: &[TableCount], table: &str) -> i64 {
+    report
+        .iter()
+        .find(|count| count.table == table)
+        .map_or(-1, |count| count.rows)
+}
+
+#[tokio::test]
+async fn sqlite_backup_restores_into_empty_database() {
+    let t = library().await;
+    let path = t.config_dir.path().join("library.sqlite");
+
+    let report = export_database_backup(t.d1_client(), path.to_string_lossy().into(), None)
+        .await
+        .unwrap();
+    assert_eq!(report.format, BackupFormat::Sqlite);
+    assert_eq!(rows(&report.tables, "smms_pictures"), 600);
+    assert_eq!(rows(&report.tables, "smms_user"), 1);
+
+    // 备份是独立可读的 SQLite 文件
+    let file = rusqlite::Connection::open(&path).unwrap();
+    let remark: String = file
+        .query_row("SELECT remark FROM smms_pictures WHERE id = 1", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(remark, "it's mine");
+
+    let target = TestApp::new().await;
+    let restored = restore_database_backup(
+        target.d1_client(),
+        target.mirror(),
+        path.to_string_lossy().into(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(rows(&restored.tables, "smms_pictures"), 600);
+    let favorite: i64 = target.scalar("SELECT is_favorite FROM smms_pictures WHERE id = 1");
+    assert_eq!(favorite, 1);
+    assert_eq!(
+        load_smms_user(target.d1_client(), None)
+            .await
+            .unwrap()
+            .token,
+        common::TOKEN
+    );
+    // 恢复后本地镜像已重新下载
+    assert_eq!(target.mirror().status().unwrap().pictures, 600);
+}
+
+#[tokio::test]
+async fn sql_dump_restores_with_credentials_reencrypted() {
+    let t = library().await;
+    let path = t.config_dir.path().join("library.sql");
+
+    let report = export_database_backup(t.d1_client(), path.to_string_lossy().into(), None)
+        .await
+        .unwrap();
+    assert_eq!(report.format, BackupFormat::Sql);
+    let dump = std::fs::read_to_string(&path).unwrap();
+    assert!(dump.contains("INSERT INTO \"smms_pictures\""));
+    assert!(dump.contains("'it''s mine'"));
+
+    // 本地后端的密钥与 D1 不同，凭证需要重新加密
+    let target = TestApp::new().await;
+    set_storage_backend(
+        target.config_store(),
+        target.d1_client(),
+        StorageKind::Local,
+    )
+    .await
+    .unwrap();
+    restore_database_backup(
+        target.d1_client(),
+        target.mirror(),
+        path.to_string_lossy().into(),
+    )
+    .await
+    .unwrap();
+
+    let user = load_smms_user(target.d1_client(), None).await.unwrap();
+    assert_eq!(
+        (user.password.as_str(), user.token.as_str()),
+        ("secret", common::TOKEN)
+    );
+    let count = get_pictures_count(target.d1_client(), target.mirror(), Default::default())
+        .await
+        .unwrap();
+    assert_eq!(count, 600);
+}
+
+#[tokio::test]
+async fn restore_requires_an_empty_database() {
+    let t = library().await;
+    let path = t.config_dir.path().join("library.sqlite");
+    export_database_backup(
+        t.d1_client(),
+        path.to_string_lossy().into(),
+        Some(BackupFormat::Sqlite),
+    )
+    .await
+    .unwrap();
+
+    let error = restore_database_backup(t.d1_client(), t.mirror(), path.to_string_lossy().into())
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+    let count: i64 = t.scalar("SELECT COUNT(*) FROM smms_pictures");
+    assert_eq!(count, 600);
+
+    let other = t.config_dir.path().join("notes.txt");
+    std::fs::write(&other, "SELECT 1;").unwrap();
+    let error = restore_database_backup(t.d1_client(), t.mirror(), other.to_string_lossy().into())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("备份文件"), "{error}");
+}
+
+#[tokio::test]
+async fn failed_restore_leaves_the_target_empty_for_a_retry() {
+    let t = library().await;
+    let path = t.config_dir.path().join("library.sqlite");
+    export_database_backup(t.d1_client(), path.to_string_lossy().into(), None)
+        .await
+        .unwrap();
+
+    // 图片分多批写入，拒绝后面一批中的一行
+    let target = TestApp::new().await;
+    sm_flare_lib::services::migrations::ensure_ready(&target.d1_client())
+        .await
+        .unwrap();
+    target.sql(
+        "CREATE TRIGGER reject_picture BEFORE INSERT ON smms_pictures
+         WHEN NEW.filename = 'shot500.png'
+         BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+    );
+    let error = restore_database_backup(
+        target.d1_client(),
+        target.mirror(),
+        path.to_string_lossy().into(),
+    )
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("smms_pictures"), "{error}");
+    let pictures: i64 = target.scalar("SELECT COUNT(*) FROM smms_pictures");
+    let users: i64 = target.scalar("SELECT COUNT(*) FROM smms_user");
+    assert_eq!((pictures, users), (0, 0));
+
+    target.sql("DROP TRIGGER reject_picture");
+    let restored = restore_database_backup(
+        target.d1_client(),
+        target.mirror(),
+        path.to_string_lossy().into(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(rows(&restored.tables, "smms_pictures"), 600);
+}
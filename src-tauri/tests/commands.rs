@@ -0,0 +1,425 @@
+//! 针对进程内 mock 服务运行全部 Tauri 命令
+mod common;
+
+use std::io::Read;
+
+use common::{smms_item, TestApp, TOKEN};
+use serde_json::json;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{D1Param, DownloadFileInfo, PictureQueryParams};
+use wiremock::matchers::{body_string_contains, header, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn query(remark: Option<&str>) -> PictureQueryParams {
+    PictureQueryParams {
+        include_deleted: Some(false),
+        remark: remark.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn get_smms_token_uses_configured_endpoint() {
+    let t = TestApp::new().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v2/token"))
+        .and(body_string_contains("username=tester"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": true,
+            "message": "Get API token success.",
+            "data": { "token": "fresh-token" }
+        })))
+        .mount(&t.server)
+        .await;
+
+    let token = get_smms_token(t.smms_client(), "tester".into(), "secret".into())
+        .await
+        .unwrap();
+    assert_eq!(token, "fresh-token");
+}
+
+#[tokio::test]
+async fn d1_config_is_saved_reloaded_and_deleted() {
+    let t = TestApp::new().await;
+    let mut config = common::d1_config();
+    config.database_id = "other".to_string();
+
+    save_d1_config(t.config_store(), t.d1_client(), config)
+        .await
+        .unwrap();
+    assert!(t.config_dir.path().join("d1_config.json").exists());
+    let loaded = load_d1_config(t.config_store()).await.unwrap();
+    assert_eq!(loaded.database_id, "other");
+
+    delete_d1_config(t.config_store(), t.d1_client())
+        .await
+        .unwrap();
+    assert!(!t.config_dir.path().join("d1_config.json").exists());
+    assert!(load_d1_config(t.config_store()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_d1_connection_reaches_mock() {
+    let t = TestApp::new().await;
+    let message = test_d1_connection(t.d1_client(), common::d1_config())
+        .await
+        .unwrap();
+    assert_eq!(message, "连接成功");
+}
+
+#[tokio::test]
+async fn execute_d1_query_binds_params() {
+    let t = TestApp::new().await;
+    let output = execute_d1_query(
+        t.d1_client(),
+        "SELECT ? AS text, ? AS number".into(),
+        Some(vec![D1Param::Text("it's".into()), D1Param::Integer(7)]),
+    )
+    .await
+    .unwrap();
+    assert_eq!(output.results, vec![json!({ "text": "it's", "number": 7 })]);
+}
+
+#[tokio::test]
+async fn smms_user_round_trip_keeps_credentials_encrypted() {
+    let t = TestApp::new().await;
+    t.login().await;
+
+    let stored: String = t.scalar("SELECT encrypted_password FROM smms_user");
+    assert_ne!(stored, "secret");
+
+    let user = load_smms_user(t.d1_client(), Some("tester".into()))
+        .await
+        .unwrap();
+    assert_eq!(user.password, "secret");
+    assert_eq!(user.token, TOKEN);
+}
+
+#[tokio::test]
+async fn upload_history_sends_token() {
+    let t = TestApp::new().await;
+    t.login().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v2/upload_history"))
+        .and(header("Authorization", TOKEN))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": true,
+            "message": "ok",
+            "data": [smms_item("h1", "a.png")]
+        })))
+        .mount(&t.server)
+        .await;
+
+    let items = get_smms_upload_history(t.d1_client(), t.smms_client(), Some(1))
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].hash, "h1");
+}
+
+#[tokio::test]
+async fn sync_smms_pictures_upserts_page() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.mock_upload_history(vec![vec![
+        smms_item("h1", "it's.png"),
+        smms_item("h2", "b.JPG"),
+    ]])
+    .await;
+
+    sync_smms_pictures(t.d1_client(), t.mirror(), t.smms_client(), Some(1))
+        .await
+        .unwrap();
+
+    let count: i64 = t.scalar("SELECT COUNT(*) FROM smms_pictures");
+    assert_eq!(count, 2);
+    let file_type: String = t.scalar("SELECT file_type FROM smms_pictures WHERE file_hash = 'h2'");
+    assert_eq!(file_type, "jpg");
+    let filename: String = t.scalar("SELECT filename FROM smms_pictures WHERE file_hash = 'h1'");
+    assert_eq!(filename, "it's.png");
+}
+
+#[tokio::test]
+async fn import_all_marks_missing_pictures_deleted() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[smms_item("gone", "gone.png"), smms_item("h1", "a.png")])
+        .await;
+    t.mock_upload_history(vec![
+        vec![smms_item("h1", "a.png"), smms_item("h2", "b.png")],
+        vec![smms_item("h3", "c.png")],
+    ])
+    .await;
+
+    let stats = import_all_smms_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        "import".into(),
+    )
+    .await
+    .unwrap();
+    assert_eq!((stats.added, stats.skipped, stats.deleted), (2, 1, 1));
+
+    let deleted: i64 = t.scalar("SELECT is_deleted FROM smms_pictures WHERE file_hash = 'gone'");
+    assert_eq!(deleted, 1);
+}
+
+#[tokio::test]
+async fn import_all_keeps_pictures_when_later_pages_fail() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[smms_item("later", "later.png"), smms_item("h1", "a.png")])
+        .await;
+    t.mock_upload_history(vec![vec![smms_item("h1", "a.png")]])
+        .await;
+    // 第一页之后的页面全部失败
+    Mock::given(method("GET"))
+        .and(path("/api/v2/upload_history"))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(6)
+        .mount(&t.server)
+        .await;
+
+    let stats = import_all_smms_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        "import".into(),
+    )
+    .await
+    .unwrap();
+    assert_eq!((stats.skipped, stats.deleted), (1, 0));
+    assert!(!stats.cancelled);
+
+    let deleted: i64 = t.scalar("SELECT is_deleted FROM smms_pictures WHERE file_hash = 'later'");
+    assert_eq!(deleted, 0);
+}
+
+#[tokio::test]
+async fn query_and_count_match_special_characters_literally() {
+    let t = TestApp::new().await;
+    t.seed_pictures(&[
+        smms_item("h1", "a.png"),
+        smms_item("h2", "b.png"),
+        smms_item("h3", "c.png"),
+    ])
+    .await;
+    update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        1,
+        Some(r"it's 50% C:\tmp 🎉".into()),
+    )
+    .await
+    .unwrap();
+    update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        2,
+        Some("50 percent".into()),
+    )
+    .await
+    .unwrap();
+
+    for keyword in ["it's", "50%", r"C:\tmp", "🎉"] {
+        let pictures = query_smms_pictures(t.d1_client(), t.mirror(), query(Some(keyword)))
+            .await
+            .unwrap();
+        assert_eq!(pictures.len(), 1, "keyword {keyword}");
+        assert_eq!(pictures[0].remark.as_deref(), Some(r"it's 50% C:\tmp 🎉"));
+
+        let count = get_pictures_count(t.d1_client(), t.mirror(), query(Some(keyword)))
+            .await
+            .unwrap();
+        assert_eq!(count, 1, "keyword {keyword}");
+    }
+
+    let mut page = query(None);
+    page.limit = Some(2);
+    page.offset = Some(1);
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), page)
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 2);
+}
+
+#[tokio::test]
+async fn file_types_and_favorites() {
+    let t = TestApp::new().await;
+    t.seed_pictures(&[smms_item("h1", "a.png")]).await;
+    t.sql("UPDATE smms_pictures SET file_type = 'gif' WHERE file_hash = 'h1'");
+
+    let types = get_all_file_types(t.d1_client(), t.mirror()).await.unwrap();
+    assert_eq!(types, vec!["gif".to_string()]);
+
+    toggle_picture_favorite(t.d1_client(), t.mirror(), t.smms_client(), 1, true)
+        .await
+        .unwrap();
+    let mut favorites = query(None);
+    favorites.is_favorite = Some(true);
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), favorites)
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 1);
+}
+
+#[tokio::test]
+async fn upload_images_stores_remark_verbatim() {
+    let t = TestApp::new().await;
+    t.login().await;
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("shot.png");
+    std::fs::write(&file, b"png-bytes").unwrap();
+    let missing = dir.path().join("missing.png");
+    let remark = r#"O'Brien "quoted" \ 😀"#;
+
+    let results = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        vec![
+            file.to_string_lossy().into_owned(),
+            missing.to_string_lossy().into_owned(),
+        ],
+        Some(remark.to_string()),
+        None,
+        None,
+        "upload".into(),
+    )
+    .await
+    .unwrap();
+
+    assert!(results[0].success, "{}", results[0].message);
+    assert!(!results[1].success);
+    let stored: String = t.scalar("SELECT remark FROM smms_pictures WHERE filename = 'shot.png'");
+    assert_eq!(stored, remark);
+}
+
+#[tokio::test]
+async fn delete_commands_soft_delete_records() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[
+        smms_item("h1", "a.png"),
+        smms_item("h2", "b.png"),
+        smms_item("h3", "c.png"),
+    ])
+    .await;
+
+    delete_picture(t.d1_client(), t.mirror(), t.smms_client(), 1)
+        .await
+        .unwrap();
+    let result = batch_delete_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        vec![2, 3, 99],
+        "delete".into(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.success_count, 2);
+    assert_eq!(result.failed_count, 1);
+    let deleted: i64 = t.scalar("SELECT COUNT(*) FROM smms_pictures WHERE is_deleted = 1");
+    assert_eq!(deleted, 3);
+}
+
+#[tokio::test]
+async fn batch_update_remark_covers_more_ids_than_one_statement_allows() {
+    let t = TestApp::new().await;
+    let items: Vec<_> = (0..150)
+        .map(|i| smms_item(&format!("h{i}"), &format!("{i}.png")))
+        .collect();
+    t.seed_pictures(&items).await;
+
+    batch_update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        (1..=150).collect(),
+        Some("bulk".into()),
+    )
+    .await
+    .unwrap();
+
+    let count: i64 = t.scalar("SELECT COUNT(*) FROM smms_pictures WHERE remark = 'bulk'");
+    assert_eq!(count, 150);
+}
+
+#[tokio::test]
+async fn download_single_file_writes_bytes() {
+    let t = TestApp::new().await;
+    Mock::given(method("GET"))
+        .and(path("/files/a.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"image-a".to_vec()))
+        .mount(&t.server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("a.png");
+
+    download_single_file(
+        t.app.handle().clone(),
+        t.jobs(),
+        format!("{}/files/a.png", t.server.uri()),
+        target.to_string_lossy().into_owned(),
+        "download".into(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(std::fs::read(target).unwrap(), b"image-a");
+}
+
+#[tokio::test]
+async fn download_files_as_zip_packs_successful_files() {
+    let t = TestApp::new().await;
+    for name in ["a.png", "b.png"] {
+        Mock::given(method("GET"))
+            .and(path(format!("/files/{name}")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(name.as_bytes().to_vec()))
+            .mount(&t.server)
+            .await;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("pictures.zip");
+    let files = ["a.png", "b.png", "missing.png"]
+        .iter()
+        .map(|name| DownloadFileInfo {
+            url: format!("{}/files/{}", t.server.uri(), name),
+            filename: name.to_string(),
+        })
+        .collect();
+
+    let message = download_files_as_zip(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.jobs(),
+        files,
+        target.to_string_lossy().into_owned(),
+        None,
+        "download".into(),
+    )
+    .await
+    .unwrap();
+    assert!(message.contains("1 个失败"), "{message}");
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(target).unwrap()).unwrap();
+    assert_eq!(archive.len(), 2);
+    let mut content = String::new();
+    archive
+        .by_name("b.png")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "b.png");
+}
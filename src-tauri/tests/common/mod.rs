@@ -0,0 +1,412 @@
+//! 集成测试公共设施：进程内 mock 服务替代 SM.MS 与 Cloudflare D1
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use sm_flare_lib::models::D1Config;
+use sm_flare_lib::services::config::{ApiEndpoints, ConfigStore};
+use sm_flare_lib::services::d1::D1Client;
+use sm_flare_lib::services::jobs::JobRegistry;
+use sm_flare_lib::services::mirror::LocalMirror;
+use sm_flare_lib::services::retry::RetryPolicy;
+use sm_flare_lib::services::smms::SmmsClient;
+use tauri::test::MockRuntime;
+use tauri::{App, Manager, State};
+use tempfile::TempDir;
+use wiremock::matchers::{method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+pub const TOKEN: &str = "test-token";
+
+/// 用内存 SQLite 模拟 D1 `/query` 接口，支持单条语句和 `batch`
+#[derive(Clone)]
+pub struct FakeD1 {
+    pub db: Arc<Mutex<Connection>>,
+    /// 为 true 时所有请求返回 503，模拟网络不可用
+    offline: Arc<AtomicBool>,
+}
+
+impl FakeD1 {
+    fn new() -> Self {
+        Self {
+            db: Arc::new(Mutex::new(Connection::open_in_memory().unwrap())),
+            offline: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::SeqCst);
+    }
+
+    fn run(conn: &Connection, statement: &Value) -> Result<Value, String> {
+        let sql = statement["sql"].as_str().ok_or("missing sql")?;
+        let params: Vec<SqlValue> = statement["params"]
+            .as_array()
+            .map(|params| params.iter().map(to_sql_value).collect())
+            .unwrap_or_default();
+
+        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let mut results = Vec::new();
+        let before = conn.total_changes();
+        if columns.is_empty() {
+            stmt.execute(rusqlite::params_from_iter(params))
+                .map_err(|e| e.to_string())?;
+        } else {
+            let mut rows = stmt
+                .query(rusqlite::params_from_iter(params))
+                .map_err(|e| e.to_string())?;
+            while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+                let mut object = serde_json::Map::new();
+                for (index, column) in columns.iter().enumerate() {
+                    object.insert(column.clone(), to_json_value(row.get_ref(index).unwrap()));
+                }
+                results.push(Value::Object(object));
+            }
+        }
+
+        let changes = conn.total_changes() - before;
+        Ok(json!({
+            "results": results,
+            "success": true,
+            "meta": {
+                "changes": changes,
+                "last_row_id": conn.last_insert_rowid(),
+                "rows_read": results.len(),
+                "rows_written": changes,
+                "duration": 0.1,
+                "served_by": "fake-d1"
+            }
+        }))
+    }
+}
+
+impl Respond for FakeD1 {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        if self.offline.load(Ordering::SeqCst) {
+            return ResponseTemplate::new(503);
+        }
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let statements = match body.get("batch") {
+            Some(batch) => batch.as_array().cloned().unwrap_or_default(),
+            None => vec![body],
+        };
+
+        let conn = self.db.lock().unwrap();
+        conn.execute_batch("SAVEPOINT fake_d1").unwrap();
+        let mut results = Vec::new();
+        for statement in &statements {
+            match Self::run(&conn, statement) {
+                Ok(result) => results.push(result),
+                Err(message) => {
+                    conn.execute_batch("ROLLBACK TO fake_d1; RELEASE fake_d1")
+                        .unwrap();
+                    return ResponseTemplate::new(400).set_body_json(json!({
+                        "success": false,
+                        "errors": [{ "code": 7500, "message": message }],
+                        "messages": [],
+                        "result": null
+                    }));
+                }
+            }
+        }
+        conn.execute_batch("RELEASE fake_d1").unwrap();
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "success": true,
+            "errors": [],
+            "messages": [],
+            "result": results
+        }))
+    }
+}
+
+fn to_sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::Number(n) => n
+            .as_i64()
+            .map(SqlValue::Integer)
+            .unwrap_or_else(|| SqlValue::Real(n.as_f64().unwrap_or_default())),
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn to_json_value(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => json!(i),
+        ValueRef::Real(f) => json!(f),
+        ValueRef::Text(t) => json!(String::from_utf8_lossy(t)),
+        ValueRef::Blob(_) => Value::Null,
+    }
+}
+
+/// 模拟 SM.MS 上传接口：按请求顺序生成唯一 hash，回显上传的文件名
+struct FakeUpload {
+    counter: AtomicUsize,
+}
+
+impl Respond for FakeUpload {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let n = self.counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let body = String::from_utf8_lossy(&request.body);
+        let filename = body
+            .split("filename=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or("unknown.png")
+            .to_string();
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "success": true,
+            "code": "success",
+            "message": "Upload success.",
+            "data": smms_item(&format!("up{}", n), &filename),
+            "RequestId": "req"
+        }))
+    }
+}
+
+/// 构造一条 SM.MS 图片记录
+pub fn smms_item(hash: &str, filename: &str) -> Value {
+    json!({
+        "file_id": 0,
+        "width": 800,
+        "height": 600,
+        "filename": filename,
+        "storename": format!("{}.png", hash),
+        "size": 1024,
+        "path": format!("/2024/01/01/{}.png", hash),
+        "hash": hash,
+        "created_at": "2024-01-01 00:00:00",
+        "url": format!("https://i.loli.net/{}.png", hash),
+        "delete": format!("https://sm.ms/delete/{}", hash),
+        "page": format!("https://sm.ms/image/{}", hash)
+    })
+}
+
+/// 测试用应用：mock Tauri 应用 + mock HTTP 服务 + 临时配置目录
+pub struct TestApp {
+    pub app: App<MockRuntime>,
+    pub server: MockServer,
+    pub d1: FakeD1,
+    pub config_dir: TempDir,
+}
+
+impl TestApp {
+    /// 创建测试应用并执行结构迁移（与应用启动时一致）
+    pub async fn new() -> Self {
+        let t = Self::unmigrated().await;
+        sm_flare_lib::services::migrations::migrate(&t.d1_client())
+            .await
+            .unwrap();
+        t
+    }
+
+    /// 创建测试应用，数据库保持为空
+    pub async fn unmigrated() -> Self {
+        let server = MockServer::start().await;
+        let d1 = FakeD1::new();
+        Mock::given(method("POST"))
+            .and(path_regex(
+                r"^/client/v4/accounts/[^/]+/d1/database/[^/]+/query$",
+            ))
+            .respond_with(d1.clone())
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v2/upload"))
+            .respond_with(FakeUpload {
+                counter: AtomicUsize::new(0),
+            })
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/api/v2/delete/[^/]+$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "code": "success",
+                "message": "File delete success.",
+                "RequestId": "req"
+            })))
+            .mount(&server)
+            .await;
+
+        let config_dir = tempfile::tempdir().unwrap();
+        let endpoints = ApiEndpoints::with_base(&server.uri());
+        let app = tauri::test::mock_app();
+        app.manage(
+            D1Client::new(endpoints.cloudflare, Some(d1_config())).with_retry_policy(fast_retry()),
+        );
+        app.manage(SmmsClient::new(endpoints.smms).with_retry_policy(fast_retry()));
+        app.manage(LocalMirror::open(&config_dir.path().join("mirror.sqlite")).unwrap());
+        app.manage(ConfigStore::new(config_dir.path()));
+        app.manage(JobRegistry::new());
+
+        Self {
+            app,
+            server,
+            d1,
+            config_dir,
+        }
+    }
+
+    pub fn d1_client(&self) -> State<'_, D1Client> {
+        self.app.state()
+    }
+
+    pub fn smms_client(&self) -> State<'_, SmmsClient> {
+        self.app.state()
+    }
+
+    pub fn mirror(&self) -> State<'_, LocalMirror> {
+        self.app.state()
+    }
+
+    pub fn config_store(&self) -> State<'_, ConfigStore> {
+        self.app.state()
+    }
+
+    pub fn jobs(&self) -> State<'_, JobRegistry> {
+        self.app.state()
+    }
+
+    /// 保存带 token 的 SM.MS 用户，供需要登录的命令使用
+    pub async fn login(&self) {
+        sm_flare_lib::commands::save_smms_user(
+            self.d1_client(),
+            "tester".to_string(),
+            "secret".to_string(),
+            TOKEN.to_string(),
+        )
+        .await
+        .unwrap();
+    }
+
+    /// 挂载上传历史分页，超出的页返回空列表
+    pub async fn mock_upload_history(&self, pages: Vec<Vec<Value>>) {
+        for (index, items) in pages.into_iter().enumerate() {
+            Mock::given(method("GET"))
+                .and(path("/api/v2/upload_history"))
+                .and(query_param("page", (index + 1).to_string()))
+                .respond_with(history_response(items))
+                .mount(&self.server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/api/v2/upload_history"))
+            .respond_with(history_response(vec![]))
+            .with_priority(10)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// mock D1 已收到的请求数
+    pub async fn d1_requests(&self) -> usize {
+        self.server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.url.path().ends_with("/query"))
+            .count()
+    }
+
+    /// 在 mock 数据库上直接执行 SQL
+    pub fn sql(&self, sql: &str) {
+        self.d1.db.lock().unwrap().execute_batch(sql).unwrap();
+    }
+
+    /// 在 mock 数据库上查询单个值
+    pub fn scalar<T: rusqlite::types::FromSql>(&self, sql: &str) -> T {
+        self.d1
+            .db
+            .lock()
+            .unwrap()
+            .query_row(sql, [], |row| row.get(0))
+            .unwrap()
+    }
+
+    /// 直接写入若干条图片记录，返回插入数量
+    pub async fn seed_pictures(&self, items: &[Value]) -> usize {
+        sm_flare_lib::commands::init_smms_pictures_table(self.d1_client())
+            .await
+            .unwrap();
+        let conn = self.d1.db.lock().unwrap();
+        for item in items {
+            conn.execute(
+                "INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, created_at) \
+                 VALUES (?1, ?2, ?3, 'png', ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                rusqlite::params![
+                    item["hash"].as_str(),
+                    item["filename"].as_str(),
+                    item["storename"].as_str(),
+                    item["width"].as_i64(),
+                    item["height"].as_i64(),
+                    item["size"].as_i64(),
+                    item["path"].as_str(),
+                    item["url"].as_str(),
+                    item["delete"].as_str(),
+                    item["page"].as_str(),
+                    item["created_at"].as_str(),
+                ],
+            )
+            .unwrap();
+        }
+        items.len()
+    }
+
+    /// 批量生成 `n` 条图片记录：hash 为 `h{i}`、文件名为 `shot{i}.png`、地址为 `{url_prefix}{i}.png`，
+    /// 创建时间从 2024-01-01 起每张递增一天
+    pub async fn seed_generated_pictures(&self, n: usize, url_prefix: &str) -> usize {
+        sm_flare_lib::commands::init_smms_pictures_table(self.d1_client())
+            .await
+            .unwrap();
+        self.d1
+            .db
+            .lock()
+            .unwrap()
+            .execute(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < ?1)
+                 INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, created_at)
+                 SELECT 'h' || i, 'shot' || i || '.png', 's.png', 'png', 10, 10, 1, '/p', ?2 || i || '.png', 'https://d', 'https://p', datetime('2024-01-01', '+' || (i - 1) || ' days') FROM n",
+                rusqlite::params![n as i64, url_prefix],
+            )
+            .unwrap()
+    }
+}
+
+fn history_response(items: Vec<Value>) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "success": true,
+        "code": "success",
+        "message": "Get list success.",
+        "data": items,
+        "RequestId": "req"
+    }))
+}
+
+/// 测试用重试策略：退避时间极短，`Retry-After` 超过 1 秒即放弃
+pub fn fast_retry() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_secs(1),
+    }
+}
+
+pub fn d1_config() -> D1Config {
+    D1Config {
+        account_id: "account".to_string(),
+        database_id: "database".to_string(),
+        api_token: "cf-token".to_string(),
+    }
+}
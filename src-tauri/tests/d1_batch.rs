@@ -0,0 +1,141 @@
+//! 多语句批量执行：逐条结果、自动拆分与失败定位
+mod common;
+
+use common::{smms_item, TestApp};
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{D1Param, D1Statement};
+
+fn insert_note(id: i64, body: Option<&str>) -> D1Statement {
+    D1Statement::new("INSERT INTO notes (id, body) VALUES (?, ?)")
+        .bind(id)
+        .bind(body)
+}
+
+#[tokio::test]
+async fn batch_returns_rows_written_per_statement() {
+    let t = TestApp::new().await;
+    t.sql("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL)");
+    t.sql("INSERT INTO notes VALUES (1, 'a'), (2, 'b')");
+
+    let result = execute_d1_batch(
+        t.d1_client(),
+        vec![
+            insert_note(3, Some("c")),
+            D1Statement::new("UPDATE notes SET body = 'x' WHERE id <= 3"),
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert!(result.is_success());
+    let written: Vec<u64> = result.results.iter().map(|r| r.meta.rows_written).collect();
+    assert_eq!(written, vec![1, 3]);
+}
+
+#[tokio::test]
+async fn batch_reports_rejected_statement_and_commits_prefix() {
+    let t = TestApp::new().await;
+    t.sql("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL)");
+
+    let statements = vec![
+        insert_note(1, Some("a")),
+        insert_note(2, Some("b")),
+        insert_note(3, Some("c")),
+        insert_note(4, None),
+        insert_note(5, Some("e")),
+    ];
+    let result = execute_d1_batch(t.d1_client(), statements).await.unwrap();
+
+    assert_eq!(result.failed_index, Some(3));
+    assert!(result.error.unwrap().message().contains("NOT NULL"));
+    let succeeded: Vec<usize> = result
+        .results
+        .iter()
+        .filter(|r| r.success)
+        .map(|r| r.index)
+        .collect();
+    assert_eq!(succeeded, vec![0, 1, 2]);
+    let count: i64 = t.scalar("SELECT COUNT(*) FROM notes");
+    assert_eq!(count, 3);
+}
+
+#[tokio::test]
+async fn batch_splits_large_input_into_several_requests() {
+    let t = TestApp::new().await;
+    t.sql("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL)");
+
+    let statements = (1..=250).map(|id| insert_note(id, Some("x"))).collect();
+    let before = t.d1_requests().await;
+    let result = execute_d1_batch(t.d1_client(), statements).await.unwrap();
+
+    assert!(result.is_success());
+    assert_eq!(result.results.len(), 250);
+    assert_eq!(t.d1_requests().await - before, 3);
+    let count: i64 = t.scalar("SELECT COUNT(*) FROM notes");
+    assert_eq!(count, 250);
+}
+
+#[tokio::test]
+async fn batch_rejects_statement_over_param_limit_without_sending() {
+    let t = TestApp::new().await;
+    let placeholders = vec!["?"; 101].join(", ");
+    let oversized = D1Statement {
+        sql: format!("SELECT {}", placeholders),
+        params: vec![D1Param::Integer(1); 101],
+    };
+    let before = t.d1_requests().await;
+
+    let result = execute_d1_batch(t.d1_client(), vec![D1Statement::new("SELECT 1"), oversized])
+        .await
+        .unwrap();
+
+    assert_eq!(result.failed_index, Some(1));
+    assert!(result.results.is_empty());
+    assert_eq!(t.d1_requests().await, before);
+}
+
+#[tokio::test]
+async fn import_all_names_rejected_record() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[]).await;
+    t.sql(
+        "CREATE TRIGGER reject_bad BEFORE INSERT ON smms_pictures WHEN NEW.file_hash = 'bad' \
+         BEGIN SELECT RAISE(ABORT, 'rejected by trigger'); END",
+    );
+    t.mock_upload_history(vec![vec![
+        smms_item("h1", "a.png"),
+        smms_item("bad", "broken.png"),
+        smms_item("h3", "c.png"),
+    ]])
+    .await;
+
+    let error = import_all_smms_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        "import".into(),
+    )
+    .await
+    .unwrap_err()
+    .to_string();
+
+    assert!(error.contains("broken.png"), "{error}");
+    assert!(error.contains("bad"), "{error}");
+    let imported: String = t.scalar("SELECT group_concat(file_hash) FROM smms_pictures");
+    assert_eq!(imported, "h1");
+}
+
+#[tokio::test]
+async fn init_table_names_rejected_index() {
+    let t = TestApp::unmigrated().await;
+    t.sql("CREATE TABLE idx_smms_pictures_type (id INTEGER)");
+
+    let error = init_smms_pictures_table(t.d1_client())
+        .await
+        .unwrap_err()
+        .to_string();
+
+    assert!(error.contains("idx_smms_pictures_type"), "{error}");
+}
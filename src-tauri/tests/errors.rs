@@ -0,0 +1,109 @@
+//! 命令错误的结构化表示：错误码、类别与可重试标记
+mod common;
+
+use common::TestApp;
+use serde_json::json;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::error::AppError;
+use sm_flare_lib::models::D1Statement;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn missing_d1_config_serializes_as_config_error() {
+    let t = TestApp::new().await;
+    t.d1_client().clear();
+
+    let error = execute_d1_query(t.d1_client(), "SELECT 1".into(), None)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        serde_json::to_value(&error).unwrap(),
+        json!({
+            "code": "config_missing",
+            "category": "config",
+            "retryable": false,
+            "message": "配置文件不存在",
+            "details": null
+        })
+    );
+}
+
+#[tokio::test]
+async fn d1_rejection_carries_d1_error_code() {
+    let t = TestApp::new().await;
+
+    let error = execute_d1_query(t.d1_client(), "SELECT * FROM missing".into(), None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "d1_error");
+    assert_eq!(error.category(), "database");
+    assert!(!error.retryable());
+    assert!(error.message().starts_with("查询失败"), "{error}");
+    assert_eq!(error.details()["d1_code"], json!(7500));
+}
+
+#[tokio::test]
+async fn batch_error_points_at_rejected_statement() {
+    let t = TestApp::new().await;
+
+    let statements = vec![
+        D1Statement::new("SELECT 1"),
+        D1Statement::new("SELECT * FROM missing"),
+    ];
+    let result = execute_d1_batch(t.d1_client(), statements).await.unwrap();
+    let error = result.error.unwrap();
+    assert_eq!(error.code(), "d1_error");
+    assert_eq!(error.details()["statement"], json!(1));
+}
+
+#[tokio::test]
+async fn missing_smms_login_requires_auth() {
+    let t = TestApp::new().await;
+
+    let error = get_smms_upload_history(t.d1_client(), t.smms_client(), None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.category(), "auth");
+    assert_eq!(error.code(), "auth_required");
+}
+
+#[tokio::test]
+async fn rejected_smms_token_requires_auth() {
+    let t = TestApp::new().await;
+    t.login().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v2/upload_history"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": false,
+            "code": "unauthorized",
+            "message": "Token mismatch.",
+            "RequestId": "req"
+        })))
+        .mount(&t.server)
+        .await;
+
+    let error = get_smms_upload_history(t.d1_client(), t.smms_client(), None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "auth_required");
+}
+
+#[tokio::test]
+async fn exhausted_retries_stay_retryable() {
+    let t = TestApp::new().await;
+    t.login().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v2/upload_history"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&t.server)
+        .await;
+
+    let error = get_smms_upload_history(t.d1_client(), t.smms_client(), None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.category(), "network");
+    assert!(error.retryable());
+    assert_eq!(error.details()["status"], json!(503));
+    assert!(matches!(error, AppError::Network { .. }));
+}
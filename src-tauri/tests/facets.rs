@@ -0,0 +1,137 @@
+//! 一次查询返回当前页、匹配总数和分面计数，分面忽略自身的筛选条件
+mod common;
+
+use common::TestApp;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{FacetCount, PictureFacets, PictureQueryParams};
+
+/// (文件名, 类型, 收藏, 已删除)
+const PICTURES: &[(&str, &str, i64, i64)] = &[
+    ("a.png", "png", 1, 0),
+    ("b.png", "png", 0, 0),
+    ("c.png", "png", 0, 1),
+    ("d.jpg", "jpg", 1, 0),
+    ("e.jpg", "jpg", 0, 0),
+    ("f.gif", "gif", 1, 1),
+];
+
+async fn library() -> TestApp {
+    let t = TestApp::new().await;
+    for (index, (filename, file_type, favorite, deleted)) in PICTURES.iter().enumerate() {
+        t.sql(&format!(
+            "INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, is_favorite, is_deleted, created_at)
+             VALUES ('h{index}', '{filename}', 's.png', '{file_type}', 10, 10, 1, '/p', 'https://u/{index}', 'https://d', 'https://p', {favorite}, {deleted}, '2024-01-0{} 00:00:00')",
+            index + 1
+        ));
+    }
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    t
+}
+
+fn facet(value: &str, count: i64) -> FacetCount {
+    FacetCount {
+        value: value.into(),
+        count,
+    }
+}
+
+#[tokio::test]
+async fn facets_ignore_their_own_filter() {
+    let t = library().await;
+    let params = PictureQueryParams {
+        file_types: Some(vec!["png".into()]),
+        is_favorite: Some(false),
+        include_deleted: Some(false),
+        ..Default::default()
+    };
+    let result = query_pictures(t.d1_client(), t.mirror(), params)
+        .await
+        .unwrap();
+
+    let names: Vec<&str> = result.items.iter().map(|p| p.filename.as_str()).collect();
+    assert_eq!(names, vec!["b.png"]);
+    assert_eq!(result.total, 1);
+    assert_eq!(
+        result.facets,
+        PictureFacets {
+            // 未收藏、未删除的各类型
+            file_types: vec![facet("jpg", 1), facet("png", 1)],
+            // 未删除的 png
+            favorites: 1,
+            not_favorites: 1,
+            // 未收藏的 png
+            active: 1,
+            deleted: 1,
+        }
+    );
+}
+
+#[tokio::test]
+async fn matches_separate_list_and_count_queries() {
+    let t = library().await;
+    let params = PictureQueryParams {
+        include_deleted: Some(false),
+        order_by: Some("created_at_asc".into()),
+        limit: Some(2),
+        ..Default::default()
+    };
+    let first = query_pictures(t.d1_client(), t.mirror(), params.clone())
+        .await
+        .unwrap();
+    let count = get_pictures_count(t.d1_client(), t.mirror(), params.clone())
+        .await
+        .unwrap();
+    assert_eq!(first.total, count);
+    assert_eq!(first.total, 4);
+    assert_eq!(
+        first.facets.file_types,
+        vec![facet("jpg", 2), facet("png", 2)]
+    );
+
+    // 游标翻页时总数和分面保持不变
+    let second = query_pictures(
+        t.d1_client(),
+        t.mirror(),
+        PictureQueryParams {
+            cursor: first.next_cursor.clone(),
+            ..params.clone()
+        },
+    )
+    .await
+    .unwrap();
+    let page = query_pictures_page(
+        t.d1_client(),
+        t.mirror(),
+        PictureQueryParams {
+            cursor: first.next_cursor,
+            ..params
+        },
+    )
+    .await
+    .unwrap();
+    let ids = |items: &[sm_flare_lib::models::SmmsPicture]| {
+        items.iter().map(|p| p.id).collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&second.items), ids(&page.items));
+    assert_eq!(ids(&second.items), vec![4, 5]);
+    assert_eq!(second.total, 4);
+    assert_eq!(second.facets, first.facets);
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    let t = library().await;
+    let error = query_pictures(
+        t.d1_client(),
+        t.mirror(),
+        PictureQueryParams {
+            updated_to: Some("yesterday".into()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+}
@@ -0,0 +1,196 @@
+//! 图片筛选：时间、大小、尺寸、宽高比、方向、多文件类型，以及按尺寸和文件名排序
+mod common;
+
+use common::TestApp;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{Orientation, PictureQueryParams};
+
+/// (文件名, 类型, 宽, 高, 大小, 创建时间)
+const PICTURES: &[(&str, &str, i64, i64, i64, &str)] = &[
+    (
+        "beach.png",
+        "png",
+        1920,
+        1080,
+        3_000_000,
+        "2024-08-15 10:00:00",
+    ),
+    (
+        "Avatar.png",
+        "png",
+        512,
+        512,
+        200_000,
+        "2024-09-30 23:59:59",
+    ),
+    (
+        "phone.jpg",
+        "jpg",
+        1080,
+        1920,
+        2_500_000,
+        "2024-07-01 00:00:00",
+    ),
+    (
+        "banner.gif",
+        "gif",
+        3000,
+        1000,
+        2_200_000,
+        "2024-10-01 00:00:00",
+    ),
+    (
+        "small.png",
+        "png",
+        800,
+        600,
+        1_000_000,
+        "2024-08-01 12:00:00",
+    ),
+];
+
+async fn library() -> TestApp {
+    let t = TestApp::new().await;
+    for (index, (filename, file_type, width, height, size, created_at)) in
+        PICTURES.iter().enumerate()
+    {
+        t.sql(&format!(
+            "INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, created_at)
+             VALUES ('h{index}', '{filename}', 's.png', '{file_type}', {width}, {height}, {size}, '/p', 'https://u/{index}', 'https://d', 'https://p', '{created_at}')"
+        ));
+    }
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    t
+}
+
+async fn names(t: &TestApp, params: PictureQueryParams) -> Vec<String> {
+    query_smms_pictures(t.d1_client(), t.mirror(), params)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| p.filename)
+        .collect()
+}
+
+#[tokio::test]
+async fn landscape_pngs_over_2mb_from_last_quarter() {
+    let t = library().await;
+    let params = PictureQueryParams {
+        file_types: Some(vec!["png".into()]),
+        orientation: Some(Orientation::Landscape),
+        min_size: Some(2 * 1024 * 1024),
+        created_from: Some("2024-07-01".into()),
+        created_to: Some("2024-09-30".into()),
+        ..Default::default()
+    };
+    assert_eq!(names(&t, params).await, vec!["beach.png"]);
+
+    let count = get_pictures_count(
+        t.d1_client(),
+        t.mirror(),
+        PictureQueryParams {
+            file_types: Some(vec!["png".into(), "gif".into()]),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(count, 4);
+}
+
+#[tokio::test]
+async fn date_upper_bound_includes_the_whole_day() {
+    let t = library().await;
+    let by_day = PictureQueryParams {
+        created_from: Some("2024-09-30".into()),
+        created_to: Some("2024-09-30".into()),
+        ..Default::default()
+    };
+    assert_eq!(names(&t, by_day).await, vec!["Avatar.png"]);
+
+    let by_time = PictureQueryParams {
+        created_to: Some("2024-08-01T12:00:00".into()),
+        order_by: Some("created_at_asc".into()),
+        ..Default::default()
+    };
+    assert_eq!(names(&t, by_time).await, vec!["phone.jpg", "small.png"]);
+
+    let invalid = PictureQueryParams {
+        created_from: Some("last quarter".into()),
+        ..Default::default()
+    };
+    let error = query_smms_pictures(t.d1_client(), t.mirror(), invalid)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+}
+
+#[tokio::test]
+async fn dimensions_aspect_ratio_and_orientation() {
+    let t = library().await;
+
+    let portrait = PictureQueryParams {
+        orientation: Some(Orientation::Portrait),
+        ..Default::default()
+    };
+    assert_eq!(names(&t, portrait).await, vec!["phone.jpg"]);
+    let square = PictureQueryParams {
+        orientation: Some(Orientation::Square),
+        ..Default::default()
+    };
+    assert_eq!(names(&t, square).await, vec!["Avatar.png"]);
+
+    // 宽屏（16:9 及更宽）
+    let wide = PictureQueryParams {
+        min_aspect_ratio: Some(16.0 / 9.0),
+        order_by: Some("width_desc".into()),
+        ..Default::default()
+    };
+    assert_eq!(names(&t, wide).await, vec!["banner.gif", "beach.png"]);
+
+    let medium = PictureQueryParams {
+        min_width: Some(800),
+        max_width: Some(1920),
+        max_height: Some(1080),
+        order_by: Some("height_asc".into()),
+        ..Default::default()
+    };
+    assert_eq!(names(&t, medium).await, vec!["small.png", "beach.png"]);
+}
+
+#[tokio::test]
+async fn sort_by_filename_ignores_case() {
+    let t = library().await;
+    let params = PictureQueryParams {
+        order_by: Some("filename_asc".into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        names(&t, params).await,
+        vec![
+            "Avatar.png",
+            "banner.gif",
+            "beach.png",
+            "phone.jpg",
+            "small.png"
+        ]
+    );
+
+    // 游标分页同样按文件名定位
+    let mut page = PictureQueryParams {
+        order_by: Some("filename_desc".into()),
+        limit: Some(2),
+        ..Default::default()
+    };
+    let first = query_pictures_page(t.d1_client(), t.mirror(), page.clone())
+        .await
+        .unwrap();
+    page.cursor = first.next_cursor;
+    let second = query_pictures_page(t.d1_client(), t.mirror(), page)
+        .await
+        .unwrap();
+    let names: Vec<String> = second.items.into_iter().map(|p| p.filename).collect();
+    assert_eq!(names, vec!["beach.png", "banner.gif"]);
+}
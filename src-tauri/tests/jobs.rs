@@ -0,0 +1,271 @@
+//! 长任务的登记与取消：取消后在安全点停止并返回已完成的部分
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{smms_item, TestApp, TOKEN};
+use serde_json::json;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{DownloadFileInfo, JobProgress, JobStage};
+use sm_flare_lib::services::download;
+use sm_flare_lib::services::jobs::{CancelToken, JobRegistry};
+use sm_flare_lib::services::progress::Progress;
+use sm_flare_lib::services::upload;
+use wiremock::matchers::{method, path, path_regex, query_param};
+use wiremock::{Mock, ResponseTemplate};
+
+/// 收集进度事件，第 `index` 个文件到达 `stage` 时取消任务
+fn cancel_at(
+    file_count: usize,
+    index: usize,
+    stage: JobStage,
+) -> (Progress, Arc<Mutex<Vec<JobProgress>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    let token = CancelToken::new();
+    let trigger = token.clone();
+    let progress = Progress::new("job-1", file_count, move |event| {
+        if event.file_index == index && event.stage == stage {
+            trigger.cancel();
+        }
+        sink.lock().unwrap().push(event)
+    })
+    .with_cancel_token(token);
+    (progress, events)
+}
+
+#[tokio::test]
+async fn jobs_are_registered_while_running() {
+    let jobs = JobRegistry::new();
+    let job = jobs.start("upload".into()).unwrap();
+    let error = jobs.start("upload".into()).err().unwrap();
+    assert_eq!(error.code(), "invalid_input");
+
+    jobs.cancel("upload").unwrap();
+    assert!(job.is_cancelled());
+    drop(job);
+
+    // 任务结束后注销，id 可以再次使用
+    let error = jobs.cancel("upload").unwrap_err();
+    assert_eq!(error.code(), "not_found");
+    assert!(!jobs.start("upload".into()).unwrap().is_cancelled());
+
+    // 调用方拿不到的 id 无法取消，任务 id 必须由调用方提供
+    let error = jobs.start(String::new()).err().unwrap();
+    assert_eq!(error.code(), "invalid_input");
+}
+
+#[tokio::test]
+async fn cancelled_upload_keeps_files_already_uploaded() {
+    let t = TestApp::new().await;
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<String> = (0..3)
+        .map(|i| {
+            let file = dir.path().join(format!("shot{i}.png"));
+            std::fs::write(&file, b"png-bytes").unwrap();
+            file.to_string_lossy().into_owned()
+        })
+        .collect();
+    let (progress, events) = cancel_at(paths.len(), 0, JobStage::Saving);
+
+    let results = upload::upload_files(
+        &t.d1_client(),
+        &t.smms_client(),
+        TOKEN,
+        &paths,
+        None,
+        1,
+        None,
+        &progress,
+    )
+    .await;
+
+    assert!(results[0].success && !results[0].cancelled);
+    assert!(results[1..].iter().all(|r| !r.success && r.cancelled));
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures"), 1);
+    let events = events.lock().unwrap();
+    let last = |index: usize| {
+        events
+            .iter()
+            .rfind(|e| e.file_index == index)
+            .unwrap()
+            .stage
+    };
+    assert_eq!(last(0), JobStage::Done);
+    assert_eq!(last(2), JobStage::Cancelled);
+}
+
+#[tokio::test]
+async fn cancel_stops_deduplicated_uploads_before_reading_the_rest() {
+    let t = TestApp::new().await;
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<String> = (0..3)
+        .map(|i| {
+            let file = dir.path().join(format!("shot{i}.png"));
+            std::fs::write(&file, format!("png-bytes-{i}")).unwrap();
+            file.to_string_lossy().into_owned()
+        })
+        .collect();
+    let (progress, events) = cancel_at(paths.len(), 0, JobStage::Reading);
+
+    let results = upload::upload_files(
+        &t.d1_client(),
+        &t.smms_client(),
+        TOKEN,
+        &paths,
+        None,
+        1,
+        Some(&t.mirror()),
+        &progress,
+    )
+    .await;
+
+    assert!(results.iter().all(|r| !r.success));
+    assert!(results[1..].iter().all(|r| r.cancelled));
+    // 每个文件只读取一次，取消后没有再读取后面的文件
+    let events = events.lock().unwrap();
+    let reading: Vec<usize> = events
+        .iter()
+        .filter(|e| e.stage == JobStage::Reading)
+        .map(|e| e.file_index)
+        .collect();
+    assert_eq!(reading, vec![0]);
+}
+
+#[tokio::test]
+async fn cancelled_zip_keeps_the_files_already_packed() {
+    let t = TestApp::new().await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/files/"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"image".to_vec()))
+        .mount(&t.server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let files = || {
+        ["a.png", "b.png", "c.png"]
+            .iter()
+            .map(|name| DownloadFileInfo {
+                url: format!("{}/files/{}", t.server.uri(), name),
+                filename: name.to_string(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let target = dir.path().join("partial.zip");
+    let (progress, _) = cancel_at(3, 0, JobStage::Done);
+    let summary = download::download_zip(files(), &target.to_string_lossy(), &progress)
+        .await
+        .unwrap();
+    assert_eq!((summary.succeeded, summary.cancelled), (1, 2));
+    let archive = zip::ZipArchive::new(std::fs::File::open(&target).unwrap()).unwrap();
+    assert_eq!(archive.file_names().collect::<Vec<_>>(), vec!["a.png"]);
+
+    // 第一个文件下载中取消：没有打包任何文件，删除 ZIP
+    let target = dir.path().join("empty.zip");
+    let (progress, events) = cancel_at(3, 0, JobStage::Downloading);
+    let error = download::download_zip(files(), &target.to_string_lossy(), &progress)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "cancelled");
+    assert!(!target.exists());
+    let cancelled = events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|e| e.stage == JobStage::Cancelled)
+        .count();
+    assert_eq!(cancelled, 3);
+}
+
+#[tokio::test]
+async fn cancelled_import_keeps_imported_pages_and_skips_cleanup() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[smms_item("old", "old.png")]).await;
+    Mock::given(method("GET"))
+        .and(path("/api/v2/upload_history"))
+        .and(query_param("page", "1"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "success": true,
+                    "code": "success",
+                    "message": "Get list success.",
+                    "data": [smms_item("h1", "a.png")],
+                    "RequestId": "req"
+                }))
+                .set_delay(Duration::from_millis(300)),
+        )
+        .mount(&t.server)
+        .await;
+    t.mock_upload_history(vec![vec![], vec![smms_item("h2", "b.png")]])
+        .await;
+
+    let import = import_all_smms_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        "import".into(),
+    );
+    let cancel = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cancel_job(t.jobs(), "import".into()).await.unwrap();
+    };
+    let (stats, _) = tokio::join!(import, cancel);
+    let stats = stats.unwrap();
+
+    assert!(stats.cancelled);
+    assert_eq!((stats.added, stats.deleted), (1, 0));
+    let old: i64 = t.scalar("SELECT is_deleted FROM smms_pictures WHERE file_hash = 'old'");
+    assert_eq!(old, 0, "取消时不标记未返回的图片");
+    let error = cancel_job(t.jobs(), "import".into()).await.unwrap_err();
+    assert_eq!(error.code(), "not_found");
+}
+
+#[tokio::test]
+async fn cancelled_batch_delete_stops_after_the_current_picture() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[
+        smms_item("h1", "a.png"),
+        smms_item("h2", "b.png"),
+        smms_item("h3", "c.png"),
+    ])
+    .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/api/v2/delete/[^/]+$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "success": true,
+                    "code": "success",
+                    "message": "File delete success.",
+                    "RequestId": "req"
+                }))
+                .set_delay(Duration::from_millis(300)),
+        )
+        .with_priority(1)
+        .mount(&t.server)
+        .await;
+
+    let delete = batch_delete_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        vec![1, 2, 3],
+        "delete".into(),
+    );
+    let cancel = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cancel_job(t.jobs(), "delete".into()).await.unwrap();
+    };
+    let (result, _) = tokio::join!(delete, cancel);
+    let result = result.unwrap();
+
+    assert_eq!((result.success_count, result.cancelled_count), (1, 2));
+    let deleted: i64 = t.scalar("SELECT COUNT(*) FROM smms_pictures WHERE is_deleted = 1");
+    assert_eq!(deleted, 1);
+}
@@ -0,0 +1,165 @@
+//! 结构迁移：版本记录、旧表兼容与校验和检查
+mod common;
+
+use common::TestApp;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::SchemaVersion;
+use sm_flare_lib::services::migrations::{self, latest_version};
+
+fn columns(t: &TestApp, table: &str) -> Vec<String> {
+    let conn = t.d1.db.lock().unwrap();
+    let mut stmt = conn
+        .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))
+        .unwrap();
+    stmt.query_map([], |row| row.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+#[tokio::test]
+async fn fresh_database_is_migrated_to_latest() {
+    let t = TestApp::unmigrated().await;
+    let before = get_schema_version(t.d1_client()).await.unwrap();
+    assert_eq!(before.current, 0);
+
+    let after = migrations::migrate(&t.d1_client()).await.unwrap();
+    assert_eq!(
+        after,
+        SchemaVersion {
+            current: latest_version(),
+            latest: latest_version(),
+        }
+    );
+    let recorded: i64 = t.scalar("SELECT COUNT(*) FROM schema_migrations");
+    assert_eq!(recorded, latest_version() as i64);
+    assert!(columns(&t, "smms_pictures").contains(&"remark".to_string()));
+
+    // 再次执行不会重复应用
+    migrations::migrate(&t.d1_client()).await.unwrap();
+    let recorded: i64 = t.scalar("SELECT COUNT(*) FROM schema_migrations");
+    assert_eq!(recorded, latest_version() as i64);
+}
+
+#[tokio::test]
+async fn legacy_table_gains_missing_columns_only() {
+    let t = TestApp::unmigrated().await;
+    // 早期版本创建的表：已有 remark，缺少软删除字段
+    t.sql(
+        "CREATE TABLE smms_pictures (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            file_hash TEXT NOT NULL UNIQUE,
+            filename TEXT NOT NULL,
+            store_name TEXT NOT NULL,
+            file_type TEXT NOT NULL,
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            path TEXT NOT NULL,
+            url TEXT NOT NULL,
+            delete_url TEXT NOT NULL,
+            page_url TEXT NOT NULL,
+            is_favorite INTEGER DEFAULT 0,
+            remark TEXT,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME DEFAULT (datetime('now'))
+        )",
+    );
+
+    migrations::migrate(&t.d1_client()).await.unwrap();
+
+    let columns = columns(&t, "smms_pictures");
+    for column in ["is_deleted", "deleted_at", "remark"] {
+        assert_eq!(
+            columns.iter().filter(|c| *c == column).count(),
+            1,
+            "{column}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn save_d1_config_applies_migrations() {
+    let t = TestApp::unmigrated().await;
+
+    save_d1_config(t.config_store(), t.d1_client(), common::d1_config())
+        .await
+        .unwrap();
+
+    let version = get_schema_version(t.d1_client()).await.unwrap();
+    assert_eq!(version.current, latest_version());
+}
+
+#[tokio::test]
+async fn modified_migration_is_rejected() {
+    let t = TestApp::new().await;
+    t.sql("UPDATE schema_migrations SET checksum = 'tampered' WHERE version = 2");
+
+    let error = migrations::migrate(&t.d1_client())
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("create_smms_pictures"), "{error}");
+}
+
+#[tokio::test]
+async fn newer_database_version_is_rejected() {
+    let t = TestApp::new().await;
+    t.sql("INSERT INTO schema_migrations (version, name, checksum) VALUES (9999, 'future', 'x')");
+
+    let error = migrations::migrate(&t.d1_client())
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("9999"), "{error}");
+}
+
+#[tokio::test]
+async fn reads_cost_one_pull_once_schema_is_ready() {
+    let t = TestApp::new().await;
+    assert!(t.d1_client().is_schema_ready());
+
+    // 第一次读取拉取镜像，之后的读取由本地提供
+    let before = t.d1_requests().await;
+    query_smms_pictures(t.d1_client(), t.mirror(), Default::default())
+        .await
+        .unwrap();
+    get_pictures_count(t.d1_client(), t.mirror(), Default::default())
+        .await
+        .unwrap();
+    assert_eq!(t.d1_requests().await - before, 1);
+}
+
+#[tokio::test]
+async fn readiness_is_checked_again_after_config_change() {
+    let t = TestApp::new().await;
+    t.d1_client().reload(common::d1_config()).unwrap();
+    assert!(!t.d1_client().is_schema_ready());
+
+    // 第一次拉取重新检查结构，之后的拉取不再检查
+    let before = t.d1_requests().await;
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    assert!(t.d1_client().is_schema_ready());
+    let checked = t.d1_requests().await;
+    assert!(checked - before > 1);
+
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    assert_eq!(t.d1_requests().await - checked, 1);
+}
+
+#[tokio::test]
+async fn unmigrated_database_is_prepared_on_first_read() {
+    let t = TestApp::unmigrated().await;
+    assert!(!t.d1_client().is_schema_ready());
+
+    let count = get_pictures_count(t.d1_client(), t.mirror(), Default::default())
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+    let version = get_schema_version(t.d1_client()).await.unwrap();
+    assert_eq!(version.current, latest_version());
+}
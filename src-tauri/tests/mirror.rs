@@ -0,0 +1,144 @@
+//! 本地镜像：离线读取、写入同步与增量拉取
+mod common;
+
+use common::{smms_item, TestApp};
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::PictureQueryParams;
+
+fn all() -> PictureQueryParams {
+    PictureQueryParams {
+        include_deleted: Some(false),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn reads_are_served_locally_when_d1_is_unreachable() {
+    let t = TestApp::new().await;
+    t.seed_pictures(&[smms_item("h1", "a.png"), smms_item("h2", "b.png")])
+        .await;
+    query_smms_pictures(t.d1_client(), t.mirror(), all())
+        .await
+        .unwrap();
+
+    t.server.reset().await;
+
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), all())
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 2);
+    let count = get_pictures_count(t.d1_client(), t.mirror(), all())
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(
+        get_all_file_types(t.d1_client(), t.mirror()).await.unwrap(),
+        vec!["png".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn status_reports_last_sync() {
+    let t = TestApp::new().await;
+    let status = get_mirror_status(t.mirror()).await.unwrap();
+    assert_eq!(status.source, None);
+    assert_eq!(status.last_synced_at, None);
+
+    t.seed_pictures(&[smms_item("h1", "a.png")]).await;
+    let status = sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    assert_eq!(status.source.as_deref(), Some("account/database"));
+    assert!(status.last_synced_at.is_some());
+    assert_eq!(status.pictures, 1);
+}
+
+#[tokio::test]
+async fn updates_are_written_through_without_pulling() {
+    let t = TestApp::new().await;
+    t.seed_pictures(&[smms_item("h1", "a.png")]).await;
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+
+    let before = t.d1_requests().await;
+    toggle_picture_favorite(t.d1_client(), t.mirror(), t.smms_client(), 1, true)
+        .await
+        .unwrap();
+    update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        1,
+        Some("note".into()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(t.d1_requests().await - before, 2);
+
+    let mut favorites = all();
+    favorites.is_favorite = Some(true);
+    favorites.remark = Some("note".into());
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), favorites)
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 1);
+    assert_eq!(t.d1_requests().await - before, 2);
+}
+
+#[tokio::test]
+async fn pull_fetches_only_rows_changed_since_last_sync() {
+    let t = TestApp::new().await;
+    t.seed_pictures(&[
+        smms_item("h1", "a.png"),
+        smms_item("h2", "b.png"),
+        smms_item("h3", "c.png"),
+    ])
+    .await;
+    t.sql("UPDATE smms_pictures SET updated_at = '2024-01-01 00:00:0' || id");
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+
+    // 其他设备修改了一张图片
+    t.sql(
+        "UPDATE smms_pictures SET remark = 'remote', updated_at = '2024-02-01 00:00:00' WHERE id = 2",
+    );
+    reset_d1_usage(t.d1_client()).await.unwrap();
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    // 只读取修改过的行，以及上次进度所在那一秒的行（同一秒内可能还有其他修改）
+    assert_eq!(get_d1_usage(t.d1_client()).await.unwrap().rows_read, 2);
+
+    let mut remote = all();
+    remote.remark = Some("remote".into());
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), remote)
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 1);
+    assert_eq!(pictures[0].id, 2);
+}
+
+#[tokio::test]
+async fn new_pictures_reach_the_mirror_after_sync() {
+    let t = TestApp::new().await;
+    t.login().await;
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    t.mock_upload_history(vec![vec![
+        smms_item("h1", "a.png"),
+        smms_item("h2", "b.png"),
+    ]])
+    .await;
+
+    sync_smms_pictures(t.d1_client(), t.mirror(), t.smms_client(), Some(1))
+        .await
+        .unwrap();
+
+    let count = get_pictures_count(t.d1_client(), t.mirror(), all())
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+}
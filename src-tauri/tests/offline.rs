@@ -0,0 +1,261 @@
+//! 离线队列：断网时排队、恢复后按顺序重放、冲突检测与放弃
+mod common;
+
+use common::{smms_item, TestApp};
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::PictureQueryParams;
+use sm_flare_lib::services::offline::{OperationStatus, PictureEdit};
+
+/// 同步镜像后的测试应用，包含两张图片
+async fn synced_app() -> TestApp {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[smms_item("h1", "a.png"), smms_item("h2", "b.png")])
+        .await;
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    t
+}
+
+fn by_remark(remark: &str) -> PictureQueryParams {
+    PictureQueryParams {
+        remark: Some(remark.into()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn edits_are_queued_offline_and_replayed_in_order() {
+    let t = synced_app().await;
+
+    t.d1.set_offline(true);
+    let message = toggle_picture_favorite(t.d1_client(), t.mirror(), t.smms_client(), 1, true)
+        .await
+        .unwrap();
+    assert!(message.contains("离线队列"), "{message}");
+    for remark in ["first", "second"] {
+        update_picture_remark(
+            t.d1_client(),
+            t.mirror(),
+            t.smms_client(),
+            1,
+            Some(remark.into()),
+        )
+        .await
+        .unwrap();
+    }
+
+    // 排队的修改立即反映在本地
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), by_remark("second"))
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 1);
+    assert_eq!(pictures[0].is_favorite, 1);
+
+    let pending = list_pending_operations(t.mirror()).await.unwrap();
+    assert_eq!(pending.len(), 3);
+    assert!(pending.windows(2).all(|w| w[0].seq < w[1].seq));
+    assert_eq!(
+        pending[0].edit,
+        PictureEdit::Favorite {
+            id: 1,
+            is_favorite: true
+        }
+    );
+
+    // 网络仍不可用时保留在队列中
+    let report = replay_pending_operations(t.d1_client(), t.mirror(), t.smms_client())
+        .await
+        .unwrap();
+    assert_eq!(report.applied, 0);
+    assert_eq!(report.remaining, 3);
+
+    t.d1.set_offline(false);
+    let report = replay_pending_operations(t.d1_client(), t.mirror(), t.smms_client())
+        .await
+        .unwrap();
+    // 同一图片上的连续修改不算冲突
+    assert_eq!(
+        (report.applied, report.conflicts, report.remaining),
+        (3, 0, 0)
+    );
+    let remark: String = t.scalar("SELECT remark FROM smms_pictures WHERE id = 1");
+    assert_eq!(remark, "second");
+    let favorite: i64 = t.scalar("SELECT is_favorite FROM smms_pictures WHERE id = 1");
+    assert_eq!(favorite, 1);
+    assert!(list_pending_operations(t.mirror())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn remote_change_is_reported_as_conflict() {
+    let t = synced_app().await;
+
+    t.d1.set_offline(true);
+    update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        1,
+        Some("local".into()),
+    )
+    .await
+    .unwrap();
+    t.d1.set_offline(false);
+
+    // 另一台设备在此期间修改了同一张图片
+    t.sql("UPDATE smms_pictures SET remark = 'remote', updated_at = '2030-01-01 00:00:00' WHERE id = 1");
+
+    let report = replay_pending_operations(t.d1_client(), t.mirror(), t.smms_client())
+        .await
+        .unwrap();
+    assert_eq!((report.applied, report.conflicts), (0, 1));
+    let remark: String = t.scalar("SELECT remark FROM smms_pictures WHERE id = 1");
+    assert_eq!(remark, "remote");
+
+    let pending = list_pending_operations(t.mirror()).await.unwrap();
+    assert_eq!(pending[0].status, OperationStatus::Conflict);
+    assert!(pending[0].error.as_deref().unwrap().contains("2030-01-01"));
+
+    // 冲突的操作不阻塞后续修改
+    toggle_picture_favorite(t.d1_client(), t.mirror(), t.smms_client(), 2, true)
+        .await
+        .unwrap();
+    let favorite: i64 = t.scalar("SELECT is_favorite FROM smms_pictures WHERE id = 2");
+    assert_eq!(favorite, 1);
+
+    assert_eq!(
+        discard_pending_operations(t.mirror(), None).await.unwrap(),
+        1
+    );
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), by_remark("remote"))
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 1);
+}
+
+#[tokio::test]
+async fn discarding_restores_the_local_copy() {
+    let t = synced_app().await;
+    update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        1,
+        Some("saved".into()),
+    )
+    .await
+    .unwrap();
+
+    t.d1.set_offline(true);
+    update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        1,
+        Some("draft".into()),
+    )
+    .await
+    .unwrap();
+    toggle_picture_favorite(t.d1_client(), t.mirror(), t.smms_client(), 1, true)
+        .await
+        .unwrap();
+    let pending = list_pending_operations(t.mirror()).await.unwrap();
+
+    // 只放弃备注修改，收藏仍在队列中
+    discard_pending_operations(t.mirror(), Some(vec![pending[0].seq]))
+        .await
+        .unwrap();
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), by_remark("saved"))
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 1);
+    assert_eq!(pictures[0].is_favorite, 1);
+
+    t.d1.set_offline(false);
+    let report = replay_pending_operations(t.d1_client(), t.mirror(), t.smms_client())
+        .await
+        .unwrap();
+    assert_eq!(report.applied, 1);
+    let remark: String = t.scalar("SELECT remark FROM smms_pictures WHERE id = 1");
+    assert_eq!(remark, "saved");
+}
+
+#[tokio::test]
+async fn pull_keeps_queued_edits_visible() {
+    let t = synced_app().await;
+
+    t.d1.set_offline(true);
+    update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        1,
+        Some("queued".into()),
+    )
+    .await
+    .unwrap();
+    t.d1.set_offline(false);
+
+    sync_local_mirror(t.d1_client(), t.mirror(), Some(true))
+        .await
+        .unwrap();
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), by_remark("queued"))
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 1);
+
+    // 下一次在线修改前先重放队列
+    toggle_picture_favorite(t.d1_client(), t.mirror(), t.smms_client(), 1, true)
+        .await
+        .unwrap();
+    let remark: String = t.scalar("SELECT remark FROM smms_pictures WHERE id = 1");
+    assert_eq!(remark, "queued");
+    assert!(list_pending_operations(t.mirror())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn deletes_are_queued_and_replayed() {
+    let t = synced_app().await;
+
+    t.d1.set_offline(true);
+    let result = batch_delete_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        vec![1, 2],
+        "delete".into(),
+    )
+    .await
+    .unwrap();
+    assert_eq!((result.success_count, result.queued_count), (0, 2));
+    let visible = get_pictures_count(
+        t.d1_client(),
+        t.mirror(),
+        PictureQueryParams {
+            include_deleted: Some(false),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(visible, 0);
+
+    t.d1.set_offline(false);
+    let report = replay_pending_operations(t.d1_client(), t.mirror(), t.smms_client())
+        .await
+        .unwrap();
+    assert_eq!(report.applied, 2);
+    let deleted: i64 = t.scalar("SELECT COUNT(*) FROM smms_pictures WHERE is_deleted = 1");
+    assert_eq!(deleted, 2);
+}
@@ -0,0 +1,125 @@
+//! 游标分页：按排序列和 id 定位下一页，翻页期间的新增和删除不会导致重复或遗漏
+mod common;
+
+use std::collections::HashSet;
+
+use common::TestApp;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{PicturePage, PictureQueryParams};
+
+/// 25 张图片，创建时间全部相同、大小只有 7 种，排序列上有大量相同值
+async fn library() -> TestApp {
+    let t = TestApp::new().await;
+    t.seed_generated_pictures(25, "https://u/").await;
+    t.sql("UPDATE smms_pictures SET size = id % 7, created_at = '2024-01-01 00:00:00'");
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    t
+}
+
+fn params(order_by: &str, cursor: Option<String>) -> PictureQueryParams {
+    PictureQueryParams {
+        order_by: Some(order_by.into()),
+        limit: Some(10),
+        cursor,
+        ..Default::default()
+    }
+}
+
+async fn page(t: &TestApp, order_by: &str, cursor: Option<String>) -> PicturePage {
+    query_pictures_page(t.d1_client(), t.mirror(), params(order_by, cursor))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn cursor_walks_every_row_once_in_order() {
+    let t = library().await;
+
+    for order_by in ["created_at_desc", "size_asc", "size_desc"] {
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let page = page(&t, order_by, cursor).await;
+            seen.extend(page.items);
+            pages += 1;
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 3, "{order_by}");
+        let ids: HashSet<i64> = seen.iter().map(|p| p.id).collect();
+        assert_eq!(ids.len(), 25, "{order_by}");
+
+        // 与一次性按偏移读取的顺序一致
+        let all = query_smms_pictures(
+            t.d1_client(),
+            t.mirror(),
+            PictureQueryParams {
+                order_by: Some(order_by.into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let expected: Vec<i64> = all.iter().map(|p| p.id).collect();
+        let actual: Vec<i64> = seen.iter().map(|p| p.id).collect();
+        assert_eq!(actual, expected, "{order_by}");
+    }
+}
+
+#[tokio::test]
+async fn changes_during_scroll_do_not_shift_items() {
+    let t = library().await;
+    let first = page(&t, "created_at_desc", None).await;
+
+    // 翻页期间上传了新图片，并删除了第一页中的一张
+    t.sql(
+        "INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, created_at)
+         VALUES ('new', 'new.png', 's.png', 'png', 10, 10, 1, '/p', 'https://u/new', 'https://d', 'https://p', '2024-06-01 00:00:00')",
+    );
+    t.sql(&format!(
+        "DELETE FROM smms_pictures WHERE id = {}",
+        first.items[0].id
+    ));
+    sync_local_mirror(t.d1_client(), t.mirror(), Some(true))
+        .await
+        .unwrap();
+
+    let second = page(&t, "created_at_desc", first.next_cursor.clone()).await;
+    let expected: Vec<i64> = (6..=15).rev().collect();
+    assert_eq!(
+        first.items.iter().map(|p| p.id).collect::<Vec<_>>(),
+        (16..=25).rev().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        second.items.iter().map(|p| p.id).collect::<Vec<_>>(),
+        expected
+    );
+}
+
+#[tokio::test]
+async fn invalid_cursors_are_rejected() {
+    let t = library().await;
+    let first = page(&t, "size_desc", None).await;
+
+    for (order_by, cursor) in [
+        ("size_asc", first.next_cursor.clone().unwrap()),
+        ("size_desc", "not-a-cursor".to_string()),
+    ] {
+        let error = query_pictures_page(t.d1_client(), t.mirror(), params(order_by, Some(cursor)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "invalid_input");
+    }
+
+    let mut both = params("size_desc", first.next_cursor);
+    both.offset = Some(10);
+    let error = query_pictures_page(t.d1_client(), t.mirror(), both)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+}
@@ -0,0 +1,194 @@
+//! 上传与下载的逐文件进度事件
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{TestApp, TOKEN};
+use sm_flare_lib::models::{DownloadFileInfo, JobProgress, JobStage};
+use sm_flare_lib::services::download;
+use sm_flare_lib::services::progress::Progress;
+use sm_flare_lib::services::upload;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// 收集进度事件
+fn collector(file_count: usize) -> (Progress, Arc<Mutex<Vec<JobProgress>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    let progress = Progress::new("job-1", file_count, move |event| {
+        sink.lock().unwrap().push(event)
+    });
+    (progress, events)
+}
+
+/// 某个文件依次经历的阶段及字节数
+fn stages(events: &[JobProgress], index: usize) -> Vec<(JobStage, u64, Option<u64>)> {
+    events
+        .iter()
+        .filter(|e| e.file_index == index)
+        .map(|e| (e.stage, e.bytes, e.total))
+        .collect()
+}
+
+#[tokio::test]
+async fn upload_reports_each_stage_per_file() {
+    let t = TestApp::new().await;
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("shot.png");
+    std::fs::write(&file, b"png-bytes").unwrap();
+    let paths = vec![
+        file.to_string_lossy().into_owned(),
+        dir.path()
+            .join("missing.png")
+            .to_string_lossy()
+            .into_owned(),
+    ];
+    let (progress, events) = collector(paths.len());
+
+    upload::upload_files(
+        &t.d1_client(),
+        &t.smms_client(),
+        TOKEN,
+        &paths,
+        None,
+        2,
+        None,
+        &progress,
+    )
+    .await;
+
+    let events = events.lock().unwrap();
+    assert!(events
+        .iter()
+        .all(|e| e.job_id == "job-1" && e.file_count == 2));
+    assert_eq!(
+        stages(&events, 0),
+        vec![
+            (JobStage::Reading, 0, None),
+            (JobStage::Uploading, 0, Some(9)),
+            (JobStage::Uploading, 9, Some(9)),
+            (JobStage::Saving, 9, Some(9)),
+            (JobStage::Done, 9, Some(9)),
+        ]
+    );
+    assert_eq!(
+        stages(&events, 1),
+        vec![(JobStage::Reading, 0, None), (JobStage::Failed, 0, None)]
+    );
+    let failed = events.iter().find(|e| e.stage == JobStage::Failed).unwrap();
+    assert_eq!(failed.filename, "missing.png");
+    assert!(failed.message.as_deref().unwrap().contains("读取文件失败"));
+}
+
+#[tokio::test]
+async fn upload_reports_bytes_sent() {
+    let t = TestApp::new().await;
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("large.png");
+    let size = 600 * 1024;
+    std::fs::write(&file, vec![7u8; size]).unwrap();
+    let (progress, events) = collector(1);
+
+    let results = upload::upload_files(
+        &t.d1_client(),
+        &t.smms_client(),
+        TOKEN,
+        &[file.to_string_lossy().into_owned()],
+        None,
+        1,
+        None,
+        &progress,
+    )
+    .await;
+    assert!(results[0].success, "{}", results[0].message);
+
+    let events = events.lock().unwrap();
+    let sent: Vec<u64> = stages(&events, 0)
+        .iter()
+        .filter(|(stage, _, total)| *stage == JobStage::Uploading && *total == Some(size as u64))
+        .map(|(_, bytes, _)| *bytes)
+        .collect();
+    assert!(sent.len() >= 3, "上传过程中发送进度: {sent:?}");
+    assert!(sent.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(*sent.last().unwrap(), size as u64);
+}
+
+#[tokio::test]
+async fn zip_export_reports_download_bytes_and_zipping() {
+    let t = TestApp::new().await;
+    let large = vec![7u8; 600 * 1024];
+    Mock::given(method("GET"))
+        .and(path("/files/large.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(large.clone()))
+        .mount(&t.server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("out.zip");
+    let files = ["large.png", "missing.png"]
+        .iter()
+        .map(|name| DownloadFileInfo {
+            url: format!("{}/files/{}", t.server.uri(), name),
+            filename: name.to_string(),
+        })
+        .collect();
+    let (progress, events) = collector(2);
+
+    let summary = download::download_zip(files, &target.to_string_lossy(), &progress)
+        .await
+        .unwrap();
+    assert_eq!(summary.succeeded, 1);
+
+    let events = events.lock().unwrap();
+    let total = Some(large.len() as u64);
+    let large_stages = stages(&events, 0);
+    assert_eq!(large_stages[0], (JobStage::Downloading, 0, total));
+    let downloaded: Vec<u64> = large_stages
+        .iter()
+        .filter(|(stage, _, _)| *stage == JobStage::Downloading)
+        .map(|(_, bytes, _)| *bytes)
+        .collect();
+    assert!(downloaded.len() >= 3, "传输过程中发送进度: {downloaded:?}");
+    assert!(downloaded.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(*downloaded.last().unwrap(), large.len() as u64);
+    assert_eq!(
+        large_stages[large_stages.len() - 2..],
+        [
+            (JobStage::Zipping, large.len() as u64, total),
+            (JobStage::Done, large.len() as u64, total),
+        ]
+    );
+    assert_eq!(stages(&events, 1), vec![(JobStage::Failed, 0, None)]);
+}
+
+#[tokio::test]
+async fn single_download_reports_saving_and_done() {
+    let t = TestApp::new().await;
+    Mock::given(method("GET"))
+        .and(path("/files/a.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"image-a".to_vec()))
+        .mount(&t.server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("a.png");
+    let (progress, events) = collector(1);
+
+    download::download_file(
+        &format!("{}/files/a.png", t.server.uri()),
+        &target.to_string_lossy(),
+        &progress,
+    )
+    .await
+    .unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(
+        stages(&events, 0),
+        vec![
+            (JobStage::Downloading, 0, Some(7)),
+            (JobStage::Downloading, 7, Some(7)),
+            (JobStage::Saving, 7, Some(7)),
+            (JobStage::Done, 7, Some(7)),
+        ]
+    );
+    assert_eq!(events[0].filename, "a.png");
+}
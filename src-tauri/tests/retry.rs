@@ -0,0 +1,225 @@
+//! 重试策略：限流、网关错误与幂等性判断
+mod common;
+
+use common::{smms_item, TestApp};
+use serde_json::json;
+use sm_flare_lib::commands::*;
+use wiremock::matchers::{method, path, path_regex};
+use wiremock::{Mock, ResponseTemplate};
+
+const D1_PATH: &str = r"^/client/v4/accounts/[^/]+/d1/database/[^/]+/query$";
+
+/// 挂载一次性失败响应，优先于常规 mock 命中
+async fn fail_once(t: &TestApp, method_name: &str, route: &str, response: ResponseTemplate) {
+    Mock::given(method(method_name))
+        .and(path_regex(route))
+        .respond_with(response)
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&t.server)
+        .await;
+}
+
+fn rate_limited(retry_after: &str) -> ResponseTemplate {
+    ResponseTemplate::new(429).insert_header("Retry-After", retry_after)
+}
+
+#[tokio::test]
+async fn d1_query_waits_out_rate_limit() {
+    let t = TestApp::new().await;
+    fail_once(&t, "POST", D1_PATH, rate_limited("0")).await;
+
+    let output = execute_d1_query(t.d1_client(), "SELECT 1 AS one".into(), None)
+        .await
+        .unwrap();
+    assert_eq!(output.results, vec![json!({ "one": 1 })]);
+}
+
+#[tokio::test]
+async fn d1_gives_up_when_retry_after_exceeds_limit() {
+    let t = TestApp::new().await;
+    fail_once(&t, "POST", D1_PATH, rate_limited("120")).await;
+
+    let error = execute_d1_query(t.d1_client(), "SELECT 1".into(), None)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("429"), "{error}");
+}
+
+#[tokio::test]
+async fn d1_bad_gateway_retries_only_idempotent_statements() {
+    let t = TestApp::new().await;
+    t.sql("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)");
+
+    // 502 时服务端可能已经执行，普通 INSERT 不重放
+    fail_once(&t, "POST", D1_PATH, ResponseTemplate::new(502)).await;
+    let error = execute_d1_query(
+        t.d1_client(),
+        "INSERT INTO notes (body) VALUES ('a')".into(),
+        None,
+    )
+    .await
+    .unwrap_err()
+    .to_string();
+    assert!(error.contains("502"), "{error}");
+
+    fail_once(&t, "POST", D1_PATH, ResponseTemplate::new(502)).await;
+    execute_d1_query(
+        t.d1_client(),
+        "INSERT INTO notes (id, body) VALUES (1, 'b') ON CONFLICT(id) DO UPDATE SET body = excluded.body"
+            .into(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let count: i64 = t.scalar("SELECT COUNT(*) FROM notes");
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn upload_reports_attempts_and_skips_replay_after_bad_gateway() {
+    let t = TestApp::new().await;
+    t.login().await;
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("shot.png");
+    std::fs::write(&file, b"png-bytes").unwrap();
+    let paths = vec![file.to_string_lossy().into_owned()];
+
+    // 带 Retry-After 的 503 表示服务端未处理，上传也可以重试
+    fail_once(&t, "POST", "^/api/v2/upload$", {
+        ResponseTemplate::new(503).insert_header("Retry-After", "0")
+    })
+    .await;
+    let results = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        paths.clone(),
+        None,
+        None,
+        None,
+        "upload".into(),
+    )
+    .await
+    .unwrap();
+    assert!(results[0].success, "{}", results[0].message);
+    assert_eq!(results[0].attempts, 2);
+
+    // 502 时文件可能已上传成功，不能重复上传（跳过内容查重，确实发出上传请求）
+    fail_once(&t, "POST", "^/api/v2/upload$", ResponseTemplate::new(502)).await;
+    let results = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        paths,
+        None,
+        None,
+        Some(false),
+        "upload".into(),
+    )
+    .await
+    .unwrap();
+    assert!(!results[0].success);
+    assert_eq!(results[0].attempts, 1);
+}
+
+#[tokio::test]
+async fn upload_retries_only_explicit_rejections() {
+    let t = TestApp::new().await;
+    t.login().await;
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("shot.png");
+    std::fs::write(&file, b"png-bytes").unwrap();
+    let upload = |paths: Vec<String>| {
+        upload_images(
+            t.app.handle().clone(),
+            t.d1_client(),
+            t.mirror(),
+            t.smms_client(),
+            t.jobs(),
+            paths,
+            None,
+            None,
+            Some(false),
+            "upload".into(),
+        )
+    };
+    let paths = vec![file.to_string_lossy().into_owned()];
+
+    // 没有 Retry-After 的 503 可能来自已接收请求体的代理，上传不重试
+    fail_once(&t, "POST", "^/api/v2/upload$", ResponseTemplate::new(503)).await;
+    let results = upload(paths.clone()).await.unwrap();
+    assert!(!results[0].success);
+    assert_eq!(results[0].attempts, 1);
+
+    fail_once(&t, "POST", "^/api/v2/upload$", rate_limited("0")).await;
+    let results = upload(paths).await.unwrap();
+    assert!(results[0].success, "{}", results[0].message);
+    assert_eq!(results[0].attempts, 2);
+}
+
+#[tokio::test]
+async fn d1_retries_idempotent_statements_after_bare_503() {
+    let t = TestApp::new().await;
+    fail_once(&t, "POST", D1_PATH, ResponseTemplate::new(503)).await;
+
+    let output = execute_d1_query(t.d1_client(), "SELECT 1 AS one".into(), None)
+        .await
+        .unwrap();
+    assert_eq!(output.results, vec![json!({ "one": 1 })]);
+}
+
+#[tokio::test]
+async fn import_all_reports_history_retries() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.mock_upload_history(vec![vec![smms_item("h1", "a.png")]])
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v2/upload_history"))
+        .respond_with(ResponseTemplate::new(504))
+        .up_to_n_times(2)
+        .with_priority(1)
+        .mount(&t.server)
+        .await;
+
+    let stats = import_all_smms_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        "import".into(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(stats.added, 1);
+    assert_eq!(stats.retries, 2);
+}
+
+#[tokio::test]
+async fn batch_delete_reports_retries() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[smms_item("h1", "a.png"), smms_item("h2", "b.png")])
+        .await;
+    fail_once(&t, "GET", r"^/api/v2/delete/[^/]+$", rate_limited("0")).await;
+
+    let result = batch_delete_pictures(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        vec![1, 2],
+        "delete".into(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.success_count, 2);
+    assert_eq!(result.retries, 1);
+}
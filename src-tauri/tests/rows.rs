@@ -0,0 +1,93 @@
+//! D1 结果行解码：SQLite 的宽松类型与错误定位
+mod common;
+
+use common::{smms_item, TestApp};
+use serde::Deserialize;
+use serde_json::json;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::services::rows::{decode_row, decode_rows};
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Row {
+    id: i64,
+    enabled: bool,
+    ratio: f64,
+    label: String,
+    note: Option<String>,
+    count: Option<u32>,
+}
+
+#[test]
+fn decodes_sqlite_quirks() {
+    let rows = vec![
+        json!({ "id": 1, "enabled": 1, "ratio": 1.5, "label": "a", "note": null, "count": null }),
+        json!({ "id": "2", "enabled": "0", "ratio": "0.25", "label": 42, "note": "n", "count": "7" }),
+        json!({ "id": 3.0, "enabled": true, "ratio": 2, "label": "c", "note": null, "count": 3, "extra": "ignored" }),
+    ];
+
+    let decoded: Vec<Row> = decode_rows(&rows).unwrap();
+
+    assert_eq!(
+        decoded[1],
+        Row {
+            id: 2,
+            enabled: false,
+            ratio: 0.25,
+            label: "42".into(),
+            note: Some("n".into()),
+            count: Some(7),
+        }
+    );
+    assert!(decoded[0].enabled);
+    assert_eq!(decoded[0].note, None);
+    assert_eq!(decoded[2].id, 3);
+}
+
+#[test]
+fn errors_name_row_and_column() {
+    let rows = vec![
+        json!({ "id": 1, "enabled": 1, "ratio": 1, "label": "a", "note": null, "count": null }),
+        json!({ "id": "x1", "enabled": 1, "ratio": 1, "label": "b", "note": null, "count": null }),
+    ];
+    let error = decode_rows::<Row>(&rows).unwrap_err().to_string();
+    assert!(error.contains("记录 1"), "{error}");
+    assert!(error.contains("id"), "{error}");
+
+    let error = decode_row::<Row>(0, &json!({ "id": 1, "label": null }))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("label"), "{error}");
+
+    let error = decode_row::<Row>(4, &json!({ "id": 1 }))
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("记录 4") && error.contains("enabled"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn query_pictures_tolerates_text_numbers_and_reports_bad_column() {
+    let t = TestApp::new().await;
+    t.seed_pictures(&[smms_item("h1", "a.png"), smms_item("h2", "b.png")])
+        .await;
+    t.sql("UPDATE smms_pictures SET width = '1024', is_deleted = NULL WHERE id = 1");
+
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), Default::default())
+        .await
+        .unwrap();
+    let first = pictures.iter().find(|p| p.id == 1).unwrap();
+    assert_eq!(first.width, 1024);
+    assert_eq!(first.is_deleted, 0);
+
+    t.sql("UPDATE smms_pictures SET height = 'tall' WHERE id = 2");
+    sync_local_mirror(t.d1_client(), t.mirror(), Some(true))
+        .await
+        .unwrap();
+    let error = query_smms_pictures(t.d1_client(), t.mirror(), Default::default())
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("height"), "{error}");
+}
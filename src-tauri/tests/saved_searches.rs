@@ -0,0 +1,174 @@
+//! 保存的搜索：增删改查，按 id 运行时按当前数据实时筛选（智能相册）
+mod common;
+
+use common::TestApp;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{PictureQueryParams, SavedSearchUpdate};
+
+fn insert_picture(t: &TestApp, index: i64, file_type: &str, favorite: i64) {
+    t.sql(&format!(
+        "INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, is_favorite, created_at)
+         VALUES ('h{index}', 'p{index}.{file_type}', 's', '{file_type}', 10, 10, 1, '/p', 'https://u/{index}', 'https://d', 'https://p', {favorite}, '2024-01-01 00:00:0{index}')"
+    ));
+}
+
+fn favorite_pngs() -> PictureQueryParams {
+    PictureQueryParams {
+        file_types: Some(vec!["png".into()]),
+        is_favorite: Some(true),
+        order_by: Some("created_at_asc".into()),
+        // 分页参数不会被保存
+        limit: Some(1),
+        offset: Some(3),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn create_update_list_and_delete() {
+    let t = TestApp::new().await;
+
+    let saved = create_saved_search(t.d1_client(), " 收藏的 PNG ".into(), favorite_pngs(), None)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "收藏的 PNG");
+    assert!(!saved.smart_album);
+    assert_eq!(saved.params.is_favorite, Some(true));
+    assert_eq!(saved.params.limit, None);
+    assert_eq!(saved.params.offset, None);
+
+    // 重名、空名和无效条件都会被拒绝
+    for (name, params) in [
+        ("收藏的 PNG", PictureQueryParams::default()),
+        ("  ", PictureQueryParams::default()),
+        (
+            "invalid",
+            PictureQueryParams {
+                created_from: Some("someday".into()),
+                ..Default::default()
+            },
+        ),
+    ] {
+        let error = create_saved_search(t.d1_client(), name.into(), params, None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "invalid_input", "{name}");
+    }
+
+    let other = create_saved_search(
+        t.d1_client(),
+        "All GIFs".into(),
+        PictureQueryParams {
+            file_type: Some("gif".into()),
+            ..Default::default()
+        },
+        Some(true),
+    )
+    .await
+    .unwrap();
+
+    let updated = update_saved_search(
+        t.d1_client(),
+        saved.id,
+        SavedSearchUpdate {
+            smart_album: Some(true),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert!(updated.smart_album);
+    assert_eq!(updated.name, "收藏的 PNG");
+    assert_eq!(updated.params.file_types, Some(vec!["png".to_string()]));
+
+    let error = update_saved_search(
+        t.d1_client(),
+        other.id,
+        SavedSearchUpdate {
+            name: Some("收藏的 PNG".into()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+
+    let names: Vec<String> = list_saved_searches(t.d1_client(), None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    assert_eq!(names, vec!["All GIFs", "收藏的 PNG"]);
+
+    delete_saved_search(t.d1_client(), other.id).await.unwrap();
+    let error = delete_saved_search(t.d1_client(), other.id)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "not_found");
+    let error = run_saved_search(t.d1_client(), t.mirror(), other.id, None, None, None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "not_found");
+
+    let albums = list_saved_searches(t.d1_client(), Some(true))
+        .await
+        .unwrap();
+    assert_eq!(albums.len(), 1);
+    assert_eq!(albums[0].id, saved.id);
+}
+
+#[tokio::test]
+async fn smart_album_follows_picture_changes() {
+    let t = TestApp::new().await;
+    insert_picture(&t, 1, "png", 1);
+    insert_picture(&t, 2, "png", 0);
+    insert_picture(&t, 3, "jpg", 1);
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+
+    let album = create_saved_search(
+        t.d1_client(),
+        "favorites".into(),
+        favorite_pngs(),
+        Some(true),
+    )
+    .await
+    .unwrap();
+    let ids = |result: &sm_flare_lib::models::PictureQueryResult| {
+        result.items.iter().map(|p| p.id).collect::<Vec<_>>()
+    };
+
+    let result = run_saved_search(t.d1_client(), t.mirror(), album.id, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(ids(&result), vec![1]);
+    assert_eq!(result.total, 1);
+
+    // 新图片和收藏状态的变化立即反映到结果中
+    insert_picture(&t, 4, "png", 1);
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    toggle_picture_favorite(t.d1_client(), t.mirror(), t.smms_client(), 2, true)
+        .await
+        .unwrap();
+    let first = run_saved_search(t.d1_client(), t.mirror(), album.id, Some(2), None, None)
+        .await
+        .unwrap();
+    assert_eq!(ids(&first), vec![1, 2]);
+    assert_eq!(first.total, 3);
+
+    let second = run_saved_search(
+        t.d1_client(),
+        t.mirror(),
+        album.id,
+        Some(2),
+        None,
+        first.next_cursor,
+    )
+    .await
+    .unwrap();
+    assert_eq!(ids(&second), vec![4]);
+}
@@ -0,0 +1,163 @@
+//! 全文搜索：相关度排序、高亮、前缀与短语查询，索引随镜像更新
+mod common;
+
+use common::{smms_item, TestApp};
+use sm_flare_lib::commands::*;
+use sm_flare_lib::services::search::fts_query;
+
+async fn library() -> TestApp {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[
+        smms_item("h1", "sunset-beach.png"),
+        smms_item("h2", "beach-party.jpg"),
+        smms_item("h3", "mountain.png"),
+        smms_item("h4", "<b>bold</b>.png"),
+    ])
+    .await;
+    t.sql(
+        "UPDATE smms_pictures SET remark = 'went to the beach with friends' WHERE id = 3;
+         UPDATE smms_pictures SET remark = 'party & fun' WHERE id = 4;",
+    );
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    t
+}
+
+async fn ids(t: &TestApp, query: &str) -> Vec<i64> {
+    search_pictures(t.d1_client(), t.mirror(), query.into(), None, None)
+        .await
+        .unwrap()
+        .items
+        .iter()
+        .map(|hit| hit.picture.id)
+        .collect()
+}
+
+#[tokio::test]
+async fn results_are_ranked_and_highlighted() {
+    let t = library().await;
+
+    let page = search_pictures(t.d1_client(), t.mirror(), "beach".into(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(page.total, 3);
+    // 文件名命中排在只有备注命中的图片之前
+    assert_eq!(page.items.last().unwrap().picture.id, 3);
+    assert!(page.items.windows(2).all(|w| w[0].rank <= w[1].rank));
+    assert_eq!(
+        page.items[0]
+            .filename_highlight
+            .matches("<mark>beach</mark>")
+            .count(),
+        1
+    );
+    let remark_hit = page.items.last().unwrap();
+    assert_eq!(
+        remark_hit.remark_snippet.as_deref(),
+        Some("went to the <mark>beach</mark> with friends")
+    );
+    assert!(page.items[0].remark_snippet.is_none());
+
+    // 原文中的 HTML 被转义
+    let page = search_pictures(t.d1_client(), t.mirror(), "bold".into(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        page.items[0].filename_highlight,
+        "&lt;b&gt;<mark>bold</mark>&lt;/b&gt;.png"
+    );
+}
+
+#[tokio::test]
+async fn prefix_phrase_and_multiple_words() {
+    let t = library().await;
+
+    assert_eq!(ids(&t, "moun*").await, vec![3]);
+    // 引号内的空格按原文匹配
+    assert_eq!(ids(&t, "\"beach-party\"").await, vec![2]);
+    assert!(ids(&t, "\"beach party\"").await.is_empty());
+    assert_eq!(ids(&t, "party beach").await, vec![2]);
+    assert_eq!(ids(&t, "party").await.len(), 2);
+    // 标点和 FTS5 运算符按普通文本处理
+    assert_eq!(ids(&t, "sunset-beach (").await, vec![1]);
+    assert!(ids(&t, "beach NOT").await.is_empty());
+
+    let error = search_pictures(t.d1_client(), t.mirror(), " * \" ".into(), None, None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+}
+
+#[tokio::test]
+async fn cjk_substrings_and_short_words_match() {
+    let t = library().await;
+    t.seed_pictures(&[smms_item("h5", "上海外滩夜景.png")])
+        .await;
+    t.sql("UPDATE smms_pictures SET remark = '和朋友去看灯光秀' WHERE id = 5");
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+
+    let page = search_pictures(t.d1_client(), t.mirror(), "海外滩".into(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(page.total, 1);
+    assert_eq!(
+        page.items[0].filename_highlight,
+        "上<mark>海外滩</mark>夜景.png"
+    );
+
+    // 少于 3 个字符的词改用 LIKE 匹配，同样高亮
+    let page = search_pictures(t.d1_client(), t.mirror(), "灯光".into(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(page.total, 1);
+    assert_eq!(
+        page.items[0].remark_snippet.as_deref(),
+        Some("和朋友去看<mark>灯光</mark>秀")
+    );
+    assert_eq!(ids(&t, "外滩 PNG").await, vec![5]);
+    assert_eq!(ids(&t, "be").await, vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn index_follows_edits_and_deletes() {
+    let t = library().await;
+
+    update_picture_remark(
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        1,
+        Some("glacier trip".into()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(ids(&t, "glacier").await, vec![1]);
+
+    delete_picture(t.d1_client(), t.mirror(), t.smms_client(), 2)
+        .await
+        .unwrap();
+    assert_eq!(ids(&t, "party").await, vec![4]);
+
+    // 全量拉取替换镜像中的行后索引仍然一致
+    t.sql("UPDATE smms_pictures SET remark = 'snowfield', updated_at = '2030-01-01 00:00:00' WHERE id = 1");
+    sync_local_mirror(t.d1_client(), t.mirror(), Some(true))
+        .await
+        .unwrap();
+    assert!(ids(&t, "glacier").await.is_empty());
+    assert_eq!(ids(&t, "snowfield").await, vec![1]);
+}
+
+#[test]
+fn user_input_is_quoted() {
+    assert_eq!(fts_query("cat dog*").as_deref(), Some("\"cat\" \"dog\"*"));
+    assert_eq!(
+        fts_query("\"big \"cat").as_deref(),
+        Some("\"big \" \"cat\"")
+    );
+    assert_eq!(fts_query("OR NOT").as_deref(), Some("\"OR\" \"NOT\""));
+    assert_eq!(fts_query("  -- "), None);
+}
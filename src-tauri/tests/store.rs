@@ -0,0 +1,198 @@
+//! 存储后端：本地 SQLite 后端与后端切换
+mod common;
+
+use common::{smms_item, TestApp};
+use serde_json::json;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{D1Statement, PictureQueryParams, StorageKind};
+use sm_flare_lib::services::d1::D1Client;
+use sm_flare_lib::services::{config, crypto, store};
+
+/// 切换到本地后端的测试应用
+async fn local_app() -> TestApp {
+    let t = TestApp::new().await;
+    set_storage_backend(t.config_store(), t.d1_client(), StorageKind::Local)
+        .await
+        .unwrap();
+    t
+}
+
+#[tokio::test]
+async fn local_backend_works_without_cloudflare() {
+    let t = local_app().await;
+    let before = t.d1_requests().await;
+    reset_d1_usage(t.d1_client()).await.unwrap();
+
+    t.login().await;
+    t.mock_upload_history(vec![vec![
+        smms_item("h1", "a.png"),
+        smms_item("h2", "b.png"),
+    ]])
+    .await;
+    sync_smms_pictures(t.d1_client(), t.mirror(), t.smms_client(), Some(1))
+        .await
+        .unwrap();
+    toggle_picture_favorite(t.d1_client(), t.mirror(), t.smms_client(), 1, true)
+        .await
+        .unwrap();
+
+    let favorites = PictureQueryParams {
+        is_favorite: Some(true),
+        ..Default::default()
+    };
+    let pictures = query_smms_pictures(t.d1_client(), t.mirror(), favorites)
+        .await
+        .unwrap();
+    assert_eq!(pictures.len(), 1);
+    assert_eq!(
+        load_smms_user(t.d1_client(), None).await.unwrap().token,
+        common::TOKEN
+    );
+
+    // 没有任何请求发往 D1，D1 中也没有数据，本地语句也不计入 D1 用量
+    assert_eq!(t.d1_requests().await, before);
+    let usage = get_d1_usage(t.d1_client()).await.unwrap();
+    assert_eq!((usage.statements, usage.rows_read), (0, 0));
+    let remote: i64 = t.scalar("SELECT COUNT(*) FROM smms_pictures");
+    assert_eq!(remote, 0);
+    assert!(t
+        .config_dir
+        .path()
+        .join(store::LOCAL_DATABASE_FILE)
+        .exists());
+}
+
+#[tokio::test]
+async fn backend_choice_is_persisted() {
+    let t = local_app().await;
+    assert_eq!(
+        get_storage_backend(t.config_store()).await.unwrap(),
+        StorageKind::Local
+    );
+
+    // 模拟重启：按保存的设置启用后端
+    let restarted = D1Client::new("http://unused", None);
+    let settings = t.config_store().read_settings().unwrap();
+    store::activate(&restarted, &t.config_store(), settings.storage).unwrap();
+    assert_eq!(restarted.kind(), Some(StorageKind::Local));
+    assert!(restarted.config().is_err());
+
+    t.config_store()
+        .write_d1_config(&common::d1_config())
+        .unwrap();
+    set_storage_backend(t.config_store(), t.d1_client(), StorageKind::D1)
+        .await
+        .unwrap();
+    assert_eq!(t.d1_client().kind(), Some(StorageKind::D1));
+    assert_eq!(t.d1_client().source().unwrap(), "account/database");
+}
+
+#[tokio::test]
+async fn d1_config_is_kept_while_local_backend_is_active() {
+    let t = local_app().await;
+
+    let message = save_d1_config(t.config_store(), t.d1_client(), common::d1_config())
+        .await
+        .unwrap();
+    assert!(message.contains("切换到 D1"), "{message}");
+    assert_eq!(t.d1_client().kind(), Some(StorageKind::Local));
+    assert_eq!(
+        load_d1_config(t.config_store()).await.unwrap().database_id,
+        "database"
+    );
+
+    delete_d1_config(t.config_store(), t.d1_client())
+        .await
+        .unwrap();
+    let error = set_storage_backend(t.config_store(), t.d1_client(), StorageKind::D1)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "config_missing");
+}
+
+#[tokio::test]
+async fn local_batch_stops_at_first_failure() {
+    let t = local_app().await;
+
+    let output = execute_d1_query(t.d1_client(), "SELECT 1 AS one".into(), None)
+        .await
+        .unwrap();
+    assert_eq!(output.results, vec![json!({ "one": 1 })]);
+    assert_eq!(output.meta.served_by.as_deref(), Some("local"));
+
+    let insert = "INSERT INTO smms_user (username, encrypted_password) VALUES (?, 'x')";
+    let result = execute_d1_batch(
+        t.d1_client(),
+        vec![
+            D1Statement::new(insert).bind("a"),
+            D1Statement::new(insert).bind("a"),
+            D1Statement::new(insert).bind("b"),
+        ],
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.failed_index, Some(1));
+    assert_eq!(result.meta.rows_written, 1);
+
+    let users = execute_d1_query(t.d1_client(), "SELECT username FROM smms_user".into(), None)
+        .await
+        .unwrap();
+    assert_eq!(users.results, vec![json!({ "username": "a" })]);
+}
+
+#[tokio::test]
+async fn local_credentials_use_a_per_install_key() {
+    let t = local_app().await;
+    t.login().await;
+
+    let key_file = t.config_dir.path().join(config::LOCAL_KEY_FILE);
+    let key = std::fs::read_to_string(&key_file).unwrap();
+    assert_eq!(key.len(), 64);
+    let stored = execute_d1_query(
+        t.d1_client(),
+        "SELECT encrypted_password FROM smms_user".into(),
+        None,
+    )
+    .await
+    .unwrap();
+    let encrypted = stored.results[0]["encrypted_password"].as_str().unwrap();
+    assert!(crypto::decrypt_password(encrypted, "local", "metadata").is_err());
+    assert!(crypto::decrypt_password(encrypted, "local", &key).is_ok());
+
+    // 备份中不包含本机密钥
+    let path = t.config_dir.path().join("local.sql");
+    export_database_backup(t.d1_client(), path.to_string_lossy().into(), None)
+        .await
+        .unwrap();
+    let dump = std::fs::read_to_string(&path).unwrap();
+    assert!(!dump.contains(&key));
+    assert!(!dump.contains("database_id"));
+}
+
+#[tokio::test]
+async fn legacy_local_credentials_are_reencrypted_with_the_new_key() {
+    let t = local_app().await;
+    t.login().await;
+
+    // 模拟旧版本：凭证用固定材料加密，尚未生成本机密钥
+    let password = crypto::encrypt_password("legacy", "local", "metadata").unwrap();
+    let token = crypto::encrypt_password(common::TOKEN, "local", "metadata").unwrap();
+    execute_d1_batch(
+        t.d1_client(),
+        vec![D1Statement::new(
+            "UPDATE smms_user SET encrypted_password = ?, encrypted_api_token = ?",
+        )
+        .bind(password)
+        .bind(token)],
+    )
+    .await
+    .unwrap();
+    std::fs::remove_file(t.config_dir.path().join(config::LOCAL_KEY_FILE)).unwrap();
+
+    store::activate(&t.d1_client(), &t.config_store(), StorageKind::Local).unwrap();
+    let user = load_smms_user(t.d1_client(), None).await.unwrap();
+    assert_eq!(
+        (user.password.as_str(), user.token.as_str()),
+        ("legacy", common::TOKEN)
+    );
+}
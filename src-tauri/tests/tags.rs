@@ -0,0 +1,235 @@
+//! 标签：增删改、合并、批量打标签、使用次数，以及 AND / OR / NOT 标签筛选
+mod common;
+
+use common::TestApp;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{PictureQueryParams, TagUsage};
+
+/// 5 张图片，id 为 1..=5
+async fn library() -> TestApp {
+    let t = TestApp::new().await;
+    t.seed_generated_pictures(5, "https://u/").await;
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    t
+}
+
+async fn tag(t: &TestApp, ids: &[i64], names: &[&str]) -> Vec<i64> {
+    add_tags_to_pictures(
+        t.d1_client(),
+        t.mirror(),
+        ids.to_vec(),
+        names.iter().map(|n| n.to_string()).collect(),
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|tag| tag.id)
+    .collect()
+}
+
+async fn usage(t: &TestApp) -> Vec<(String, i64)> {
+    list_tags(t.d1_client(), t.mirror())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|TagUsage { name, count, .. }| (name, count))
+        .collect()
+}
+
+async fn ids(t: &TestApp, params: PictureQueryParams) -> Vec<i64> {
+    let params = PictureQueryParams {
+        order_by: Some("created_at_asc".into()),
+        ..params
+    };
+    query_smms_pictures(t.d1_client(), t.mirror(), params)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| p.id)
+        .collect()
+}
+
+#[tokio::test]
+async fn bulk_tagging_and_usage_counts() {
+    let t = library().await;
+    let tags = tag(&t, &[1, 2, 3], &["Acme", "project-x", "acme "]).await;
+    assert_eq!(tags.len(), 2, "名称不区分大小写，重复的只创建一次");
+    tag(&t, &[3, 4, 99], &["ACME"]).await;
+
+    assert_eq!(
+        usage(&t).await,
+        vec![("Acme".to_string(), 4), ("project-x".to_string(), 3)]
+    );
+    assert_eq!(
+        t.scalar::<i64>("SELECT COUNT(*) FROM picture_tags WHERE picture_id = 99"),
+        0,
+        "不存在的图片不会被打上标签"
+    );
+
+    let picture_tags = get_picture_tags(t.d1_client(), t.mirror(), vec![3, 5])
+        .await
+        .unwrap();
+    let names: Vec<Vec<&str>> = picture_tags
+        .iter()
+        .map(|p| p.tags.iter().map(|tag| tag.name.as_str()).collect())
+        .collect();
+    assert_eq!(names, vec![vec!["Acme", "project-x"], vec![]]);
+
+    // 已删除的图片不计入使用次数
+    t.sql(
+        "UPDATE smms_pictures SET is_deleted = 1, updated_at = '2030-01-01 00:00:00' WHERE id = 4",
+    );
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    assert_eq!(usage(&t).await[0], ("Acme".to_string(), 3));
+
+    remove_tags_from_pictures(t.d1_client(), t.mirror(), vec![1, 2], vec![tags[0]])
+        .await
+        .unwrap();
+    assert_eq!(usage(&t).await[0], ("Acme".to_string(), 1));
+    assert_eq!(
+        t.scalar::<i64>("SELECT COUNT(*) FROM picture_tags WHERE tag_id = (SELECT id FROM tags WHERE name = 'Acme')"),
+        2
+    );
+
+    let error = add_tags_to_pictures(t.d1_client(), t.mirror(), vec![1], vec!["  ".into()])
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+}
+
+#[tokio::test]
+async fn and_or_not_filters() {
+    let t = library().await;
+    let acme = tag(&t, &[1, 2, 3], &["acme"]).await[0];
+    let globex = tag(&t, &[2, 3, 4], &["globex"]).await[0];
+    let draft = tag(&t, &[3], &["draft"]).await[0];
+
+    let all = PictureQueryParams {
+        tags_all: Some(vec![acme, globex]),
+        ..Default::default()
+    };
+    assert_eq!(ids(&t, all).await, vec![2, 3]);
+
+    let any = PictureQueryParams {
+        tags_any: Some(vec![acme, globex]),
+        ..Default::default()
+    };
+    assert_eq!(ids(&t, any).await, vec![1, 2, 3, 4]);
+
+    let none = PictureQueryParams {
+        tags_none: Some(vec![acme]),
+        ..Default::default()
+    };
+    assert_eq!(ids(&t, none).await, vec![4, 5]);
+
+    // 三种条件同时使用，重复的 id 不影响结果
+    let combined = PictureQueryParams {
+        tags_all: Some(vec![globex, globex]),
+        tags_any: Some(vec![acme, draft]),
+        tags_none: Some(vec![draft]),
+        ..Default::default()
+    };
+    assert_eq!(ids(&t, combined.clone()).await, vec![2]);
+
+    let count = get_pictures_count(t.d1_client(), t.mirror(), combined)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn rename_merge_and_delete() {
+    let t = library().await;
+    let client = tag(&t, &[1, 2], &["client"]).await[0];
+    let customer = tag(&t, &[2, 3], &["Customer"]).await[0];
+    let old = tag(&t, &[4], &["old"]).await[0];
+
+    let error = rename_tag(t.d1_client(), t.mirror(), client, "customer".into())
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+    let renamed = rename_tag(t.d1_client(), t.mirror(), customer, "customer".into())
+        .await
+        .unwrap();
+    assert_eq!(renamed.name, "customer");
+
+    merge_tags(t.d1_client(), t.mirror(), vec![client, customer], customer)
+        .await
+        .unwrap();
+    assert_eq!(
+        usage(&t).await,
+        vec![("customer".to_string(), 3), ("old".to_string(), 1)]
+    );
+    let merged = PictureQueryParams {
+        tags_all: Some(vec![customer]),
+        ..Default::default()
+    };
+    assert_eq!(ids(&t, merged).await, vec![1, 2, 3]);
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM picture_tags"), 4);
+
+    delete_tag(t.d1_client(), t.mirror(), old).await.unwrap();
+    assert_eq!(usage(&t).await, vec![("customer".to_string(), 3)]);
+    let error = delete_tag(t.d1_client(), t.mirror(), old)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "not_found");
+    let error = merge_tags(t.d1_client(), t.mirror(), vec![old], customer)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), "not_found");
+}
+
+#[tokio::test]
+async fn tag_changes_from_other_devices_reach_the_mirror() {
+    let t = library().await;
+    let acme = tag(&t, &[1], &["acme"]).await[0];
+
+    // 其他设备修改了标签：本机镜像的修订号落后，下一次修改时改为重新拉取
+    t.sql(&format!(
+        "INSERT INTO picture_tags (picture_id, tag_id) VALUES (5, {acme});
+         UPDATE tag_revision SET revision = revision + 1;"
+    ));
+    tag(&t, &[2], &["acme"]).await;
+    let tagged = PictureQueryParams {
+        tags_any: Some(vec![acme]),
+        ..Default::default()
+    };
+    assert_eq!(ids(&t, tagged.clone()).await, vec![1, 2, 5]);
+
+    // 没有本机修改时由定期拉取同步
+    t.sql(
+        "DELETE FROM picture_tags WHERE picture_id = 1;
+         INSERT INTO tags (name) VALUES ('remote');
+         UPDATE tag_revision SET revision = revision + 1;",
+    );
+    assert_eq!(ids(&t, tagged.clone()).await, vec![1, 2, 5]);
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    assert_eq!(ids(&t, tagged).await, vec![2, 5]);
+    assert_eq!(usage(&t).await.len(), 2);
+}
+
+#[tokio::test]
+async fn tags_on_pictures_not_yet_in_the_mirror_are_kept() {
+    let t = library().await;
+    // 其他设备刚上传的图片，本机镜像还没有拉取
+    t.sql(
+        "INSERT INTO smms_pictures (file_hash, filename, store_name, file_type, width, height, size, path, url, delete_url, page_url, created_at)
+         VALUES ('h6', 'shot6.png', 's.png', 'png', 10, 10, 1, '/p', 'https://u/6', 'https://d', 'https://p', '2024-01-06 00:00:00');",
+    );
+    let acme = tag(&t, &[1, 6], &["acme"]).await[0];
+
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    let tagged = PictureQueryParams {
+        tags_any: Some(vec![acme]),
+        ..Default::default()
+    };
+    assert_eq!(ids(&t, tagged).await, vec![1, 6]);
+}
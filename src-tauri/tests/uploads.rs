@@ -0,0 +1,486 @@
+//! 并发上传：并发数限制、结果顺序、分批写入与内容查重
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{smms_item, TestApp};
+use serde_json::json;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::{D1Statement, UploadResult};
+use sm_flare_lib::services::mirror::LocalMirror;
+use sm_flare_lib::services::progress::Progress;
+use sm_flare_lib::services::upload::{self, content_sha256};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, Request, Respond, ResponseTemplate};
+
+/// 按文件名中的数字延迟响应（`3.png` 延迟 3 个单位），并记录同时处理的最大请求数
+struct SlowUpload {
+    unit: Duration,
+    in_flight: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+impl Respond for SlowUpload {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body = String::from_utf8_lossy(&request.body);
+        let filename = body
+            .split("filename=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or("0.png")
+            .to_string();
+        let n: u32 = filename.trim_end_matches(".png").parse().unwrap_or(0);
+
+        // 请求到达时计数加一，响应发出前半个单位减一：客户端收到响应后才开始下一个上传，
+        // 负载高时计时任务晚于响应也不会多算
+        let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(current, Ordering::SeqCst);
+        let in_flight = self.in_flight.clone();
+        let delay = self.unit * n;
+        let release = delay.saturating_sub(self.unit / 2);
+        tokio::spawn(async move {
+            tokio::time::sleep(release).await;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        ResponseTemplate::new(200)
+            .set_delay(delay)
+            .set_body_json(json!({
+                "success": true,
+                "code": "success",
+                "message": "Upload success.",
+                "data": smms_item(&format!("hash-{filename}"), &filename),
+                "RequestId": "req"
+            }))
+    }
+}
+
+fn write_files(dir: &std::path::Path, names: impl IntoIterator<Item = String>) -> Vec<String> {
+    names
+        .into_iter()
+        .map(|name| {
+            // 内容各不相同，不会被当作同一批中的重复文件
+            let file = dir.join(&name);
+            std::fs::write(&file, name.as_bytes()).unwrap();
+            file.to_string_lossy().into_owned()
+        })
+        .collect()
+}
+
+async fn upload_requests(t: &TestApp) -> usize {
+    t.server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/api/v2/upload")
+        .count()
+}
+
+async fn upload_one(t: &TestApp, file: &std::path::Path, dedup: Option<bool>) -> UploadResult {
+    upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        vec![file.to_string_lossy().into_owned()],
+        None,
+        None,
+        dedup,
+        "upload".into(),
+    )
+    .await
+    .unwrap()
+    .remove(0)
+}
+
+async fn insert_requests(t: &TestApp) -> usize {
+    t.server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| {
+            r.url.path().ends_with("/query")
+                && String::from_utf8_lossy(&r.body).contains("INSERT INTO smms_pictures")
+        })
+        .count()
+}
+
+#[tokio::test]
+async fn uploads_run_in_parallel_and_keep_input_order() {
+    let t = TestApp::new().await;
+    t.login().await;
+    let peak = Arc::new(AtomicUsize::new(0));
+    Mock::given(method("POST"))
+        .and(path("/api/v2/upload"))
+        .respond_with(SlowUpload {
+            unit: Duration::from_millis(100),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            peak: peak.clone(),
+        })
+        .with_priority(1)
+        .mount(&t.server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    // 先上传的文件响应更慢，结果仍按输入顺序返回
+    let mut paths = write_files(dir.path(), (1..=6).rev().map(|n| format!("{n}.png")));
+    paths.insert(
+        2,
+        dir.path()
+            .join("missing.png")
+            .to_string_lossy()
+            .into_owned(),
+    );
+
+    let started = std::time::Instant::now();
+    let results = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        paths,
+        None,
+        Some(3),
+        None,
+        "upload".into(),
+    )
+    .await
+    .unwrap();
+    let elapsed = started.elapsed();
+
+    let names: Vec<&str> = results.iter().map(|r| r.filename.as_str()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "6.png",
+            "5.png",
+            "missing.png",
+            "4.png",
+            "3.png",
+            "2.png",
+            "1.png"
+        ]
+    );
+    assert_eq!(results.iter().filter(|r| r.success).count(), 6);
+    assert!(!results[2].success);
+    assert_eq!(peak.load(Ordering::SeqCst), 3, "同时上传的数量不超过 3");
+    // 逐个上传需要 2.1 秒
+    assert!(elapsed < Duration::from_millis(1600), "{elapsed:?}");
+
+    assert_eq!(insert_requests(&t).await, 1, "一批记录只需一个请求");
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures"), 6);
+}
+
+#[tokio::test]
+async fn records_are_written_while_slow_uploads_continue() {
+    let t = TestApp::new().await;
+    t.login().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v2/upload"))
+        .respond_with(SlowUpload {
+            unit: Duration::from_millis(500),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            peak: Arc::new(AtomicUsize::new(0)),
+        })
+        .with_priority(1)
+        .mount(&t.server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let paths = write_files(dir.path(), ["0.png", "4.png"].map(String::from));
+
+    let upload = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        paths,
+        None,
+        Some(2),
+        None,
+        "upload".into(),
+    );
+    // `4.png` 要 2 秒才上传完，`0.png` 的记录不等它凑批
+    let check = async {
+        tokio::time::sleep(Duration::from_millis(1200)).await;
+        t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures")
+    };
+    let (results, written) = tokio::join!(upload, check);
+
+    assert_eq!(written, 1);
+    assert!(results.unwrap().iter().all(|r| r.success));
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures"), 2);
+}
+
+#[tokio::test]
+async fn inserts_are_grouped_into_batches() {
+    let t = TestApp::new().await;
+    t.login().await;
+    let dir = tempfile::tempdir().unwrap();
+    let paths = write_files(dir.path(), (0..45).map(|n| format!("shot{n}.png")));
+
+    let results = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        paths,
+        Some("batch".into()),
+        Some(8),
+        None,
+        "upload".into(),
+    )
+    .await
+    .unwrap();
+
+    assert!(results.iter().all(|r| r.success));
+    assert_eq!(results[44].filename, "shot44.png");
+    assert_eq!(insert_requests(&t).await, 3);
+    assert_eq!(
+        t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures WHERE remark = 'batch'"),
+        45
+    );
+}
+
+#[tokio::test]
+async fn failed_insert_marks_the_rest_of_the_batch() {
+    let t = TestApp::new().await;
+    t.login().await;
+    // 第二条记录触发约束失败，批量在此停止
+    t.sql(
+        "CREATE TRIGGER reject_second BEFORE INSERT ON smms_pictures
+         WHEN NEW.filename = 'b.png'
+         BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let paths = write_files(dir.path(), ["a.png", "b.png", "c.png"].map(String::from));
+
+    let results = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        paths,
+        None,
+        Some(1),
+        None,
+        "upload".into(),
+    )
+    .await
+    .unwrap();
+
+    assert!(results[0].success);
+    assert!(!results[1].success);
+    assert!(
+        results[1].message.contains("rejected"),
+        "{}",
+        results[1].message
+    );
+    assert!(!results[2].success);
+    assert!(results[2].url.is_some(), "文件已上传，返回地址");
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures"), 1);
+
+    let error = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        Vec::new(),
+        None,
+        Some(0),
+        None,
+        "upload".into(),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.code(), "invalid_input");
+}
+
+#[tokio::test]
+async fn identical_content_returns_the_existing_picture() {
+    let t = TestApp::new().await;
+    t.login().await;
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("a.png");
+    let copy = dir.path().join("copy of a.png");
+    std::fs::write(&first, b"same-bytes").unwrap();
+    std::fs::write(&copy, b"same-bytes").unwrap();
+
+    let uploaded = upload_one(&t, &first, None).await;
+    assert!(uploaded.success && !uploaded.duplicate);
+    let stored: String = t.scalar("SELECT content_sha256 FROM smms_pictures");
+    assert_eq!(stored, content_sha256(b"same-bytes"));
+
+    let duplicate = upload_one(&t, &copy, None).await;
+    assert!(duplicate.success && duplicate.duplicate);
+    assert_eq!(duplicate.filename, "copy of a.png");
+    assert_eq!(duplicate.url, uploaded.url);
+    assert!(duplicate.message.contains("a.png"), "{}", duplicate.message);
+    assert_eq!(upload_requests(&t).await, 1);
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures"), 1);
+
+    // 可以按次跳过查重
+    let forced = upload_one(&t, &copy, Some(false)).await;
+    assert!(forced.success && !forced.duplicate);
+    assert_ne!(forced.url, uploaded.url);
+    assert_eq!(upload_requests(&t).await, 2);
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures"), 2);
+}
+
+#[tokio::test]
+async fn duplicate_keeps_the_new_remark() {
+    let t = TestApp::new().await;
+    t.login().await;
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("a.png");
+    let copy = dir.path().join("copy of a.png");
+    std::fs::write(&first, b"same-bytes").unwrap();
+    std::fs::write(&copy, b"same-bytes").unwrap();
+    upload_one(&t, &first, None).await;
+
+    let upload = |remark: Option<&str>| {
+        upload_images(
+            t.app.handle().clone(),
+            t.d1_client(),
+            t.mirror(),
+            t.smms_client(),
+            t.jobs(),
+            vec![copy.to_string_lossy().into_owned()],
+            remark.map(str::to_string),
+            None,
+            None,
+            "upload".into(),
+        )
+    };
+    let duplicate = upload(Some("holiday")).await.unwrap().remove(0);
+    assert!(duplicate.success && duplicate.duplicate);
+    assert_eq!(duplicate.remark.as_deref(), Some("holiday"));
+    let stored: String = t.scalar("SELECT remark FROM smms_pictures");
+    assert_eq!(stored, "holiday");
+
+    // 未指定备注时保留已有的备注
+    let duplicate = upload(None).await.unwrap().remove(0);
+    assert_eq!(duplicate.remark.as_deref(), Some("holiday"));
+    let stored: String = t.scalar("SELECT remark FROM smms_pictures");
+    assert_eq!(stored, "holiday");
+    assert_eq!(upload_requests(&t).await, 1);
+}
+
+#[tokio::test]
+async fn identical_files_in_one_call_are_uploaded_once() {
+    let t = TestApp::new().await;
+    t.login().await;
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<String> = ["a.png", "b.png", "a again.png"]
+        .iter()
+        .map(|name| {
+            let file = dir.path().join(name);
+            let content = if name.starts_with('b') {
+                "b-bytes"
+            } else {
+                "a-bytes"
+            };
+            std::fs::write(&file, content).unwrap();
+            file.to_string_lossy().into_owned()
+        })
+        .collect();
+
+    let results = upload_images(
+        t.app.handle().clone(),
+        t.d1_client(),
+        t.mirror(),
+        t.smms_client(),
+        t.jobs(),
+        paths,
+        Some("batch".into()),
+        None,
+        None,
+        "upload".into(),
+    )
+    .await
+    .unwrap();
+
+    assert!(results.iter().all(|r| r.success));
+    // 两个相同的文件中先读完的那个上传，另一个沿用它的结果
+    assert!(!results[1].duplicate);
+    assert!(results[0].duplicate != results[2].duplicate);
+    assert_eq!(results[2].filename, "a again.png");
+    assert_eq!(results[2].url, results[0].url);
+    assert!(results.iter().all(|r| r.remark.as_deref() == Some("batch")));
+    assert_eq!(upload_requests(&t).await, 2);
+    assert_eq!(t.scalar::<i64>("SELECT COUNT(*) FROM smms_pictures"), 2);
+}
+
+#[tokio::test]
+async fn failed_dedup_lookup_is_reported() {
+    let t = TestApp::new().await;
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.png");
+    std::fs::write(&file, b"a-bytes").unwrap();
+    let broken = LocalMirror::in_memory().unwrap();
+    broken
+        .execute(&[D1Statement::new("DROP TABLE smms_pictures")])
+        .unwrap();
+
+    let results = upload::upload_files(
+        &t.d1_client(),
+        &t.smms_client(),
+        common::TOKEN,
+        &[file.to_string_lossy().into_owned()],
+        None,
+        1,
+        Some(&broken),
+        &Progress::silent(1),
+    )
+    .await;
+
+    assert!(!results[0].success);
+    assert!(
+        results[0].message.contains("查找相同内容的图片失败"),
+        "{}",
+        results[0].message
+    );
+    assert_eq!(upload_requests(&t).await, 0);
+}
+
+#[tokio::test]
+async fn dedup_sees_other_devices_and_ignores_deleted_pictures() {
+    let t = TestApp::new().await;
+    t.login().await;
+    t.seed_pictures(&[
+        smms_item("remote", "remote.png"),
+        smms_item("gone", "gone.png"),
+    ])
+    .await;
+    sync_local_mirror(t.d1_client(), t.mirror(), None)
+        .await
+        .unwrap();
+    // 镜像拉取之后其他设备才写入内容 hash
+    t.sql(&format!(
+        "UPDATE smms_pictures SET content_sha256 = '{}', updated_at = datetime('now', '+1 minute') WHERE file_hash = 'remote';
+         UPDATE smms_pictures SET content_sha256 = '{}', is_deleted = 1, updated_at = datetime('now', '+1 minute') WHERE file_hash = 'gone';",
+        content_sha256(b"remote-bytes"),
+        content_sha256(b"gone-bytes")
+    ));
+    let dir = tempfile::tempdir().unwrap();
+    let remote = dir.path().join("remote.png");
+    let gone = dir.path().join("gone.png");
+    std::fs::write(&remote, b"remote-bytes").unwrap();
+    std::fs::write(&gone, b"gone-bytes").unwrap();
+
+    let result = upload_one(&t, &remote, None).await;
+    assert!(result.duplicate);
+    assert_eq!(result.url.as_deref(), Some("https://i.loli.net/remote.png"));
+
+    let result = upload_one(&t, &gone, None).await;
+    assert!(result.success && !result.duplicate, "已删除的图片不算重复");
+    assert_eq!(upload_requests(&t).await, 1);
+}
@@ -0,0 +1,69 @@
+//! D1 执行开销与会话用量统计
+mod common;
+
+use common::TestApp;
+use sm_flare_lib::commands::*;
+use sm_flare_lib::models::D1Statement;
+
+#[tokio::test]
+async fn query_returns_meta_alongside_rows() {
+    let t = TestApp::new().await;
+    t.sql("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)");
+    t.sql("INSERT INTO notes (body) VALUES ('a'), ('b'), ('c')");
+
+    let output = execute_d1_query(t.d1_client(), "SELECT * FROM notes".into(), None)
+        .await
+        .unwrap();
+    assert_eq!(output.results.len(), 3);
+    assert_eq!(output.meta.rows_read, 3);
+    assert_eq!(output.meta.rows_written, 0);
+    assert_eq!(output.meta.served_by.as_deref(), Some("fake-d1"));
+}
+
+#[tokio::test]
+async fn batch_meta_sums_statements() {
+    let t = TestApp::new().await;
+    t.sql("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)");
+
+    let statements = (1..=4)
+        .map(|id| D1Statement::new("INSERT INTO notes (id, body) VALUES (?, 'x')").bind(id))
+        .collect();
+    let result = execute_d1_batch(t.d1_client(), statements).await.unwrap();
+    assert_eq!(result.meta.rows_written, 4);
+    assert!(result.results.iter().all(|r| r.meta.rows_written == 1));
+}
+
+#[tokio::test]
+async fn usage_groups_statements_by_template_and_resets() {
+    let t = TestApp::new().await;
+    t.sql("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)");
+    t.sql("INSERT INTO notes (body) VALUES ('a'), ('b'), ('c')");
+    reset_d1_usage(t.d1_client()).await.unwrap();
+
+    for ids in [vec![1, 2], vec![1, 2, 3]] {
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let sql = format!("SELECT  *  FROM notes WHERE id IN ({})", placeholders);
+        let statement = ids
+            .into_iter()
+            .fold(D1Statement::new(sql), |stmt, id| stmt.bind(id));
+        t.d1_client().query(statement).await.unwrap();
+    }
+    t.d1_client()
+        .query(D1Statement::new("UPDATE notes SET body = 'z' WHERE id = 1"))
+        .await
+        .unwrap();
+
+    let usage = get_d1_usage(t.d1_client()).await.unwrap();
+    assert_eq!(usage.statements, 3);
+    assert_eq!(usage.rows_read, 5);
+    assert_eq!(usage.rows_written, 1);
+    assert_eq!(usage.operations.len(), 2);
+    let select = &usage.operations[0];
+    assert_eq!(select.sql, "SELECT * FROM notes WHERE id IN (?)");
+    assert_eq!((select.count, select.rows_read), (2, 5));
+
+    reset_d1_usage(t.d1_client()).await.unwrap();
+    let usage = get_d1_usage(t.d1_client()).await.unwrap();
+    assert_eq!(usage.statements, 0);
+    assert!(usage.operations.is_empty());
+}
@@ -0,0 +1,37 @@
+// 后端命令返回的结构化错误（对应 src-tauri/src/error.rs）
+export type AppErrorCategory =
+  | 'config'
+  | 'database'
+  | 'smms'
+  | 'auth'
+  | 'network'
+  | 'io'
+  | 'crypto'
+  | 'validation'
+  | 'cancelled'
+
+export interface AppError {
+  code: string
+  category: AppErrorCategory
+  retryable: boolean
+  message: string
+  details: Record<string, unknown> | null
+}
+
+export const isAppError = (error: unknown): error is AppError =>
+  typeof error === 'object' &&
+  error !== null &&
+  typeof (error as AppError).code === 'string' &&
+  typeof (error as AppError).message === 'string'
+
+// 取出用于展示的错误信息
+export const errorMessage = (error: unknown): string => {
+  if (isAppError(error)) {
+    return error.retryable ? `${error.message}（请稍后重试）` : error.message
+  }
+  return error instanceof Error ? error.message : String(error)
+}
+
+// 判断错误类别
+export const isErrorCategory = (error: unknown, category: AppErrorCategory): boolean =>
+  isAppError(error) && error.category === category
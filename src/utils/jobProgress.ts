@@ -0,0 +1,56 @@
+// 上传/下载任务的逐文件进度（对应 src-tauri/src/models/job.rs）
+import {invoke} from '@tauri-apps/api/core'
+import {listen} from '@tauri-apps/api/event'
+
+export type JobStage =
+  | 'reading'
+  | 'uploading'
+  | 'saving'
+  | 'downloading'
+  | 'zipping'
+  | 'done'
+  | 'failed'
+  | 'cancelled'
+
+export interface JobProgress {
+  job_id: string
+  file_index: number
+  file_count: number
+  filename: string
+  stage: JobStage
+  bytes: number
+  total: number | null
+  message: string | null
+}
+
+export const stageLabels: Record<JobStage, string> = {
+  reading: '读取中',
+  uploading: '上传中',
+  saving: '写入数据库',
+  downloading: '下载中',
+  zipping: '打包中',
+  done: '完成',
+  failed: '失败',
+  cancelled: '已取消'
+}
+
+export const newJobId = () => crypto.randomUUID()
+
+// 生成任务 id 并监听该任务的进度事件，返回取消监听的函数
+export const trackJob = async (onProgress: (event: JobProgress) => void) => {
+  const jobId = newJobId()
+  const unlisten = await listen<JobProgress>('job-progress', ({payload}) => {
+    if (payload.job_id === jobId) onProgress(payload)
+  })
+  return {jobId, unlisten}
+}
+
+// 已结束（成功、失败或取消）的文件数
+export const finishedCount = (files: Map<number, JobProgress>) =>
+  Array.from(files.values()).filter(f => ['done', 'failed', 'cancelled'].includes(f.stage)).length
+
+// 请求取消任务，任务会在下一个安全点停止并返回已完成的部分
+export const cancelJob = async (jobId: string | null) => {
+  if (!jobId) return
+  await invoke<string>('cancel_job', {jobId})
+}